@@ -4,8 +4,6 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use copy_dir;
-
 fn main() {
     let resource_path = get_output_path().join("resources");
 
@@ -20,10 +18,9 @@ fn get_output_path() -> PathBuf {
     //<root or manifest path>/target/<profile>/
     let manifest_dir_string = env::var("CARGO_MANIFEST_DIR").unwrap();
     let build_type = env::var("PROFILE").unwrap();
-    let path = Path::new(&manifest_dir_string)
+    Path::new(&manifest_dir_string)
         .join("target")
-        .join(build_type);
-    return PathBuf::from(path);
+        .join(build_type)
 }
 
 /*
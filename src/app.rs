@@ -1,47 +1,120 @@
-use std::error::Error;
+use std::{cell::RefCell, error::Error, path::PathBuf, rc::Rc};
 
 use egui::{ColorImage, Context, Id, modal};
-use winreg::enums::HKEY_CLASSES_ROOT;
 
-use crate::edit_context_lib::addtocontext::{
-    add_to_context_window, get_current_context_window, remove_from_context_window,
+use crate::edit_context_lib::addtocontext::get_current_context_window;
+use crate::edit_context_lib::association::resolve_associations;
+use crate::edit_context_lib::backup::{BackupOptions, list_backups};
+use crate::edit_context_lib::catalogue::{KnownLocation, find_location, location_of};
+use crate::edit_context_lib::diff::{diff_registries, snapshot};
+use crate::edit_context_lib::export::{ExportTarget, export_verbs};
+use crate::edit_context_lib::history::{History, HistoryEntry};
+use crate::edit_context_lib::import::{
+    ImportVerb, apply_import, import_keys, plan_import, suggest_name,
+};
+use crate::edit_context_lib::order::{explorer_key, plan_order};
+use crate::edit_context_lib::origin::{move_keys, move_verb, tag_origins};
+use crate::edit_context_lib::perceived::read_perceived_types;
+use crate::edit_context_lib::restore::{restore_all, restore_keys, restore_verbs};
+use crate::edit_context_lib::scope::Scope;
+use crate::edit_context_lib::shellex::{
+    ShellExtension, block_hive, read_extensions, set_enabled, toggle_keys,
+};
+use crate::edit_context_lib::simulate::simulate;
+use crate::edit_context_lib::staging::{PendingChange, apply_changes, preview_changes};
+use crate::edit_context_lib::submenu::{group_into_submenu, nested_path};
+use crate::edit_context_lib::types::{
+    Key, KeyProps, Keys, NormalResult, StdCommand, key_path_string,
+};
+use crate::parsing::{
+    check_key_name, command_with_icon, folderize_title, sanitize_command, unique_key_name,
+};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::reader::{RegReader, RegVerb};
+use crate::resources::config::{AppConfig, PINNED_KEY};
+use crate::resources::resources::save_config_option;
+use crate::ui::appmodal::{
+    AppModal, BackupBrowser, ImportDialog, OpenBackup, Simulator, show_backup_modal,
+    show_create_modal, show_delete_modal, show_diff_modal, show_edit_modal, show_export_modal,
+    show_group_modal, show_import_modal, show_locations_modal, show_review_modal,
+    show_simulate_modal, show_sub_command_modal,
 };
-use crate::edit_context_lib::types::{Key, Keys, StdCommand};
-use crate::parsing::{command_with_icon, folderize_title, sanitize_command};
-use crate::resources::config::AppConfig;
-use crate::ui::appmodal::{AppModal, show_create_modal, show_delete_modal, show_edit_modal};
 use crate::ui::appstyle::AppStyle;
-use crate::ui::components::button_standard;
-use crate::ui::menu::{render_context_menu, render_heading};
+use crate::ui::components::{add_dialog_button, button_standard};
+use crate::ui::menu::{
+    render_context_menu, render_extension_tabs, render_extensions, render_heading, render_history,
+    render_pending, render_scope,
+};
 use crate::ui::message::Message;
 use crate::ui::state::AppState;
 
 pub struct App<'a> {
     pub heading: &'a str,
+    // Where the config keys are for the current scope
     keys: Keys,
+    config_keys: Keys,
+    // Known locations among the config keys, saved to the config when changed
+    pinned: Vec<String>,
+    extension_keys: Keys,
+    config_extension_keys: Keys,
+    scope: Scope,
+    backup: BackupOptions,
+    history: History,
+    registry: Box<dyn RegistryBackend>,
     state: AppState,
     style: AppStyle,
 }
 
-fn load_item(key: &Key) -> Result<Vec<(StdCommand, ColorImage)>, Box<dyn Error>> {
-    match get_current_context_window(key.clone_path(), HKEY_CLASSES_ROOT) {
-        Ok(items) => Ok(items
-            .into_iter()
-            .map(|item| sanitize_command(item))
-            //.into_iter()
-            //.parallel_map(|item| command_with_icon(item))
-            .map(|item| command_with_icon(item))
-            //.into_iter()
-            .collect::<Vec<(StdCommand, ColorImage)>>()),
+fn load_item(
+    key: &Key,
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<(StdCommand, ColorImage)>, Box<dyn Error>> {
+    match get_current_context_window(key.clone_path(), reg, key.hive) {
+        Ok(mut items) => Ok({
+            items.sort_by_key(explorer_key);
+            tag_origins(items, reg)
+        }
+        .into_iter()
+        .map(sanitize_command)
+        //.into_iter()
+        //.parallel_map(|item| command_with_icon(item))
+        .map(command_with_icon)
+        //.into_iter()
+        .collect::<Vec<(StdCommand, ColorImage)>>()),
         Err(err) => Err(format!("Failed to get current context window of {}", err).into()),
     }
 }
 
+// Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes
+fn history_shortcut(ctx: &Context) -> Message {
+    ctx.input(|input| {
+        let command = input.modifiers.command;
+
+        if command && input.key_pressed(egui::Key::Y)
+            || command && input.modifiers.shift && input.key_pressed(egui::Key::Z)
+        {
+            Message::Redo
+        } else if command && input.key_pressed(egui::Key::Z) {
+            Message::Undo
+        } else {
+            Message::None
+        }
+    })
+}
+
 impl<'a> App<'a> {
-    pub fn new(config: AppConfig) -> Self {
+    pub fn new(config: AppConfig, registry: Box<dyn RegistryBackend>) -> Self {
         Self {
             heading: config.title,
-            keys: config.keys,
+            keys: config.scoped_keys(),
+            extension_keys: config.scoped_extension_keys(),
+            config_keys: config.keys,
+            pinned: config.pinned,
+            config_extension_keys: config.extension_keys,
+            scope: config.scope,
+            backup: config.backup,
+            history: History::default(),
+            registry,
             state: AppState::default(),
             style: AppStyle {
                 icon_size: config.icon_size,
@@ -53,28 +126,58 @@ impl<'a> App<'a> {
         match self
             .keys
             .iter()
-            .map(load_item)
+            .map(|key| load_item(key, self.registry.as_ref()))
             .collect::<Result<Box<[Vec<(StdCommand, ColorImage)>]>, Box<dyn Error>>>()
         {
-            Ok(new_items) => self.state.items = new_items,
+            Ok(new_items) => {
+                self.state.items = new_items;
+                self.state.selected.clear();
+                self.state.reordered = false;
+            }
             Err(err) => self.alert(err.to_string().as_str()),
         }
+
+        if let Some((index, _)) = self.state.extensions {
+            self.open_extensions(index);
+        }
+
+        // The open association of a file type isn't one of the configured keys
+        if let Some((key, index)) = self.state.path.clone()
+            && !Rc::ptr_eq(&key.path, &self.keys[index].path)
+        {
+            self.reload_key(key, index);
+        }
     }
 
     fn update_modal(&mut self, modal: AppModal) {
         self.state.modal = modal;
     }
 
+    // The known location of the open tab. Keys an extension's verbs come from are files.
+    fn open_location(&self) -> Option<&'static KnownLocation> {
+        let (key, _) = self.state.path.as_ref()?;
+
+        location_of(key.hive, &key_path_string(&key.path)).or_else(|| {
+            (!self.state.associations.is_empty())
+                .then(|| find_location("File"))
+                .flatten()
+        })
+    }
+
     fn render_modal(&mut self, ctx: &Context) -> Message {
+        let location = self.open_location();
+
         match self.state.modal.clone() {
             AppModal::Create(command) => {
+                let taken = |name: &str| self.key_taken(name);
+
                 modal::Modal::new(Id::new("Add-Window"))
-                    .show(ctx, |ui| show_create_modal(ui, command))
+                    .show(ctx, |ui| show_create_modal(ui, command, &taken, location))
                     .inner
             }
             AppModal::Edit(command) => {
                 modal::Modal::new(Id::new("Edit-Window"))
-                    .show(ctx, |ui| show_edit_modal(ui, command))
+                    .show(ctx, |ui| show_edit_modal(ui, command, location))
                     .inner
             }
             AppModal::Delete(command) => {
@@ -82,6 +185,57 @@ impl<'a> App<'a> {
                     .show(ctx, |ui| show_delete_modal(ui, command))
                     .inner
             }
+            AppModal::Review(preview) => {
+                modal::Modal::new(Id::new("Review-Window"))
+                    .show(ctx, |ui| show_review_modal(ui, &preview))
+                    .inner
+            }
+            AppModal::Backups(browser) => {
+                modal::Modal::new(Id::new("Backup-Window"))
+                    .show(ctx, |ui| show_backup_modal(ui, browser))
+                    .inner
+            }
+            AppModal::Diff(diffs) => {
+                modal::Modal::new(Id::new("Diff-Window"))
+                    .show(ctx, |ui| show_diff_modal(ui, &diffs))
+                    .inner
+            }
+            AppModal::Export(target) => {
+                let count = self.state.selected.len();
+
+                modal::Modal::new(Id::new("Export-Window"))
+                    .show(ctx, |ui| show_export_modal(ui, target, count))
+                    .inner
+            }
+            AppModal::Import(dialog) => {
+                modal::Modal::new(Id::new("Import-Window"))
+                    .show(ctx, |ui| show_import_modal(ui, dialog))
+                    .inner
+            }
+            AppModal::CreateSub(parent, child) => {
+                modal::Modal::new(Id::new("Sub-Command-Window"))
+                    .show(ctx, |ui| {
+                        show_sub_command_modal(ui, parent, child, location)
+                    })
+                    .inner
+            }
+            AppModal::Group(title) => {
+                let count = self.state.selected.len();
+
+                modal::Modal::new(Id::new("Group-Window"))
+                    .show(ctx, |ui| show_group_modal(ui, title, count))
+                    .inner
+            }
+            AppModal::Locations => {
+                modal::Modal::new(Id::new("Locations-Window"))
+                    .show(ctx, |ui| show_locations_modal(ui, &self.pinned))
+                    .inner
+            }
+            AppModal::Simulate(simulator) => {
+                modal::Modal::new(Id::new("Simulate-Window"))
+                    .show(ctx, |ui| show_simulate_modal(ui, simulator))
+                    .inner
+            }
             AppModal::None => Message::None,
         }
     }
@@ -92,8 +246,37 @@ impl<'a> App<'a> {
             Message::AddCommand(cmd) => self.add_command(cmd),
             Message::UpdateCommand(cmd) => self.edit_command(cmd),
             Message::RemoveCommand(cmd) => self.remove_command(cmd),
+            Message::ToggleDisabled(cmd) => self.toggle_disabled(cmd),
+            Message::MoveItem(from, to) => self.move_item(from, to),
+            Message::SetPrefixOrder(prefixes) => self.state.prefix_order = prefixes,
+            Message::StageOrder => self.stage_order(),
+            Message::ResetOrder => self.show_pending(),
             Message::SetPath(path) => self.set_path(path),
-            Message::ReloadKey((path, index)) => self.reload_key(path, index), //Message::LoadBackup(path) => self.load_backup(path),
+            Message::ReloadKey((path, index)) => self.reload_key(path, index),
+            Message::OpenAssociation(index) => self.open_association(index),
+            Message::PinLocation(name, pinned) => self.pin_location(&name, pinned),
+            Message::Simulate(simulator) => self.simulate(simulator),
+            Message::ReviewChanges => self.review_changes(),
+            Message::ApplyChanges => self.apply_changes(),
+            Message::DiscardChanges => self.discard_changes(),
+            Message::ShowBackups => self.show_backups(),
+            Message::OpenBackup(path) => self.open_backup(path),
+            Message::RestoreAll(reader) => self.restore_backup(reader, None),
+            Message::RestoreVerbs(reader, verbs) => self.restore_backup(reader, Some(verbs)),
+            Message::CompareWithLive(reader) => self.compare_with_live(reader),
+            Message::CompareWithFile(reader, path) => self.compare_with_file(reader, path),
+            Message::ToggleSelected(cmd) => self.toggle_selected(cmd),
+            Message::ClearSelection => self.state.selected.clear(),
+            Message::ExportSelected(target, path) => self.export_selected(target, path),
+            Message::OpenImport(path) => self.open_import(path),
+            Message::ApplyImport(reader, verbs) => self.apply_import(reader, verbs),
+            Message::SetScope(scope) => self.set_scope(scope),
+            Message::MoveVerb(cmd, scope) => self.move_verb(cmd, scope),
+            Message::GroupSelected(title) => self.group_selected(title),
+            Message::OpenExtensions(index) => self.open_extensions(index),
+            Message::ToggleExtension(extension) => self.toggle_extension(extension),
+            Message::Undo => self.undo(),
+            Message::Redo => self.redo(),
             Message::None => {}
         }
     }
@@ -101,70 +284,109 @@ impl<'a> App<'a> {
     fn add_command(&mut self, mut cmd: StdCommand) {
         if let Some((key, p_index)) = &self.state.path {
             cmd.path = key.clone_path();
-            cmd.folder = folderize_title(&cmd.title);
+            cmd.hive = key.hive;
 
-            match add_to_context_window(&cmd, HKEY_CLASSES_ROOT) {
-                Ok(()) => self.state.items[*p_index].push(command_with_icon(cmd)),
-                Err(err) => {
-                    self.alert(format!("Add to context error: {}", err));
-                }
+            if cmd.folder.is_empty() {
+                cmd.folder = folderize_title(&cmd.title);
             }
+
+            // Adding writes into the key, an existing verb would be overwritten
+            if let Err(err) = check_key_name(&cmd.folder) {
+                self.alert(format!("Unable to add item, {}", err));
+                return;
+            } else if self.key_taken(&cmd.folder) {
+                self.alert(format!(
+                    "Unable to add item, \"{}\" already exists",
+                    cmd.folder
+                ));
+                return;
+            }
+
+            self.state.items[*p_index].push(command_with_icon(cmd.clone()));
+            self.stage(PendingChange::Add(cmd));
         } else {
-            self.alert(format!("You must open a path to add an item."));
+            self.alert("You must open a path to add an item.");
         }
 
         self.close_modal();
     }
 
     fn edit_command(&mut self, cmd: StdCommand) {
-        if let Some((_, p_index)) = &self.state.path {
-            match add_to_context_window(&cmd, HKEY_CLASSES_ROOT) {
-                Ok(()) => {
-                    match self.state.items[*p_index]
-                        .iter()
-                        .enumerate()
-                        .find(|x| x.1.0 == cmd)
-                    {
-                        Some((index, _)) => {
-                            let cur_item = &self.state.items[*p_index][index];
-
-                            // Only reload the icon if the command has a different icon
-                            if cur_item.0.icon != cmd.icon {
-                                self.state.items[*p_index][index] = command_with_icon(cmd);
-                            } else {
-                                self.state.items[*p_index][index].0 = cmd;
-                            }
-                        }
-                        None => {
-                            self.alert("Unable to edit item, item not found!");
+        if let Some(p_index) = self.state.path.as_ref().map(|(_, index)| *index) {
+            match self.state.items[p_index]
+                .iter()
+                .enumerate()
+                .find(|x| x.1.0 == cmd)
+            {
+                Some((index, _)) => {
+                    let old_folder = self.state.items[p_index][index].0.folder.clone();
+
+                    if old_folder != cmd.folder {
+                        if let Err(err) = self.check_rename(&old_folder, &cmd.folder) {
+                            self.alert(format!("Unable to rename item, {}", err));
+                            return;
                         }
+
+                        self.stage(PendingChange::Rename(cmd.clone(), old_folder));
+                    }
+
+                    self.stage(PendingChange::Edit(cmd.clone()));
+
+                    let cur_item = &self.state.items[p_index][index];
+
+                    // Only reload the icon if the command has a different icon
+                    if cur_item.0.icon != cmd.icon {
+                        self.state.items[p_index][index] = command_with_icon(cmd);
+                    } else {
+                        self.state.items[p_index][index].0 = cmd;
                     }
                 }
-                Err(err) => {
-                    self.alert(format!("Add to context error: {}", err));
+                None => {
+                    self.alert("Unable to edit item, item not found!");
                 }
             }
         } else {
-            self.alert(format!("You must open a path to edit an item."));
+            self.alert("You must open a path to edit an item.");
         }
 
         self.close_modal();
     }
 
+    fn check_rename(&self, from: &str, to: &str) -> NormalResult {
+        check_key_name(to)?;
+
+        // A verb that only changes case is taken by itself
+        if !from.eq_ignore_ascii_case(to) && self.key_taken(to) {
+            Err(format!("\"{}\" already exists", to).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Whether the open location has a verb named `name`, listed or already in its hive.
+    // Key names are compared the way the registry does, without case.
+    fn key_taken(&self, name: &str) -> bool {
+        let Some((key, p_index)) = &self.state.path else {
+            return false;
+        };
+
+        self.state.items[*p_index]
+            .iter()
+            .any(|(item, _)| item.folder.eq_ignore_ascii_case(name))
+            || self.registry.key_exists(
+                key.hive,
+                &format!("{}\\{}", key_path_string(&key.path), name),
+            )
+    }
+
     fn remove_command(&mut self, cmd: StdCommand) {
         if let Some((_, p_index)) = &self.state.path {
-            match remove_from_context_window(&cmd, HKEY_CLASSES_ROOT) {
-                Ok(()) => {
-                    self.state.items[*p_index] = self.state.items[*p_index]
-                        .iter()
-                        .filter(|x| x.0 != cmd)
-                        .map(|x| x.clone())
-                        .collect();
-                }
-                Err(err) => {
-                    self.alert(format!("Error removing item: {}", err));
-                }
-            }
+            self.state.items[*p_index] = self.state.items[*p_index]
+                .iter()
+                .filter(|x| x.0 != cmd)
+                .cloned()
+                .collect();
+            self.stage(PendingChange::Remove(cmd));
         } else {
             self.alert("Cannot remove item from empty path");
         }
@@ -172,6 +394,497 @@ impl<'a> App<'a> {
         self.close_modal();
     }
 
+    // The selected entries are moved under a new submenu, with everything their keys hold
+    fn group_selected(&mut self, title: String) {
+        let Some((key, p_index)) = self.state.path.clone() else {
+            self.alert("You must open a path to group items.");
+            return;
+        };
+
+        // The selection holds copies, the listed items share the open key's path
+        let verbs = self.state.items[p_index]
+            .iter()
+            .map(|(item, _)| item)
+            .filter(|item| self.state.selected.contains(item))
+            .cloned()
+            .collect::<Vec<StdCommand>>();
+        let folder = unique_key_name(&folderize_title(&title), |name| self.key_taken(name));
+
+        if verbs.len() != self.state.selected.len() {
+            self.alert("Only entries in the open location can be grouped.");
+            return;
+        }
+
+        let group = group_into_submenu(title, folder, &key, &verbs);
+
+        self.state.items[p_index].retain(|(item, _)| !verbs.contains(item));
+        self.state.items[p_index].push(command_with_icon(group.clone()));
+
+        // The moved keys already hold what the submenu lists, writing it leaves them as they are
+        let shell = nested_path(&group);
+        verbs
+            .into_iter()
+            .for_each(|verb| self.stage(PendingChange::Move(verb, Rc::clone(&shell))));
+        self.stage(PendingChange::Add(group));
+
+        self.state.selected.clear();
+        self.close_modal();
+    }
+
+    fn toggle_disabled(&mut self, mut cmd: StdCommand) {
+        let Some(p_index) = self.state.path.as_ref().map(|(_, index)| *index) else {
+            self.alert("You must open a path to disable an item.");
+            return;
+        };
+
+        cmd.attributes.disabled = !cmd.attributes.disabled;
+
+        match self.state.items[p_index]
+            .iter_mut()
+            .find(|(item, _)| *item == cmd)
+        {
+            Some((item, _)) => {
+                item.attributes.disabled = cmd.attributes.disabled;
+                self.stage(PendingChange::SetDisabled(cmd));
+            }
+            None => self.alert("Unable to disable item, item not found!"),
+        }
+    }
+
+    fn move_item(&mut self, from: usize, to: usize) {
+        let Some((key, p_index)) = self.state.path.as_ref() else {
+            return;
+        };
+        let p_index = *p_index;
+
+        // The shell key's default value lists the verbs Explorer tries first
+        if !self.state.reordered {
+            self.state.default_verbs = self
+                .registry
+                .get_string(key.hive, &key_path_string(&key.path), "")
+                .unwrap_or_default()
+                .split([',', ' '])
+                .filter(|verb| !verb.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        let items = &mut self.state.items[p_index];
+
+        if from < items.len() && to < items.len() {
+            let item = items.remove(from);
+            items.insert(to, item);
+            self.state.reordered = true;
+        }
+    }
+
+    // Stages what it takes for Explorer to show the open list in its current order
+    fn stage_order(&mut self) {
+        let Some(p_index) = self.state.path.as_ref().map(|(_, index)| *index) else {
+            return;
+        };
+
+        let order = self.state.items[p_index]
+            .iter()
+            .map(|(item, _)| item.clone())
+            .collect::<Vec<StdCommand>>();
+        let plan = plan_order(&order, self.state.prefix_order, &self.state.default_verbs);
+
+        plan.changes.into_iter().for_each(|change| {
+            let cmd = change.command();
+            let from = change.renamed_from().unwrap_or(&cmd.folder);
+
+            if let Some((item, _)) = self.state.items[p_index]
+                .iter_mut()
+                .find(|(item, _)| item.folder == from)
+            {
+                item.folder = cmd.folder.clone();
+                item.attributes.position = cmd.attributes.position;
+            }
+
+            self.stage(change);
+        });
+
+        self.state.reordered = false;
+    }
+
+    fn review_changes(&mut self) {
+        match preview_changes(&self.state.pending, self.registry.as_ref()) {
+            Ok(preview) => self.update_modal(AppModal::Review(preview.into())),
+            Err(err) => self.alert(format!("Unable to preview changes: {}", err)),
+        }
+    }
+
+    fn apply_changes(&mut self) {
+        let keys = self
+            .state
+            .pending
+            .iter()
+            .flat_map(PendingChange::keys)
+            .collect::<Vec<(Hive, String)>>();
+        let label = match self.state.pending.as_slice() {
+            [change] => change.to_string(),
+            changes => format!("Apply {} changes", changes.len()),
+        };
+
+        match HistoryEntry::record(label, &keys, self.registry.as_ref(), || {
+            apply_changes(&self.state.pending, self.registry.as_ref())
+        }) {
+            Ok(entry) => {
+                self.history.push(entry);
+                self.state.pending.clear();
+                self.state.unstaged.clear();
+                self.close_modal();
+                self.reload_items();
+            }
+            Err(err) => self.alert(format!("Unable to apply changes: {}", err)),
+        }
+    }
+
+    fn discard_changes(&mut self) {
+        self.state.pending.clear();
+        self.state.unstaged.clear();
+        self.close_modal();
+        self.reload_items();
+    }
+
+    fn show_diff(&mut self, old: &RegReader, new: &RegReader) {
+        match diff_registries(old, new) {
+            Ok(diffs) => self.update_modal(AppModal::Diff(diffs.into())),
+            Err(err) => self.alert(format!("Unable to compare: {}", err)),
+        }
+    }
+
+    fn compare_with_live(&mut self, reader: Rc<RegReader>) {
+        match snapshot(&self.keys, self.registry.as_ref()) {
+            Ok(live) => self.show_diff(&reader, &live),
+            Err(err) => self.alert(format!("Unable to read the registry: {}", err)),
+        }
+    }
+
+    fn compare_with_file(&mut self, reader: Rc<RegReader>, path: PathBuf) {
+        match RegReader::try_read_file(&path) {
+            Ok(other) => self.show_diff(&reader, &other),
+            Err(err) => self.alert(format!("Unable to read file: {}", err)),
+        }
+    }
+
+    fn toggle_selected(&mut self, mut cmd: StdCommand) {
+        if self.state.selected.contains(&cmd) {
+            self.state.selected.retain(|selected| *selected != cmd);
+        } else {
+            // The path is shared with the open key, which changes along with the extension box
+            let path = cmd.path.borrow().clone();
+            cmd.path = Rc::new(RefCell::new(path));
+            self.state.selected.push(cmd);
+        }
+    }
+
+    fn export_selected(&mut self, target: ExportTarget, path: PathBuf) {
+        match export_verbs(&self.state.selected, self.registry.as_ref(), target)
+            .and_then(|writer| writer.write_to(&path))
+        {
+            Ok(()) => {
+                self.close_modal();
+                self.alert(format!(
+                    "Exported {} entries to {}",
+                    self.state.selected.len(),
+                    path.display()
+                ));
+            }
+            Err(err) => self.alert(format!("Unable to export: {}", err)),
+        }
+    }
+
+    fn open_import(&mut self, path: PathBuf) {
+        match RegReader::try_read_file(&path) {
+            Ok(reader) => {
+                let plan = plan_import(&reader, &self.keys, self.registry.as_ref());
+                let suggested = plan
+                    .verbs
+                    .iter()
+                    .map(|import| suggest_name(self.registry.as_ref(), &import.verb))
+                    .collect();
+
+                self.update_modal(AppModal::Import(ImportDialog {
+                    file: path,
+                    reader: Rc::new(reader),
+                    plan,
+                    suggested,
+                }));
+            }
+            Err(err) => self.alert(format!("Unable to read file: {}", err)),
+        }
+    }
+
+    fn apply_import(&mut self, reader: Rc<RegReader>, verbs: Vec<ImportVerb>) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before importing.");
+            return;
+        }
+
+        let keys = import_keys(&verbs, self.registry.as_ref());
+        let label = format!("Import {} entries", keys.len());
+        let mut mismatches = Vec::new();
+
+        let result = HistoryEntry::record(label, &keys, self.registry.as_ref(), || {
+            mismatches = apply_import(&reader, &verbs, self.registry.as_ref())?;
+            Ok(())
+        })
+        .map(|entry| {
+            self.history.push(entry);
+            mismatches
+        });
+
+        match result {
+            Ok(mismatches) if mismatches.is_empty() => {
+                self.close_modal();
+                self.reload_items();
+            }
+            Ok(mismatches) => {
+                self.close_modal();
+                self.reload_items();
+                self.alert(format!(
+                    "Imported the file, but {} entries do not match it:\n{}",
+                    mismatches.len(),
+                    mismatches
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                ));
+            }
+            Err(err) => self.alert(format!("Unable to import: {}", err)),
+        }
+    }
+
+    fn move_verb(&mut self, cmd: StdCommand, scope: Scope) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before moving an entry.");
+            return;
+        }
+
+        let label = format!("Move \"{}\" to {}", cmd.title, scope);
+
+        match HistoryEntry::record(label, &move_keys(&cmd), self.registry.as_ref(), || {
+            move_verb(&cmd, scope, self.registry.as_ref())
+        }) {
+            Ok(entry) => {
+                self.history.push(entry);
+                self.reload_items();
+            }
+            Err(err) => self.alert(format!("Unable to move \"{}\": {}", cmd.title, err)),
+        }
+    }
+
+    fn open_extensions(&mut self, index: usize) {
+        let Some(key) = self.extension_keys.get(index) else {
+            return;
+        };
+
+        match read_extensions(key, self.registry.as_ref()) {
+            Ok(extensions) => {
+                self.state.path = None;
+                self.state.extensions = Some((index, extensions));
+            }
+            Err(err) => self.alert(format!("Unable to list shell extensions: {}", err)),
+        }
+    }
+
+    fn toggle_extension(&mut self, extension: ShellExtension) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before toggling an extension.");
+            return;
+        }
+
+        let enabled = !extension.is_enabled();
+        let hive = block_hive(self.scope);
+        let label = format!(
+            "{} \"{}\"",
+            if enabled { "Enable" } else { "Disable" },
+            extension.name
+        );
+
+        match HistoryEntry::record(
+            label,
+            &toggle_keys(&extension, hive),
+            self.registry.as_ref(),
+            || set_enabled(&extension, enabled, hive, self.registry.as_ref()),
+        ) {
+            Ok(entry) => {
+                self.history.push(entry);
+                self.reload_items();
+            }
+            Err(err) => self.alert(format!("Unable to toggle \"{}\": {}", extension.name, err)),
+        }
+    }
+
+    fn stage(&mut self, change: PendingChange) {
+        self.state.pending.push(change);
+        self.state.unstaged.clear();
+    }
+
+    // Reloads the lists from the registry and shows the pending changes on top again
+    fn show_pending(&mut self) {
+        self.reload_items();
+
+        let pending = self.state.pending.clone();
+
+        pending.iter().for_each(|change| {
+            let cmd = change.command();
+            // Verbs of an open association share its key's path instead of a configured one
+            let Some(index) = self
+                .keys
+                .iter()
+                .position(|key| Rc::ptr_eq(&key.path, &cmd.path))
+                .or_else(|| {
+                    self.state
+                        .path
+                        .as_ref()
+                        .filter(|(key, _)| Rc::ptr_eq(&key.path, &cmd.path))
+                        .map(|(_, index)| *index)
+                })
+            else {
+                return;
+            };
+
+            // Reloading gives every item a new id, the key name still matches
+            let items = &mut self.state.items[index];
+            items.retain(|(item, _)| {
+                item.folder != cmd.folder && Some(item.folder.as_str()) != change.renamed_from()
+            });
+
+            if !matches!(change, PendingChange::Remove(_) | PendingChange::Move(..)) {
+                items.push(command_with_icon(cmd.clone()));
+            }
+        });
+    }
+
+    // Pending changes are taken back first, then changes already written
+    fn undo(&mut self) {
+        if let Some(change) = self.state.pending.pop() {
+            self.state.unstaged.push(change);
+            self.show_pending();
+            return;
+        }
+
+        match self.history.undo(self.registry.as_ref()) {
+            Ok(Some(_)) => self.reload_items(),
+            Ok(None) => {}
+            Err(err) => self.alert(format!("Unable to undo: {}", err)),
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(change) = self.state.unstaged.pop() {
+            self.state.pending.push(change);
+            self.show_pending();
+            return;
+        }
+
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before redoing.");
+            return;
+        }
+
+        match self.history.redo(self.registry.as_ref()) {
+            Ok(Some(_)) => self.reload_items(),
+            Ok(None) => {}
+            Err(err) => self.alert(format!("Unable to redo: {}", err)),
+        }
+    }
+
+    fn set_scope(&mut self, scope: Scope) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before switching scope.");
+            return;
+        }
+
+        self.scope = scope;
+        self.keys = self
+            .config_keys
+            .iter()
+            .map(|key| key.in_scope(scope))
+            .collect();
+        self.extension_keys = self
+            .config_extension_keys
+            .iter()
+            .map(|key| key.in_scope(scope))
+            .collect();
+        self.set_path(None);
+        self.reload_items();
+    }
+
+    // Pinned locations are tabs after the ones already open, unpinning one closes it
+    fn pin_location(&mut self, name: &str, pin: bool) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before changing the tabs.");
+            return;
+        }
+
+        let Some(location) = find_location(name) else {
+            return;
+        };
+
+        if pin {
+            if self.config_keys.iter().any(|key| key.name == location.name) {
+                self.alert(format!("\"{}\" is already a tab.", location.name));
+                return;
+            }
+
+            self.pinned.push(location.name.to_string());
+            self.config_keys = self
+                .config_keys
+                .iter()
+                .cloned()
+                .chain([location.key()])
+                .collect();
+        } else {
+            if !self.pinned.iter().any(|pinned| pinned == location.name) {
+                self.alert(format!(
+                    "\"{}\" is set in the config's [RegKeys], remove it there.",
+                    location.name
+                ));
+                return;
+            }
+
+            self.pinned.retain(|pinned| pinned != location.name);
+            self.config_keys = self
+                .config_keys
+                .iter()
+                .filter(|key| key.name != location.name)
+                .cloned()
+                .collect();
+        }
+
+        self.keys = self
+            .config_keys
+            .iter()
+            .map(|key| key.in_scope(self.scope))
+            .collect();
+        self.set_path(None);
+        self.reload_items();
+
+        if let Err(err) = save_config_option(PINNED_KEY, &self.pinned.join(", ")) {
+            self.alert(format!("Unable to save the pinned locations: {}", err));
+        }
+    }
+
+    fn simulate(&mut self, simulator: Simulator) {
+        match simulate(
+            self.registry.as_ref(),
+            simulator.path.trim(),
+            simulator.kind,
+            simulator.extended,
+        ) {
+            Ok(simulation) => self.update_modal(AppModal::Simulate(Simulator {
+                result: Some(Rc::new(simulation)),
+                ..simulator
+            })),
+            Err(err) => self.alert(format!("Unable to build the menu: {}", err)),
+        }
+    }
+
     fn close_modal(&mut self) {
         self.state.modal = AppModal::None;
     }
@@ -203,11 +916,59 @@ impl<'a> App<'a> {
     }
 
     fn set_path(&mut self, path: Option<(Key, usize)>) {
+        if path.is_some() {
+            self.state.extensions = None;
+        }
+
+        if self.state.reordered {
+            self.show_pending();
+        }
+
+        self.state.associations = path
+            .as_ref()
+            .map(|(key, _)| resolve_associations(self.registry.as_ref(), key))
+            .unwrap_or_default();
+
+        if let Some((key, _)) = &path
+            && key.properties == KeyProps::PerceivedType
+            && self.state.perceived_types.is_empty()
+        {
+            match read_perceived_types(self.registry.as_ref()) {
+                Ok(types) => self.state.perceived_types = types,
+                Err(err) => self.alert(format!("Unable to list perceived types: {}", err)),
+            }
+        }
+
         self.state.path = path;
     }
 
+    // Shows the verbs of one of the keys the open file type takes them from
+    fn open_association(&mut self, association: usize) {
+        let (Some((_, index)), Some(association)) = (
+            self.state.path.as_ref(),
+            self.state.associations.get(association),
+        ) else {
+            return;
+        };
+        let (key, index) = (association.key.clone(), *index);
+
+        if self.state.reordered {
+            self.show_pending();
+        }
+
+        self.state.selected.clear();
+        self.state.path = Some((key.clone(), index));
+        self.reload_key(key, index);
+    }
+
     fn reload_key(&mut self, key: Key, index: usize) {
-        match load_item(&key) {
+        // Reloading a file type starts again from the extension
+        if key.properties == KeyProps::HasExt {
+            self.state.associations = resolve_associations(self.registry.as_ref(), &key);
+            self.state.path = Some((key.clone(), index));
+        }
+
+        match load_item(&key, self.registry.as_ref()) {
             Ok(new_list) => self.state.items[index] = new_list,
             Err(err) => {
                 self.alert(err.to_string());
@@ -217,44 +978,81 @@ impl<'a> App<'a> {
         }
     }
 
-    /*fn load_backup<T: AsRef<Path>>(&mut self, path: T) {
-        use crate::registry_io::reader::RegReader;
+    fn show_backups(&mut self) {
+        match self.backup.dir().and_then(|dir| list_backups(&dir)) {
+            Ok(files) => self.update_modal(AppModal::Backups(BackupBrowser {
+                files: files.into(),
+                open: None,
+            })),
+            Err(err) => self.alert(format!("Unable to list backups: {}", err)),
+        }
+    }
 
-        let reader: RegReader = match RegReader::try_read_file(path) {
-            Ok(reader) => reader,
-            Err(err) => {
-                self.alert(format!("Unable to read file: {}", err));
-                return;
-            }
+    fn open_backup(&mut self, path: PathBuf) {
+        let AppModal::Backups(browser) = &self.state.modal else {
+            return;
         };
 
-        let tr = match Transaction::new() {
-            Ok(tr) => tr,
-            Err(err) => {
-                self.alert(format!("Unable to create transaction: {}", err));
-                return;
+        match RegReader::try_read_file(&path) {
+            Ok(reader) => {
+                let browser = BackupBrowser {
+                    files: Rc::clone(&browser.files),
+                    open: Some(OpenBackup::new(path, reader)),
+                };
+                self.update_modal(AppModal::Backups(browser));
             }
+            Err(err) => self.alert(format!("Unable to read file: {}", err)),
+        }
+    }
+
+    // None restores the whole file
+    fn restore_backup(&mut self, reader: Rc<RegReader>, verbs: Option<Vec<RegVerb>>) {
+        if !self.state.pending.is_empty() {
+            self.alert("Apply or discard your pending changes before restoring a backup.");
+            return;
+        }
+
+        let keys = restore_keys(&reader, verbs.as_deref());
+        let label = match &verbs {
+            Some(verbs) => format!("Restore {} entries from a backup", verbs.len()),
+            None => "Restore a backup".to_string(),
         };
+        let mut mismatches = Vec::new();
 
-        match reader.load_all_transacted(&tr) {
-            Ok(()) => {}
-            Err(err) => {
+        let result = HistoryEntry::record(label, &keys, self.registry.as_ref(), || {
+            mismatches = match &verbs {
+                Some(verbs) => restore_verbs(&reader, verbs, self.registry.as_ref())?,
+                None => restore_all(&reader, self.registry.as_ref())?,
+            };
+            Ok(())
+        })
+        .map(|entry| {
+            self.history.push(entry);
+            mismatches
+        });
+
+        match result {
+            Ok(mismatches) if mismatches.is_empty() => {
+                self.close_modal();
+                self.alert("Restored the backup, the registry now matches it.");
+            }
+            Ok(mismatches) => {
+                self.close_modal();
                 self.alert(format!(
-                    "Unable to load regasshi values into winreg: {}",
-                    err
+                    "Restored the backup, but {} entries do not match it:\n{}",
+                    mismatches.len(),
+                    mismatches
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>()
+                        .join("\n")
                 ));
-                return;
-            }
-        };
-
-        match tr.commit() {
-            Ok(()) => (),
-            Err(err) => {
-                self.alert(format!("Unable to commit transaction: {}", err));
-                ()
             }
+            Err(err) => self.alert(format!("Unable to restore backup: {}", err)),
         }
-    }*/
+
+        self.reload_items();
+    }
 }
 
 impl<'a> eframe::App for App<'a> {
@@ -262,26 +1060,72 @@ impl<'a> eframe::App for App<'a> {
         egui::CentralPanel::default().show(ctx, |ui| {
             //println!("{:?}", ui.style());
 
-            ui.horizontal_top(|ui| {
-                ui.heading(self.heading);
+            let heading_msg = ui
+                .horizontal_top(|ui| {
+                    ui.heading(self.heading);
 
-                ui.add_space(50f32);
+                    ui.add_space(50f32);
 
-                /*if let Some(reg_file) =
-                    add_dialog_button(ui, "Load Backup", &[("Registry Files", &["reg"])])
-                {
-                    self.load_backup(reg_file);
-                }*/
-            });
+                    let scope = render_scope(self.scope, ui);
+                    let history = render_history(&self.history, &self.state, ui);
+                    let locations = ui.add(button_standard("Locations")).clicked();
+                    let simulate = ui.add(button_standard("Simulate")).clicked();
+                    let backups = ui.add(button_standard("Backups")).clicked();
+                    let import = add_dialog_button(ui, "Import", &[("Registry Files", &["reg"])]);
+                    let pending = render_pending(&self.state.pending, ui);
+
+                    if let Some(scope) = scope {
+                        Message::SetScope(scope)
+                    } else if !matches!(history, Message::None) {
+                        history
+                    } else if locations {
+                        Message::UpdateModal(AppModal::Locations)
+                    } else if simulate {
+                        Message::UpdateModal(AppModal::Simulate(Simulator::default()))
+                    } else if backups {
+                        Message::ShowBackups
+                    } else if let Some(path) = import {
+                        Message::OpenImport(path)
+                    } else {
+                        pending
+                    }
+                })
+                .inner;
+
+            self.handle_message(heading_msg);
 
             self.handle_message(
-                ui.horizontal_top(|ui| render_heading(&self.keys, &self.state.path, ui))
-                    .inner,
+                ui.horizontal_top(|ui| {
+                    let heading = render_heading(&self.keys, &self.state.path, ui);
+
+                    ui.separator();
+
+                    let open = self.state.extensions.as_ref().map(|(index, _)| *index);
+                    let extensions = render_extension_tabs(&self.extension_keys, open, ui);
+
+                    if matches!(heading, Message::None) {
+                        extensions
+                    } else {
+                        heading
+                    }
+                })
+                .inner,
             );
 
             ui.add_space(10f32);
 
-            self.handle_message(render_context_menu(&self.state, &self.style, ctx, ui));
+            match &self.state.extensions {
+                Some((_, extensions)) => {
+                    let msg = render_extensions(extensions, ui);
+                    self.handle_message(msg);
+                }
+                None => self.handle_message(render_context_menu(&self.state, &self.style, ctx, ui)),
+            }
+
+            // Text boxes in the modals have their own undo
+            if matches!(self.state.modal, AppModal::None) {
+                self.handle_message(history_shortcut(ctx));
+            }
 
             let msg = self.render_modal(ctx);
 
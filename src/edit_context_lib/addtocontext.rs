@@ -1,14 +1,16 @@
 use std::{cell::RefCell, error::Error, ffi::OsStr, fmt::Display, rc::Rc};
 
-use winreg::{RegKey, types::ToRegValue};
-
+use crate::edit_context_lib::attributes::{VerbAttributes, write_value};
+use crate::edit_context_lib::handler::read_handler;
+use crate::edit_context_lib::problem::VerbProblem;
+use crate::edit_context_lib::submenu::{read_submenu, write_submenu};
 use crate::edit_context_lib::types::{
-    ContextCommandInfo, KeyPath, NormalResult, StdCommand, StdCommandList,
+    ContextCommandInfo, KeyPath, NormalResult, StdCommand, StdCommandList, key_path_string,
 };
+use crate::registry_io::backend::{Hive, RegData, RegistryBackend};
 
 pub const DIRECTORY_SUBKEY: [&str; 2] = ["Directory", "shell"];
 pub const BACKGROUND_SUBKEY: [&str; 3] = ["Directory", "Background", "shell"];
-pub const FILE_SUBKEY: [&str; 2] = ["*", "shell"];
 
 pub fn convert_subkey(subkey: &[&str]) -> KeyPath {
     Rc::new(RefCell::new(
@@ -19,12 +21,7 @@ pub fn convert_subkey(subkey: &[&str]) -> KeyPath {
     ))
 }
 
-fn validate_command_info<
-    U: AsRef<OsStr>,
-    T: ToRegValue + Display,
-    V: ToRegValue + Display,
-    W: ToRegValue + Display,
->(
+fn validate_command_info<U: AsRef<OsStr>, T: Display, V: Display, W: Display>(
     command_info: &ContextCommandInfo<T, V, W, U>,
 ) -> NormalResult {
     if command_info.title.to_string().is_empty()
@@ -37,114 +34,334 @@ fn validate_command_info<
     }
 }
 
-pub fn add_to_context_window<
-    U: AsRef<OsStr>,
-    T: ToRegValue + Display,
-    V: ToRegValue + Display,
-    W: ToRegValue + Display,
->(
+pub fn add_to_context_window<U: AsRef<OsStr>, T: Display, V: Display, W: Display>(
     command_info: &ContextCommandInfo<T, V, W, U>,
-    hkey: winreg::HKEY,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
 ) -> NormalResult {
     validate_command_info(command_info)?;
 
-    let root = RegKey::predef(hkey);
+    let path = key_path_string(&command_info.path);
+
+    // Per-user and machine classes only hold the locations something has been added to,
+    // so those are created as needed. HKEY_CLASSES_ROOT is expected to have them already.
+    if hive != Hive::ClassesRoot {
+        reg.create_key(hive, &path)?;
+    } else if !reg.key_exists(hive, &path) {
+        return Err(format!("Location {}\\{} does not exist", hive, path).into());
+    }
 
-    let file: RegKey = command_info
-        .path
-        .borrow()
-        .iter()
-        .fold(Ok(root), |cur, next| {
-            let next_str = next.as_ref();
-            cur?.open_subkey(next_str)
-        })?;
+    let file = format!(
+        "{}\\{}",
+        path,
+        command_info.folder.as_ref().to_string_lossy()
+    );
 
-    let (file, _) = file.create_subkey(&command_info.folder)?;
+    reg.create_key(hive, &file)?;
 
-    // Set name and icon
-    file.set_value("", &command_info.title)?;
-    file.set_value("Icon", &command_info.icon)?;
+    // Set name and icon, a verb without an icon has no Icon value at all
+    let title = command_info.title.to_string();
+    write_value(reg, hive, &file, "", Some(RegData::sz(&title)))?;
+    let icon = command_info.icon.to_string();
+    write_value(
+        reg,
+        hive,
+        &file,
+        "Icon",
+        (!icon.is_empty()).then(|| RegData::sz(&icon)),
+    )?;
+    command_info.attributes.write(reg, hive, &file)?;
 
-    // Command can be empty for multi-level menu options
-    if !command_info.command.to_string().is_empty() {
-        let (command_file, _) = file.create_subkey("command")?;
-        command_file.set_value("", &command_info.command)?;
+    // Explorer labels a cascading menu with MUIVerb, the default value isn't shown
+    if command_info.submenu.is_some() && command_info.attributes.mui_verb.is_none() {
+        write_value(reg, hive, &file, "MUIVerb", Some(RegData::sz(&title)))?;
+    }
+
+    write_submenu(command_info.submenu.as_ref(), reg, hive, &file)?;
+
+    // Command can be empty for multi-level menu options and verbs run by a COM handler,
+    // otherwise a cleared command goes with its key
+    let command = command_info.command.to_string();
+    let command_file = file + "\\command";
+
+    if !command.is_empty() {
+        reg.create_key(hive, &command_file)?;
+        write_value(reg, hive, &command_file, "", Some(RegData::sz(&command)))?;
+    } else if command_info.submenu.is_none()
+        && command_info.handler.is_none()
+        && reg.key_exists(hive, &command_file)
+    {
+        reg.delete_key_all(hive, &command_file)?;
     }
 
     Ok(())
 }
 
-pub fn get_current_context_window(
+/// Reads the verb `name` under the location `path`
+pub fn read_command(
     path: KeyPath,
-    hkey: winreg::HKEY,
-) -> Result<StdCommandList, Box<dyn Error>> {
-    let root = RegKey::predef(hkey);
-
-    let file: RegKey = path.borrow().iter().fold(Ok(root), |cur, next| {
-        let next_str: &str = next.as_ref();
-        cur?.open_subkey(next_str)
-    })?;
-
-    let subnames = file
-        .enum_keys()
-        .filter_map(|x| match x {
-            Ok(v) => Some(v),
-            _ => None,
-        })
-        .map(|name| {
-            let key = file.open_subkey(&name)?;
+    name: String,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+) -> Result<StdCommand, Box<dyn Error>> {
+    read_command_at(path, name, reg, hive, 0)
+}
 
-            let title: String = key.get_value("")?;
-            let icon: String = key.get_value("icon")?;
+// `depth` is how many submenus down the verb is. Values that are missing are noted in
+// `problems`, only a key that can't be read at all is an error.
+pub(super) fn read_command_at(
+    path: KeyPath,
+    name: String,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    depth: usize,
+) -> Result<StdCommand, Box<dyn Error>> {
+    let key = format!("{}\\{}", key_path_string(&path), name);
 
-            let command_key = key.open_subkey("command")?;
+    if !reg.key_exists(hive, &key) {
+        return Err(format!("{}\\{} does not exist", hive, key).into());
+    }
+
+    let mut problems = Vec::new();
+    let attributes = VerbAttributes::read(reg, hive, &key);
 
-            let command: String = command_key.get_value("")?;
+    let title = reg
+        .get_string(hive, &key, "")
+        .ok()
+        .filter(|title| !title.is_empty())
+        .or_else(|| attributes.mui_verb.clone())
+        .unwrap_or_else(|| {
+            problems.push(VerbProblem::NoTitle);
+            name.clone()
+        });
+    let icon = reg.get_string(hive, &key, "icon").unwrap_or_else(|_| {
+        problems.push(VerbProblem::NoIcon);
+        String::new()
+    });
 
-            Ok(ContextCommandInfo::new(
-                title,
-                icon,
-                command,
-                name,
-                Rc::clone(&path),
-            ))
+    let mut cmd = ContextCommandInfo::new(title, icon, String::new(), name, path).with_hive(hive);
+    cmd.attributes = attributes;
+    cmd.submenu = read_submenu(reg, hive, &cmd, depth);
+    cmd.handler = read_handler(reg, hive, &key);
+
+    if let Some(handler) = cmd
+        .handler
+        .as_ref()
+        .filter(|handler| !handler.is_registered())
+    {
+        problems.push(VerbProblem::UnknownClsid(handler.clsid.clone()));
+    }
+
+    let command_key = key + "\\command";
+
+    // Submenus and COM handlers have no command line to run
+    match reg.get_string(hive, &command_key, "") {
+        Ok(command) => cmd.command = command,
+        Err(_) if cmd.submenu.is_some() || cmd.handler.is_some() => {}
+        Err(_) if reg.key_exists(hive, &command_key) => problems.push(VerbProblem::EmptyCommand),
+        Err(_) => problems.push(VerbProblem::NoCommandKey),
+    }
+
+    cmd.problems = problems;
+    Ok(cmd)
+}
+
+/// Stands in for a verb whose key couldn't be read, so it can still be seen and removed
+pub(super) fn unreadable(
+    path: KeyPath,
+    name: String,
+    hive: Hive,
+    err: Box<dyn Error>,
+) -> StdCommand {
+    let mut cmd = ContextCommandInfo::new(name.clone(), String::new(), String::new(), name, path)
+        .with_hive(hive);
+    cmd.problems = vec![VerbProblem::Unreadable(err.to_string())];
+    cmd
+}
+
+pub fn get_current_context_window(
+    path: KeyPath,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+) -> Result<StdCommandList, Box<dyn Error>> {
+    let file = key_path_string(&path);
+
+    // Nothing has been added to this location in this hive yet
+    if hive != Hive::ClassesRoot && !reg.key_exists(hive, &file) {
+        return Ok(StdCommandList::new());
+    }
+
+    let subnames = reg
+        .enum_keys(hive, &file)?
+        .into_iter()
+        .map(|name| {
+            read_command(Rc::clone(&path), name.clone(), reg, hive)
+                .unwrap_or_else(|err| unreadable(Rc::clone(&path), name, hive, err))
         })
-        .filter_map(
-            |info_result: Result<StdCommand, Box<dyn Error>>| match info_result {
-                Ok(info) => Some(info),
-                _ => None,
-            },
-        )
         .collect::<StdCommandList>();
 
     Ok(subnames)
 }
 
-pub fn remove_from_context_window<
-    U: AsRef<OsStr> + Display,
-    T: ToRegValue + Display,
-    V: ToRegValue + Display,
-    W: ToRegValue + Display,
->(
+pub fn remove_from_context_window<U: AsRef<OsStr> + Display, T: Display, V: Display, W: Display>(
     command_info: &ContextCommandInfo<T, V, W, U>,
-    hkey: winreg::HKEY,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
 ) -> NormalResult {
     validate_command_info(command_info)?;
 
-    let root = RegKey::predef(hkey);
-
-    let file: RegKey = command_info
-        .path
-        .borrow()
-        .iter()
-        .fold(Ok(root), |cur, next| {
-            let next_str = next.as_ref();
-            cur?.open_subkey(next_str)
-        })?;
-
     println!("{}", &command_info.folder);
 
-    file.delete_subkey_all(&command_info.folder)?;
+    reg.delete_key_all(
+        hive,
+        &format!(
+            "{}\\{}",
+            key_path_string(&command_info.path),
+            command_info.folder
+        ),
+    )?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    #[test]
+    fn edits_keep_value_types_and_a_cleared_command_is_removed() {
+        let reg = MemoryRegistry::new();
+        let verb = "Directory\\shell\\Tool";
+        let icon = "%SystemRoot%\\system32\\shell32.dll,3";
+        let command = "%SystemRoot%\\system32\\notepad.exe \"%V\"";
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", verb))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, verb, "", "Tool").unwrap();
+        reg.set_value(Hive::ClassesRoot, verb, "Icon", &RegData::expand_sz(icon))
+            .unwrap();
+        reg.set_value(
+            Hive::ClassesRoot,
+            &format!("{}\\command", verb),
+            "",
+            &RegData::expand_sz(command),
+        )
+        .unwrap();
+
+        let mut cmd = read_command(
+            convert_subkey(&DIRECTORY_SUBKEY),
+            "Tool".to_string(),
+            &reg,
+            Hive::ClassesRoot,
+        )
+        .unwrap();
+        cmd.title = "Renamed".to_string();
+        add_to_context_window(&cmd, &reg, Hive::ClassesRoot).unwrap();
+
+        assert_eq!(
+            reg.get_value(Hive::ClassesRoot, verb, "Icon").unwrap(),
+            RegData::expand_sz(icon)
+        );
+        assert_eq!(
+            reg.get_value(Hive::ClassesRoot, &format!("{}\\command", verb), "")
+                .unwrap(),
+            RegData::expand_sz(command)
+        );
+
+        cmd.command = String::new();
+        add_to_context_window(&cmd, &reg, Hive::ClassesRoot).unwrap();
+        assert!(!reg.key_exists(Hive::ClassesRoot, &format!("{}\\command", verb)));
+    }
+
+    #[test]
+    fn add_list_and_remove_round_trip() {
+        let reg = MemoryRegistry::new();
+        let path = convert_subkey(&DIRECTORY_SUBKEY);
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+
+        let cmd = StdCommand::new(
+            "Open Here".to_string(),
+            "cmd.exe".to_string(),
+            "cmd.exe /k cd \"%V\"".to_string(),
+            "OpenHere".to_string(),
+            Rc::clone(&path),
+        );
+
+        add_to_context_window(&cmd, &reg, Hive::ClassesRoot).unwrap();
+
+        let listed = get_current_context_window(Rc::clone(&path), &reg, Hive::ClassesRoot).unwrap();
+
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].title, "Open Here");
+        assert_eq!(listed[0].icon, "cmd.exe");
+        assert_eq!(listed[0].command, "cmd.exe /k cd \"%V\"");
+        assert_eq!(listed[0].folder, "OpenHere");
+
+        let mut cleared = cmd.clone();
+        cleared.icon = String::new();
+        add_to_context_window(&cleared, &reg, Hive::ClassesRoot).unwrap();
+
+        let listed = get_current_context_window(Rc::clone(&path), &reg, Hive::ClassesRoot).unwrap();
+        assert_eq!(listed[0].problems, [VerbProblem::NoIcon]);
+
+        remove_from_context_window(&cmd, &reg, Hive::ClassesRoot).unwrap();
+
+        assert!(
+            get_current_context_window(path, &reg, Hive::ClassesRoot)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn incomplete_verbs_are_listed_with_the_reason() {
+        let reg = MemoryRegistry::new();
+        let path = convert_subkey(&DIRECTORY_SUBKEY);
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\Leftover")
+            .unwrap();
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\NoIcon\\command")
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, "Directory\\shell\\NoIcon", "", "No Icon")
+            .unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            "Directory\\shell\\NoIcon\\command",
+            "",
+            "tool.exe",
+        )
+        .unwrap();
+
+        let listed = get_current_context_window(path, &reg, Hive::ClassesRoot).unwrap();
+
+        assert_eq!(listed[0].title, "Leftover");
+        assert_eq!(
+            listed[0].problems,
+            [
+                VerbProblem::NoTitle,
+                VerbProblem::NoIcon,
+                VerbProblem::NoCommandKey
+            ]
+        );
+        assert_eq!(listed[1].command, "tool.exe");
+        assert_eq!(listed[1].problems, [VerbProblem::NoIcon]);
+    }
+
+    #[test]
+    fn add_requires_existing_location() {
+        let reg = MemoryRegistry::new();
+
+        let cmd = StdCommand::new(
+            "Title".to_string(),
+            "".to_string(),
+            "".to_string(),
+            "Title".to_string(),
+            convert_subkey(&BACKGROUND_SUBKEY),
+        );
+
+        assert!(add_to_context_window(&cmd, &reg, Hive::ClassesRoot).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::Display;
+
+use crate::edit_context_lib::scope::find_classes_key;
+use crate::edit_context_lib::types::{Key, KeyProps, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+/// Where the per-user default program for each extension is kept
+pub const FILE_EXTS: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\FileExts";
+
+// CurVer can point to a ProgID that has a CurVer of its own
+const MAX_CUR_VER: usize = 4;
+
+/// Why Explorer shows the verbs of a key for a file type
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssociationSource {
+    Extension,
+    // The default value of the extension's key, after following CurVer from the second
+    ProgId(String, Option<String>),
+    // The program the user picked with "Open with", it can differ from the ProgID
+    UserChoice(String),
+    SystemFileAssociations,
+}
+
+impl Display for AssociationSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Extension => write!(f, "Extension"),
+            Self::ProgId(prog_id, None) => write!(f, "ProgID {}", prog_id),
+            Self::ProgId(prog_id, Some(from)) => {
+                write!(f, "ProgID {} (CurVer of {})", prog_id, from)
+            }
+            Self::UserChoice(prog_id) => write!(f, "UserChoice {}", prog_id),
+            Self::SystemFileAssociations => write!(f, "SystemFileAssociations"),
+        }
+    }
+}
+
+/// One of the keys whose verbs are shown for a file type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Association {
+    pub source: AssociationSource,
+    pub key: Key,
+}
+
+// The default value of a class, from the hive's own classes or the merged view
+fn class_default(reg: &dyn RegistryBackend, hive: Hive, class: &str) -> Option<String> {
+    let (hive, path) = find_classes_key(reg, hive, class);
+
+    reg.get_string(hive, &path, "")
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+// Explorer uses the newest version a ProgID points to with CurVer
+fn follow_cur_ver(reg: &dyn RegistryBackend, hive: Hive, prog_id: String) -> AssociationSource {
+    let newest = (0..MAX_CUR_VER).try_fold(prog_id.clone(), |current, _| {
+        match class_default(reg, hive, &format!("{}\\CurVer", current)) {
+            Some(next) if !next.eq_ignore_ascii_case(&current) => Ok(next),
+            _ => Err(current),
+        }
+    });
+    let newest = newest.unwrap_or_else(|current| current);
+
+    if newest == prog_id {
+        AssociationSource::ProgId(prog_id, None)
+    } else {
+        AssociationSource::ProgId(newest, Some(prog_id))
+    }
+}
+
+/// Every key Explorer takes verbs from for the extension `key` is open at: the extension
+/// itself, its ProgID, the user's choice of program and SystemFileAssociations.
+/// Keys other than the extension are only listed when they can be read or written to.
+pub fn resolve_associations(reg: &dyn RegistryBackend, key: &Key) -> Vec<Association> {
+    let extension = key.extension();
+
+    if key.properties != KeyProps::HasExt || !extension.starts_with('.') {
+        return Vec::new();
+    }
+
+    let prog_id = class_default(reg, key.hive, &extension)
+        .map(|prog_id| follow_cur_ver(reg, key.hive, prog_id));
+    let user_choice = reg
+        .get_string(
+            Hive::CurrentUser,
+            &format!("{}\\{}\\UserChoice", FILE_EXTS, extension),
+            "ProgId",
+        )
+        .ok()
+        .filter(|prog_id| !prog_id.is_empty())
+        .map(AssociationSource::UserChoice);
+
+    let others = prog_id
+        .into_iter()
+        .chain(user_choice)
+        .chain([AssociationSource::SystemFileAssociations])
+        .map(|source| {
+            let class = match &source {
+                AssociationSource::ProgId(prog_id, _) | AssociationSource::UserChoice(prog_id) => {
+                    prog_id.clone()
+                }
+                _ => format!("SystemFileAssociations\\{}", extension),
+            };
+
+            Association {
+                key: Key {
+                    name: format!("{} ({})", key.name, source),
+                    ..key.for_class(class)
+                },
+                source,
+            }
+        })
+        .filter(|association| {
+            association.key.hive != Hive::ClassesRoot
+                || reg.key_exists(Hive::ClassesRoot, &key_path_string(&association.key.path))
+        });
+
+    std::iter::once(Association {
+        source: AssociationSource::Extension,
+        key: key.clone(),
+    })
+    .chain(others)
+    .fold(Vec::new(), |mut associations, association| {
+        // The user's choice is often the ProgID as well
+        let path = key_path_string(&association.key.path);
+
+        if !associations
+            .iter()
+            .any(|other: &Association| key_path_string(&other.key.path).eq_ignore_ascii_case(&path))
+        {
+            associations.push(association);
+        }
+
+        associations
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    #[test]
+    fn extensions_resolve_through_prog_id_user_choice_and_system_associations() {
+        let reg = MemoryRegistry::new();
+        let key = Key::new("File")
+            .with_path("*/shell")
+            .with_props("HasExt")
+            .with_extension(".txt");
+
+        [
+            ".txt\\shell",
+            "txtfile\\CurVer",
+            "txtfile.2\\shell",
+            "Applications\\notepad.exe\\shell",
+            "SystemFileAssociations\\.txt\\shell",
+        ]
+        .iter()
+        .for_each(|path| reg.create_key(Hive::ClassesRoot, path).unwrap());
+        reg.set_string(Hive::ClassesRoot, ".txt", "", "txtfile")
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, "txtfile\\CurVer", "", "txtfile.2")
+            .unwrap();
+
+        let user_choice = format!("{}\\.txt\\UserChoice", FILE_EXTS);
+        reg.create_key(Hive::CurrentUser, &user_choice).unwrap();
+        reg.set_string(
+            Hive::CurrentUser,
+            &user_choice,
+            "ProgId",
+            "Applications\\notepad.exe",
+        )
+        .unwrap();
+
+        let associations = resolve_associations(&reg, &key);
+        let found = associations
+            .iter()
+            .map(|association| {
+                (
+                    association.source.to_string(),
+                    key_path_string(&association.key.path),
+                )
+            })
+            .collect::<Vec<(String, String)>>();
+
+        assert_eq!(
+            found,
+            [
+                ("Extension".to_string(), ".txt\\shell".to_string()),
+                (
+                    "ProgID txtfile.2 (CurVer of txtfile)".to_string(),
+                    "txtfile.2\\shell".to_string()
+                ),
+                (
+                    "UserChoice Applications\\notepad.exe".to_string(),
+                    "Applications\\notepad.exe\\shell".to_string()
+                ),
+                (
+                    "SystemFileAssociations".to_string(),
+                    "SystemFileAssociations\\.txt\\shell".to_string()
+                ),
+            ]
+        );
+        assert_eq!(associations[1].key.properties, KeyProps::None);
+    }
+}
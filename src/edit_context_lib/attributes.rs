@@ -0,0 +1,447 @@
+use std::fmt::Display;
+
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegData, RegDataType, RegistryBackend};
+
+/// Where Explorer puts the verb in the menu
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Top,
+    Bottom,
+}
+
+impl Position {
+    pub const ALL: [Position; 2] = [Self::Top, Self::Bottom];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "top" => Some(Self::Top),
+            "bottom" => Some(Self::Bottom),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Top => write!(f, "Top"),
+            Self::Bottom => write!(f, "Bottom"),
+        }
+    }
+}
+
+/// How a verb is invoked when several files are selected
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MultiSelectModel {
+    // Only shown when one item is selected
+    Single,
+    // One invocation with every item
+    Player,
+    // One invocation per item
+    Document,
+}
+
+impl MultiSelectModel {
+    pub const ALL: [MultiSelectModel; 3] = [Self::Single, Self::Player, Self::Document];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "single" => Some(Self::Single),
+            "player" => Some(Self::Player),
+            "document" => Some(Self::Document),
+            _ => None,
+        }
+    }
+}
+
+impl Display for MultiSelectModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Single => write!(f, "Single"),
+            Self::Player => write!(f, "Player"),
+            Self::Document => write!(f, "Document"),
+        }
+    }
+}
+
+const LEGACY_DISABLE: &str = "LegacyDisable";
+const EXTENDED: &str = "Extended";
+pub const POSITION: &str = "Position";
+const SEPARATOR_BEFORE: &str = "SeparatorBefore";
+const SEPARATOR_AFTER: &str = "SeparatorAfter";
+pub const MUI_VERB: &str = "MUIVerb";
+const NEVER_DEFAULT: &str = "NeverDefault";
+const HAS_LUA_SHIELD: &str = "HasLUAShield";
+const PROGRAMMATIC_ACCESS_ONLY: &str = "ProgrammaticAccessOnly";
+pub const MULTI_SELECT_MODEL: &str = "MultiSelectModel";
+pub const APPLIES_TO: &str = "AppliesTo";
+pub const COMMAND_FLAGS: &str = "CommandFlags";
+pub const HIDE_BASED_ON_VELOCITY_ID: &str = "HideBasedOnVelocityId";
+
+/// The values on a verb's key that Explorer honours besides the title and icon.
+/// Flags are set by the value being there at all, whatever it holds.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VerbAttributes {
+    // Hidden from the menu, the verb is kept as it is otherwise
+    pub disabled: bool,
+    // Only shown with Shift held
+    pub extended: bool,
+    pub position: Option<Position>,
+    pub separator_before: bool,
+    pub separator_after: bool,
+    // Shown instead of the default value, can be an indirect string such as @shell32.dll,-8506
+    pub mui_verb: Option<String>,
+    pub never_default: bool,
+    pub has_lua_shield: bool,
+    // Hidden from the menu, only callable by programs
+    pub programmatic_access_only: bool,
+    pub multi_select_model: Option<MultiSelectModel>,
+    // An Advanced Query Syntax condition, such as System.FileName:"*.txt"
+    pub applies_to: Option<String>,
+    pub command_flags: Option<u32>,
+    pub hide_based_on_velocity_id: Option<u32>,
+    // Values of the fields above that couldn't be parsed, written back as they were until
+    // the field is changed
+    pub unparsed: Vec<(String, RegData)>,
+}
+
+pub(super) fn read_string(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    name: &str,
+) -> Option<String> {
+    reg.get_value(hive, path, name)
+        .ok()
+        .and_then(|data| data.as_string())
+}
+
+fn read_dword(reg: &dyn RegistryBackend, hive: Hive, path: &str, name: &str) -> Option<u32> {
+    reg.get_value(hive, path, name)
+        .ok()
+        .and_then(|data| data.as_dword())
+}
+
+// Writes the value, or removes it when there's nothing to write. A value that already holds
+// the same is left alone, and a REG_EXPAND_SZ string stays one so its variables still expand.
+pub(super) fn write_value(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    name: &str,
+    value: Option<RegData>,
+) -> NormalResult {
+    match value {
+        Some(data) => {
+            let current = reg.get_value(hive, path, name).ok();
+            let data = match (&current, data.as_string()) {
+                (Some(current), Some(text))
+                    if data.vtype == RegDataType::Sz && current.vtype == RegDataType::ExpandSz =>
+                {
+                    RegData::expand_sz(text)
+                }
+                _ => data,
+            };
+
+            if current.as_ref() == Some(&data) {
+                Ok(())
+            } else {
+                reg.set_value(hive, path, name, &data)
+            }
+        }
+        None if reg.get_value(hive, path, name).is_ok() => reg.delete_value(hive, path, name),
+        None => Ok(()),
+    }
+}
+
+// Flags that are already there keep whatever they hold
+fn write_flag(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    name: &str,
+    set: bool,
+) -> NormalResult {
+    match set && reg.get_value(hive, path, name).is_ok() {
+        true => Ok(()),
+        false => write_value(reg, hive, path, name, set.then(|| RegData::sz(""))),
+    }
+}
+
+/// Hides or shows the verb at `path` with LegacyDisable, nothing else is written
+pub fn write_disabled(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    disabled: bool,
+) -> NormalResult {
+    write_flag(reg, hive, path, LEGACY_DISABLE, disabled)
+}
+
+/// Sets or removes Position on the verb at `path`, nothing else is written
+pub fn write_position(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    position: Option<Position>,
+) -> NormalResult {
+    write_value(
+        reg,
+        hive,
+        path,
+        POSITION,
+        position.map(|position| RegData::sz(position.to_string())),
+    )
+}
+
+// The parsed value, or None with the raw value kept when the value is there but can't be parsed
+fn keep_unparsed<T>(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    name: &str,
+    parsed: Option<T>,
+    unparsed: &mut Vec<(String, RegData)>,
+) -> Option<T> {
+    if parsed.is_none()
+        && let Ok(raw) = reg.get_value(hive, path, name)
+    {
+        unparsed.push((name.to_string(), raw));
+    }
+
+    parsed
+}
+
+impl VerbAttributes {
+    pub fn read(reg: &dyn RegistryBackend, hive: Hive, path: &str) -> Self {
+        let has = |name: &str| reg.get_value(hive, path, name).is_ok();
+        let mut unparsed = Vec::new();
+
+        let position = keep_unparsed(
+            reg,
+            hive,
+            path,
+            POSITION,
+            read_string(reg, hive, path, POSITION).and_then(|name| Position::from_name(&name)),
+            &mut unparsed,
+        );
+        let mui_verb = keep_unparsed(
+            reg,
+            hive,
+            path,
+            MUI_VERB,
+            read_string(reg, hive, path, MUI_VERB),
+            &mut unparsed,
+        );
+        let multi_select_model = keep_unparsed(
+            reg,
+            hive,
+            path,
+            MULTI_SELECT_MODEL,
+            read_string(reg, hive, path, MULTI_SELECT_MODEL)
+                .and_then(|name| MultiSelectModel::from_name(&name)),
+            &mut unparsed,
+        );
+        let applies_to = keep_unparsed(
+            reg,
+            hive,
+            path,
+            APPLIES_TO,
+            read_string(reg, hive, path, APPLIES_TO),
+            &mut unparsed,
+        );
+        let command_flags = keep_unparsed(
+            reg,
+            hive,
+            path,
+            COMMAND_FLAGS,
+            read_dword(reg, hive, path, COMMAND_FLAGS),
+            &mut unparsed,
+        );
+        let hide_based_on_velocity_id = keep_unparsed(
+            reg,
+            hive,
+            path,
+            HIDE_BASED_ON_VELOCITY_ID,
+            read_dword(reg, hive, path, HIDE_BASED_ON_VELOCITY_ID),
+            &mut unparsed,
+        );
+
+        Self {
+            disabled: has(LEGACY_DISABLE),
+            extended: has(EXTENDED),
+            position,
+            separator_before: has(SEPARATOR_BEFORE),
+            separator_after: has(SEPARATOR_AFTER),
+            mui_verb,
+            never_default: has(NEVER_DEFAULT),
+            has_lua_shield: has(HAS_LUA_SHIELD),
+            programmatic_access_only: has(PROGRAMMATIC_ACCESS_ONLY),
+            multi_select_model,
+            applies_to,
+            command_flags,
+            hide_based_on_velocity_id,
+            unparsed,
+        }
+    }
+
+    /// Writes every attribute that is set and removes the ones that aren't.
+    /// Flags that are already there are left as they are.
+    pub fn write(&self, reg: &dyn RegistryBackend, hive: Hive, path: &str) -> NormalResult {
+        let flags = [
+            (LEGACY_DISABLE, self.disabled),
+            (EXTENDED, self.extended),
+            (SEPARATOR_BEFORE, self.separator_before),
+            (SEPARATOR_AFTER, self.separator_after),
+            (NEVER_DEFAULT, self.never_default),
+            (HAS_LUA_SHIELD, self.has_lua_shield),
+            (PROGRAMMATIC_ACCESS_ONLY, self.programmatic_access_only),
+        ];
+
+        flags
+            .into_iter()
+            .try_for_each(|(name, set)| write_flag(reg, hive, path, name, set))?;
+
+        let strings = [
+            (POSITION, self.position.map(|position| position.to_string())),
+            (MUI_VERB, self.mui_verb.clone()),
+            (
+                MULTI_SELECT_MODEL,
+                self.multi_select_model.map(|model| model.to_string()),
+            ),
+            (APPLIES_TO, self.applies_to.clone()),
+        ];
+
+        strings.into_iter().try_for_each(|(name, value)| {
+            self.write_parsed(reg, hive, path, name, value.map(RegData::sz))
+        })?;
+
+        [
+            (COMMAND_FLAGS, self.command_flags),
+            (HIDE_BASED_ON_VELOCITY_ID, self.hide_based_on_velocity_id),
+        ]
+        .into_iter()
+        .try_for_each(|(name, value)| {
+            self.write_parsed(reg, hive, path, name, value.map(RegData::dword))
+        })
+    }
+
+    /// What the value `name` held when it was read but couldn't be parsed
+    pub fn unparsed(&self, name: &str) -> Option<&RegData> {
+        self.unparsed
+            .iter()
+            .find(|(unparsed, _)| unparsed == name)
+            .map(|(_, raw)| raw)
+    }
+
+    /// Drops the value `name` held when it was read, an unset field then removes it
+    pub fn clear_unparsed(&mut self, name: &str) {
+        self.unparsed.retain(|(unparsed, _)| unparsed != name);
+    }
+
+    // A value that is unset but couldn't be parsed when read is put back as it was, unless
+    // the field has been changed since
+    fn write_parsed(
+        &self,
+        reg: &dyn RegistryBackend,
+        hive: Hive,
+        path: &str,
+        name: &str,
+        value: Option<RegData>,
+    ) -> NormalResult {
+        let raw = self.unparsed(name).cloned();
+
+        write_value(reg, hive, path, name, value.or(raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    const VERB: &str = "Directory\\shell\\Tool";
+
+    #[test]
+    fn attributes_round_trip_and_unset_ones_are_removed() {
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, VERB).unwrap();
+        reg.set_value(Hive::ClassesRoot, VERB, "Extended", &RegData::dword(1))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, VERB, "NeverDefault", "")
+            .unwrap();
+
+        let attributes = VerbAttributes {
+            extended: true,
+            position: Some(Position::Bottom),
+            separator_before: true,
+            mui_verb: Some("@shell32.dll,-8506".to_string()),
+            multi_select_model: Some(MultiSelectModel::Player),
+            applies_to: Some("System.FileName:\"*.txt\"".to_string()),
+            command_flags: Some(0x20),
+            ..VerbAttributes::default()
+        };
+        attributes.write(&reg, Hive::ClassesRoot, VERB).unwrap();
+
+        assert_eq!(
+            VerbAttributes::read(&reg, Hive::ClassesRoot, VERB),
+            attributes
+        );
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, VERB, "Position").unwrap(),
+            "Bottom"
+        );
+        // An existing flag keeps whatever it held
+        assert_eq!(
+            reg.get_value(Hive::ClassesRoot, VERB, "Extended").unwrap(),
+            RegData::dword(1)
+        );
+        assert!(
+            reg.get_value(Hive::ClassesRoot, VERB, "NeverDefault")
+                .is_err()
+        );
+
+        VerbAttributes::default()
+            .write(&reg, Hive::ClassesRoot, VERB)
+            .unwrap();
+        assert!(reg.enum_values(Hive::ClassesRoot, VERB).unwrap().is_empty());
+    }
+
+    #[test]
+    fn values_that_cant_be_parsed_survive_an_edit() {
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, VERB).unwrap();
+        reg.set_string(Hive::ClassesRoot, VERB, "Position", "Middle")
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, VERB, "CommandFlags", "0x20")
+            .unwrap();
+
+        let mut attributes = VerbAttributes::read(&reg, Hive::ClassesRoot, VERB);
+        assert_eq!(attributes.position, None);
+        assert_eq!(attributes.command_flags, None);
+
+        attributes.extended = true;
+        attributes.write(&reg, Hive::ClassesRoot, VERB).unwrap();
+
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, VERB, "Position").unwrap(),
+            "Middle"
+        );
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, VERB, "CommandFlags")
+                .unwrap(),
+            "0x20"
+        );
+        assert!(reg.get_value(Hive::ClassesRoot, VERB, "Extended").is_ok());
+
+        // Clearing the field removes the value
+        attributes.clear_unparsed(POSITION);
+        attributes.write(&reg, Hive::ClassesRoot, VERB).unwrap();
+        assert!(reg.get_value(Hive::ClassesRoot, VERB, "Position").is_err());
+        assert!(
+            reg.get_value(Hive::ClassesRoot, VERB, "CommandFlags")
+                .is_ok()
+        );
+    }
+}
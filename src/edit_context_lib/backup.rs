@@ -1,48 +1,128 @@
 use chrono::Local;
-use log::error;
-use std::{error::Error, rc::Rc};
-use winreg::{HKEY, RegKey, enums::HKEY_CLASSES_ROOT};
+use log::{error, info};
+use std::{
+    cmp::Reverse,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use super::get_resource_path;
-use crate::edit_context_lib::types::{KeyPath, NormalResult};
+use crate::edit_context_lib::types::{Key, NormalResult, key_path_string};
+use crate::registry_io::backend::RegistryBackend;
 use crate::registry_io::writer::RegWriter;
 
 /*fn reg_hexify<'a>(byte: &'a u8) -> String {
     format!("{:02X}", byte).to_lowercase()
 }*/
 
-fn hkey_to_string(hkey: HKEY) -> String {
-    match hkey {
-        HKEY_CLASSES_ROOT => "HKEY_CLASSES_ROOT".to_string(),
-        _ => String::new(),
+/// Where backups are written and how many of them are kept around
+#[derive(Debug, Clone, Default)]
+pub struct BackupOptions {
+    // As written in the config, empty means the resources folder
+    pub dir: String,
+    // 0 keeps every backup
+    pub max_count: usize,
+    // 0 keeps backups forever
+    pub max_age_days: u64,
+}
+
+impl BackupOptions {
+    /// Expands %VARIABLES% and resolves relative paths against the resources folder
+    pub fn dir(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let dir = expand_env_vars(self.dir.trim());
+
+        if dir.is_empty() {
+            get_resource_path()
+        } else if Path::new(&dir).is_absolute() {
+            Ok(PathBuf::from(dir))
+        } else {
+            Ok(get_resource_path()?.join(dir))
+        }
     }
 }
 
+fn expand_env_vars(path: &str) -> String {
+    let parts = path.split('%').collect::<Vec<&str>>();
+
+    parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| {
+            // Every other part sits between two % signs, unless it's the last one
+            if index % 2 == 1 && index + 1 < parts.len() {
+                env::var(part).unwrap_or_else(|_| format!("%{}%", part))
+            } else {
+                part.to_string()
+            }
+        })
+        .collect()
+}
+
 fn current_date_time() -> String {
     // Get the current local date and time
     let now = Local::now();
-    // Format it as YYYY-MM-DD-hh-mm-ss
-    now.format("%Y-%m-%d-%H-%M-%S").to_string()
+    // Format it as YYYY-MM-DD-hh-mm-ss-mmm
+    now.format("%Y-%m-%d-%H-%M-%S-%3f").to_string()
 }
 
-pub fn backup_paths(paths: Rc<[KeyPath]>, hkey: HKEY) -> NormalResult {
-    let writer: RegWriter = paths.iter().fold(
-        Ok(RegWriter::new()),
-        |writer: Result<RegWriter, Box<dyn Error>>, path: &KeyPath| {
-            let regkey = path.borrow().iter().fold(
-                Ok(RegKey::predef(hkey)),
-                |acc: Result<RegKey, Box<dyn Error>>, key: &Box<str>| {
-                    Ok(acc?.open_subkey(key.as_ref())?)
-                },
-            )?;
+// Two backups in the same millisecond get a counter on the end
+fn unique_backup_path(dir: &Path) -> PathBuf {
+    let stamp = current_date_time();
 
-            let path_name = hkey_to_string(hkey) + "\\" + &path.borrow().join("\\");
+    (0..)
+        .map(|count| match count {
+            0 => dir.join(format!("backup-{}.reg", stamp)),
+            _ => dir.join(format!("backup-{}-{}.reg", stamp, count)),
+        })
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| dir.join(format!("backup-{}-{}.reg", stamp, uuid::Uuid::new_v4())))
+}
 
-            writer?.with_all_subkeys(regkey, path_name)
-        },
-    )?;
+fn backup_age(path: &Path) -> Option<Duration> {
+    fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()
+}
+
+fn prune_backups(dir: &Path, options: &BackupOptions) -> NormalResult {
+    let max_age = Duration::from_secs(options.max_age_days * 24 * 60 * 60);
+
+    list_backups(dir)?
+        .iter()
+        .enumerate()
+        // The newest backup is always kept, even if it's past the maximum age
+        .skip(1)
+        .filter(|(index, path)| {
+            (options.max_count > 0 && *index >= options.max_count)
+                || (options.max_age_days > 0 && backup_age(path).is_some_and(|age| age > max_age))
+        })
+        .try_for_each(|(_, path)| {
+            info!("Removing old backup {}", path.display());
+            fs::remove_file(path)
+        })?;
 
-    let resource_path = match get_resource_path() {
+    Ok(())
+}
+
+/// Writes every key to a new backup, each from its own hive. Keys that don't exist are left out.
+pub fn backup_paths(
+    keys: &[Key],
+    reg: &dyn RegistryBackend,
+    options: &BackupOptions,
+) -> NormalResult {
+    let writer: RegWriter = keys
+        .iter()
+        .map(|key| (key.hive, key_path_string(&key.path)))
+        .filter(|(hive, path)| reg.key_exists(*hive, path))
+        .try_fold(
+            RegWriter::new(),
+            |writer: RegWriter, (hive, path)| -> Result<RegWriter, Box<dyn Error>> {
+                writer.with_all_subkeys(reg, hive, path)
+            },
+        )?;
+
+    let backup_dir = match options.dir() {
         Ok(path) => path,
         Err(e) => {
             error!("Unable to backup paths: {}", e);
@@ -50,11 +130,45 @@ pub fn backup_paths(paths: Rc<[KeyPath]>, hkey: HKEY) -> NormalResult {
         }
     };
 
-    let file_path = resource_path.join(format!("backup-{}.reg", current_date_time()));
+    fs::create_dir_all(&backup_dir)?;
 
-    writer.write_to(file_path)?;
+    let newest = list_backups(&backup_dir)?.into_iter().next();
 
-    Ok(())
+    if newest.is_some_and(|newest| writer.same_as_file(newest)) {
+        info!("Nothing has changed since the last backup, skipping");
+    } else {
+        writer.write_to(unique_backup_path(&backup_dir))?;
+    }
+
+    prune_backups(&backup_dir, options)
+}
+
+/// Every backup in the folder, newest first
+pub fn list_backups(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("backup-") && name.ends_with(".reg"))
+        })
+        .collect::<Vec<PathBuf>>();
+
+    backups.sort_by_key(|path| Reverse(backup_order(path)));
+
+    Ok(backups)
+}
+
+// The numbers in a backup's name, the timestamp and then the counter a backup gets when it
+// collides with another. Compared as numbers `-10` comes after `-9`, and a backup without
+// a counter before the ones that collided with it.
+fn backup_order(path: &Path) -> Vec<u64> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .split('-')
+        .filter_map(|part| part.parse().ok())
+        .collect()
 }
 //fn regashii_to_winreg(value: Value) -> Option<Value> {}
 
@@ -157,3 +271,85 @@ fn save_all_in_key(regkey: RegKey, path: String) -> Result<String, Box<dyn Error
 
     Ok(result)
 }*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::backend::Hive;
+    use crate::registry_io::memory::MemoryRegistry;
+    use crate::registry_io::reader::RegReader;
+
+    #[test]
+    fn skips_identical_backups_and_keeps_the_newest() {
+        let dir = env::temp_dir().join(format!("context-editor-backups-{}", uuid::Uuid::new_v4()));
+        let options = BackupOptions {
+            dir: dir.to_string_lossy().to_string(),
+            max_count: 2,
+            max_age_days: 0,
+        };
+        let keys = [Key::new("Directory").with_path("Directory/shell")];
+
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\Tool")
+            .unwrap();
+
+        let backup = |title: &str| -> Result<Vec<PathBuf>, Box<dyn Error>> {
+            reg.set_string(Hive::ClassesRoot, "Directory\\shell\\Tool", "", title)?;
+            backup_paths(&keys, &reg, &options)?;
+            list_backups(&dir)
+        };
+        let title = |path: &PathBuf| -> Result<Option<String>, Box<dyn Error>> {
+            Ok(RegReader::try_read_file(path)?.verbs()[0].title.clone())
+        };
+
+        // How many backups each of the first three leaves, then the titles the last keeps
+        type Outcome = (Vec<usize>, Vec<Option<String>>);
+        let run = || -> Result<Outcome, Box<dyn Error>> {
+            let counts = ["First", "First", "Second"]
+                .into_iter()
+                .map(|name| Ok(backup(name)?.len()))
+                .collect::<Result<Vec<usize>, Box<dyn Error>>>()?;
+            let titles = backup("Third")?
+                .iter()
+                .map(title)
+                .collect::<Result<Vec<Option<String>>, Box<dyn Error>>>()?;
+
+            Ok((counts, titles))
+        };
+
+        // Removed before anything can fail, so no files are left behind
+        let result = run();
+        let _ = fs::remove_dir_all(&dir);
+        let (counts, titles) = result.unwrap();
+
+        assert_eq!(counts, [1, 1, 2]);
+
+        // Only the two newest are kept, newest first
+        assert_eq!(
+            titles,
+            [Some("Third".to_string()), Some("Second".to_string())]
+        );
+    }
+
+    #[test]
+    fn backups_that_collided_sort_by_their_counter() {
+        let dir = env::temp_dir().join(format!("context-editor-backups-{}", uuid::Uuid::new_v4()));
+        let stamp = "backup-2026-10-18-10-50-22-123";
+        let name = |counter: &str| format!("{}{}.reg", stamp, counter);
+
+        let listed = fs::create_dir_all(&dir)
+            .and_then(|_| {
+                ["", "-1", "-9", "-10"]
+                    .into_iter()
+                    .try_for_each(|counter| fs::write(dir.join(name(counter)), ""))
+            })
+            .map_err(Into::into)
+            .and_then(|_| list_backups(&dir));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(
+            listed.unwrap(),
+            ["-10", "-9", "-1", ""].map(|counter| dir.join(name(counter)))
+        );
+    }
+}
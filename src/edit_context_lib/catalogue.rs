@@ -0,0 +1,189 @@
+use crate::edit_context_lib::scope::classes_path;
+use crate::edit_context_lib::types::{Key, KeyProps};
+use crate::registry_io::backend::Hive;
+
+// What the command line placeholders stand for, see `KnownLocation::placeholders`.
+// The first is the one a new command is given.
+const ITEM: [&str; 6] = ["%1", "%L", "%V", "%W", "%*", "%D"];
+const BACKGROUND: [&str; 2] = ["%V", "%W"];
+const NETWORK: [&str; 4] = ["%1", "%L", "%V", "%D"];
+
+/// A key Explorer reads verbs from, known to the app whether or not the config lists it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnownLocation {
+    pub name: &'static str,
+    // In the config's format, relative to the classes root
+    pub path: &'static str,
+    // As `[RegProps]` would give them
+    pub props: &'static str,
+    // When Explorer shows the verbs here
+    pub shown: &'static str,
+    // The placeholders that are given a value when a verb here is run
+    pub placeholders: &'static [&'static str],
+}
+
+/// Every location the app knows of, in the order they're listed
+pub const LOCATIONS: [KnownLocation; 12] = [
+    KnownLocation {
+        name: "Background",
+        path: "Directory/Background/shell",
+        props: "",
+        shown: "Right-clicking the empty space of an open folder, and of the desktop",
+        placeholders: &BACKGROUND,
+    },
+    KnownLocation {
+        name: "Directory",
+        path: "Directory/shell",
+        props: "",
+        shown: "Right-clicking a folder on disk",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "File",
+        path: "*/shell",
+        props: "HasExt",
+        shown: "Right-clicking a file, every file or those with the extension typed in",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "Perceived",
+        path: "SystemFileAssociations/image/shell",
+        props: "PerceivedType",
+        shown: "Right-clicking a file whose extension has the perceived type picked",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "Drive",
+        path: "Drive/shell",
+        props: "",
+        shown: "Right-clicking a drive in This PC or the navigation pane",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "Folder",
+        path: "Folder/shell",
+        props: "",
+        shown: "Right-clicking any folder, including virtual ones such as This PC and libraries",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "AllFilesystemObjects",
+        path: "AllFilesystemObjects/shell",
+        props: "",
+        shown: "Right-clicking any file or folder on disk",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "DesktopBackground",
+        path: "DesktopBackground/Shell",
+        props: "",
+        shown: "Right-clicking the empty space of the desktop only",
+        placeholders: &BACKGROUND,
+    },
+    KnownLocation {
+        name: "LibraryBackground",
+        path: "LibraryFolder/background/shell",
+        props: "",
+        shown: "Right-clicking the empty space of an open library",
+        placeholders: &BACKGROUND,
+    },
+    KnownLocation {
+        name: "Library",
+        path: "LibraryFolder/shell",
+        props: "",
+        shown: "Right-clicking a library",
+        placeholders: &ITEM,
+    },
+    KnownLocation {
+        name: "Network",
+        path: "Network/shell",
+        props: "",
+        shown: "Right-clicking a computer or device in the Network folder",
+        placeholders: &NETWORK,
+    },
+    KnownLocation {
+        name: "NetShare",
+        path: "NetShare/shell",
+        props: "",
+        shown: "Right-clicking a folder shared by another computer on the network",
+        placeholders: &NETWORK,
+    },
+];
+
+/// Used when the config doesn't say which known locations are tabs
+pub const DEFAULT_PINNED: [&str; 3] = ["Background", "Directory", "File"];
+
+impl KnownLocation {
+    pub fn key(&self) -> Key {
+        Key::new(self.name)
+            .with_path(self.path)
+            .with_props(self.props)
+    }
+}
+
+pub fn find_location(name: &str) -> Option<&'static KnownLocation> {
+    LOCATIONS
+        .iter()
+        .find(|location| location.name.eq_ignore_ascii_case(name))
+}
+
+/// The known location a verb location is at in any hive. The part of an extension or
+/// perceived type location that is picked matches anything.
+pub fn location_of(hive: Hive, path: &str) -> Option<&'static KnownLocation> {
+    let parts = classes_path(hive, path)?.split('\\').collect::<Vec<&str>>();
+
+    LOCATIONS.iter().find(|location| {
+        // Which part is picked, and what it has to look like
+        let picked = |index: usize, part: &str| match location.key().properties {
+            KeyProps::None => false,
+            KeyProps::HasExt => index == 0 && (part == "*" || part.starts_with('.')),
+            KeyProps::PerceivedType => index == 1 && !part.starts_with('.'),
+        };
+        let known = location.path.split('/').collect::<Vec<&str>>();
+
+        known.len() == parts.len()
+            && known
+                .iter()
+                .zip(&parts)
+                .enumerate()
+                .all(|(index, (known, part))| {
+                    picked(index, part) || known.eq_ignore_ascii_case(part)
+                })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_are_found_in_any_hive_whatever_is_picked() {
+        let find = |hive, path| location_of(hive, path).map(|location| location.name);
+
+        assert_eq!(find(Hive::ClassesRoot, "Drive\\shell"), Some("Drive"));
+        assert_eq!(
+            find(Hive::CurrentUser, "Software\\Classes\\.txt\\shell"),
+            Some("File")
+        );
+        assert_eq!(
+            find(
+                Hive::LocalMachine,
+                "Software\\Classes\\SystemFileAssociations\\video\\shell"
+            ),
+            Some("Perceived")
+        );
+        assert_eq!(find(Hive::ClassesRoot, "txtfile\\shell"), None);
+        assert_eq!(find(Hive::CurrentUser, "Drive\\shell"), None);
+
+        assert!(
+            LOCATIONS
+                .iter()
+                .all(|location| find_location(location.name).is_some())
+        );
+        assert!(
+            DEFAULT_PINNED
+                .iter()
+                .all(|name| find_location(name).is_some())
+        );
+    }
+}
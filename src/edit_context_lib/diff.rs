@@ -0,0 +1,367 @@
+use std::{collections::BTreeMap, error::Error, fmt::Display};
+
+use serde::Serialize;
+
+use crate::edit_context_lib::scope::classes_path;
+use crate::edit_context_lib::types::{Key, key_path_string};
+use crate::registry_io::backend::RegistryBackend;
+use crate::registry_io::reader::{RegReader, RegVerb};
+use crate::registry_io::tree::RegTree;
+use crate::registry_io::writer::RegWriter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One key or value inside a verb that differs between the two sides
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueDiff {
+    pub kind: DiffKind,
+    // Readable name such as "Command", or the subkey and value name for anything else
+    pub field: String,
+    // Relative to the verb, empty for the verb's own key
+    pub key: String,
+    // None when the line is about the key itself
+    pub value: Option<String>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Display for ValueDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {}", self.kind, self.field)?;
+
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, ": {} -> {}", old, new),
+            (None, Some(new)) => write!(f, ": {}", new),
+            (Some(old), None) => write!(f, ": was {}", old),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerbDiff {
+    pub kind: DiffKind,
+    pub location: String,
+    pub verb: String,
+    pub title: Option<String>,
+    pub changes: Vec<ValueDiff>,
+}
+
+impl Display for VerbDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {}\\{}", self.kind, self.location, self.verb)?;
+
+        if let Some(title) = &self.title {
+            write!(f, " \"{}\"", title)?;
+        }
+
+        self.changes
+            .iter()
+            .try_for_each(|change| write!(f, "\n    {}", change))
+    }
+}
+
+fn field_name(key: &str, value: Option<&str>) -> String {
+    let lower_value = value.map(str::to_lowercase);
+
+    match (key.to_lowercase().as_str(), lower_value.as_deref()) {
+        ("", Some("")) => "Title".to_string(),
+        ("", Some("icon")) => "Icon".to_string(),
+        ("command", Some("")) => "Command".to_string(),
+        (_, None) => format!("Key {}", key),
+        ("", Some(_)) => value.unwrap_or_default().to_string(),
+        (_, Some("")) => format!("{}\\(Default)", key),
+        (_, Some(_)) => format!("{}\\{}", key, value.unwrap_or_default()),
+    }
+}
+
+// (key, value name) -> data, keys themselves are listed with no value name and no data.
+// The map is keyed in lowercase since the registry ignores case.
+type Flat = BTreeMap<(String, Option<String>), (String, Option<String>, Option<String>)>;
+
+fn flatten(tree: &RegTree, key: &str, output: &mut Flat) {
+    output.insert((key.to_lowercase(), None), (key.to_string(), None, None));
+
+    tree.values.iter().for_each(|(name, data)| {
+        output.insert(
+            (key.to_lowercase(), Some(name.to_lowercase())),
+            (key.to_string(), Some(name.clone()), Some(data.to_string())),
+        );
+    });
+
+    tree.subkeys.iter().for_each(|(name, sub)| {
+        let subkey = if key.is_empty() {
+            name.clone()
+        } else {
+            format!("{}\\{}", key, name)
+        };
+
+        flatten(sub, &subkey, output)
+    });
+}
+
+fn value_diff(
+    kind: DiffKind,
+    (key, value, _): &(String, Option<String>, Option<String>),
+    old: Option<String>,
+    new: Option<String>,
+) -> ValueDiff {
+    ValueDiff {
+        kind,
+        field: field_name(key, value.as_deref()),
+        key: key.clone(),
+        value: value.clone(),
+        old,
+        new,
+    }
+}
+
+fn diff_trees(old: &RegTree, new: &RegTree) -> Vec<ValueDiff> {
+    let mut old_flat = Flat::new();
+    let mut new_flat = Flat::new();
+    flatten(old, "", &mut old_flat);
+    flatten(new, "", &mut new_flat);
+
+    let changed = new_flat
+        .iter()
+        .filter_map(|(id, entry)| match old_flat.get(id) {
+            None => Some(value_diff(DiffKind::Added, entry, None, entry.2.clone())),
+            Some(old_entry) if old_entry.2 != entry.2 => Some(value_diff(
+                DiffKind::Modified,
+                entry,
+                old_entry.2.clone(),
+                entry.2.clone(),
+            )),
+            _ => None,
+        });
+
+    let removed = old_flat
+        .iter()
+        .filter(|(id, _)| !new_flat.contains_key(*id))
+        .map(|(_, entry)| value_diff(DiffKind::Removed, entry, entry.2.clone(), None));
+
+    changed
+        .chain(removed)
+        // The verb's own key is already covered by the verb being added or removed
+        .filter(|change| !(change.key.is_empty() && change.value.is_none()))
+        .collect()
+}
+
+// The same verb read from HKEY_CLASSES_ROOT or from a hive's Software\Classes gets one id,
+// so a backup can be compared with the live registry whichever scope it's read through
+fn verb_id(verb: &RegVerb) -> String {
+    let path = verb.path();
+
+    classes_path(verb.hive, &path)
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| format!("{}\\{}", verb.hive, path.to_lowercase()))
+}
+
+fn verb_diff(kind: DiffKind, verb: &RegVerb, changes: Vec<ValueDiff>) -> VerbDiff {
+    VerbDiff {
+        kind,
+        location: format!("{}\\{}", verb.hive, verb.location),
+        verb: verb.name.clone(),
+        title: verb.title.clone(),
+        changes,
+    }
+}
+
+fn verb_tree(reader: &RegReader, verb: &RegVerb) -> Result<RegTree, Box<dyn Error>> {
+    reader.tree(verb.hive, &verb.path())
+}
+
+/// Every verb that was added, removed or changed going from `old` to `new`.
+/// Values are compared by what they hold, so the order keys were written in doesn't matter.
+pub fn diff_registries(old: &RegReader, new: &RegReader) -> Result<Vec<VerbDiff>, Box<dyn Error>> {
+    let old_verbs = old
+        .verbs()
+        .into_iter()
+        .map(|verb| (verb_id(&verb), verb))
+        .collect::<BTreeMap<_, _>>();
+    let new_verbs = new
+        .verbs()
+        .into_iter()
+        .map(|verb| (verb_id(&verb), verb))
+        .collect::<BTreeMap<_, _>>();
+
+    let empty = RegTree::default();
+
+    let mut diffs = new_verbs
+        .iter()
+        .filter_map(|(id, verb)| {
+            let new_tree = match verb_tree(new, verb) {
+                Ok(tree) => tree,
+                Err(err) => return Some(Err(err)),
+            };
+
+            match old_verbs.get(id) {
+                None => Some(Ok(verb_diff(
+                    DiffKind::Added,
+                    verb,
+                    diff_trees(&empty, &new_tree),
+                ))),
+                Some(old_verb) => {
+                    let changes = match verb_tree(old, old_verb) {
+                        Ok(old_tree) => diff_trees(&old_tree, &new_tree),
+                        Err(err) => return Some(Err(err)),
+                    };
+
+                    if changes.is_empty() {
+                        None
+                    } else {
+                        Some(Ok(verb_diff(DiffKind::Modified, verb, changes)))
+                    }
+                }
+            }
+        })
+        .collect::<Result<Vec<VerbDiff>, Box<dyn Error>>>()?;
+
+    diffs.extend(
+        old_verbs
+            .iter()
+            .filter(|(id, _)| !new_verbs.contains_key(*id))
+            .map(|(_, verb)| {
+                let old_tree = verb_tree(old, verb)?;
+                Ok(verb_diff(
+                    DiffKind::Removed,
+                    verb,
+                    diff_trees(&old_tree, &empty),
+                ))
+            })
+            .collect::<Result<Vec<VerbDiff>, Box<dyn Error>>>()?,
+    );
+
+    diffs.sort_by(|a, b| (&a.location, &a.verb).cmp(&(&b.location, &b.verb)));
+
+    Ok(diffs)
+}
+
+/// Reads the given locations the same way a backup would, so they can be compared against one
+pub fn snapshot(keys: &[Key], reg: &dyn RegistryBackend) -> Result<RegReader, Box<dyn Error>> {
+    let writer = keys
+        .iter()
+        .map(|key| (key.hive, key_path_string(&key.path)))
+        .filter(|(hive, path)| reg.key_exists(*hive, path))
+        .try_fold(RegWriter::new(), |writer, (hive, path)| {
+            writer.with_all_subkeys(reg, hive, path)
+        })?;
+
+    Ok(RegReader::from_registry(writer.into_registry()))
+}
+
+pub fn diff_to_text(diffs: &[VerbDiff]) -> String {
+    if diffs.is_empty() {
+        return "No differences".to_string();
+    }
+
+    diffs
+        .iter()
+        .map(VerbDiff::to_string)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn diff_to_json(diffs: &[VerbDiff]) -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string_pretty(diffs)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::backend::Hive;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    fn add_verb(reg: &MemoryRegistry, name: &str, command: &str) {
+        let path = format!("Directory\\shell\\{}", name);
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", path))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, &path, "", name).unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("{}\\command", path),
+            "",
+            command,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn reports_added_removed_and_modified_verbs() {
+        let keys = [Key::new("Directory").with_path("Directory/shell")];
+        let reg = MemoryRegistry::new();
+        add_verb(&reg, "Keep", "keep.exe");
+        add_verb(&reg, "Change", "old.exe");
+        add_verb(&reg, "Gone", "gone.exe");
+
+        let before = snapshot(&keys, &reg).unwrap();
+
+        reg.delete_key_all(Hive::ClassesRoot, "Directory\\shell\\Gone")
+            .unwrap();
+        add_verb(&reg, "Change", "new.exe");
+        add_verb(&reg, "New", "new.exe");
+
+        let after = snapshot(&keys, &reg).unwrap();
+        let diffs = diff_registries(&before, &after).unwrap();
+
+        let kinds = diffs
+            .iter()
+            .map(|diff| (diff.verb.as_str(), diff.kind))
+            .collect::<Vec<(&str, DiffKind)>>();
+        assert_eq!(
+            kinds,
+            [
+                ("Change", DiffKind::Modified),
+                ("Gone", DiffKind::Removed),
+                ("New", DiffKind::Added),
+            ]
+        );
+
+        assert_eq!(
+            diffs[0].changes,
+            [ValueDiff {
+                kind: DiffKind::Modified,
+                field: "Command".to_string(),
+                key: "command".to_string(),
+                value: Some("".to_string()),
+                old: Some("\"old.exe\"".to_string()),
+                new: Some("\"new.exe\"".to_string()),
+            }]
+        );
+
+        assert!(diff_registries(&after, &after).unwrap().is_empty());
+    }
+
+    #[test]
+    fn the_same_verb_matches_across_hives() {
+        let reg = MemoryRegistry::new();
+        add_verb(&reg, "Keep", "keep.exe");
+        let backup = snapshot(&[Key::new("Directory").with_path("Directory/shell")], &reg).unwrap();
+
+        let path = "Software\\Classes\\Directory\\shell\\Keep";
+        reg.create_key(Hive::CurrentUser, &format!("{}\\command", path))
+            .unwrap();
+        reg.set_string(Hive::CurrentUser, path, "", "Keep").unwrap();
+        reg.set_string(
+            Hive::CurrentUser,
+            &format!("{}\\command", path),
+            "",
+            "keep.exe",
+        )
+        .unwrap();
+        let live = snapshot(
+            &[Key::new("Directory").with_path("HKCU/Software/Classes/Directory/shell")],
+            &reg,
+        )
+        .unwrap();
+
+        assert!(diff_registries(&backup, &live).unwrap().is_empty());
+    }
+}
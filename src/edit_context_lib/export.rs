@@ -0,0 +1,114 @@
+use std::{error::Error, fmt::Display};
+
+use crate::edit_context_lib::scope::classes_path;
+use crate::edit_context_lib::types::{StdCommand, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::writer::RegWriter;
+
+/// Where an exported snippet puts its verbs when it's imported on another machine
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExportTarget {
+    // Every user, importing needs admin rights
+    #[default]
+    ClassesRoot,
+    // Only whoever imports it, no admin rights needed
+    CurrentUserClasses,
+}
+
+impl ExportTarget {
+    fn key_name(self, path: &str) -> String {
+        match self {
+            Self::ClassesRoot => format!("{}\\{}", Hive::ClassesRoot, path),
+            Self::CurrentUserClasses => {
+                format!("{}\\Software\\Classes\\{}", Hive::CurrentUser, path)
+            }
+        }
+    }
+}
+
+impl Display for ExportTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ClassesRoot => write!(f, "HKEY_CLASSES_ROOT (all users)"),
+            Self::CurrentUserClasses => {
+                write!(f, "HKEY_CURRENT_USER\\Software\\Classes (current user)")
+            }
+        }
+    }
+}
+
+/// Builds a .reg snippet holding only the given verbs and their subkeys, rewritten to the target hive
+pub fn export_verbs(
+    commands: &[StdCommand],
+    reg: &dyn RegistryBackend,
+    target: ExportTarget,
+) -> Result<RegWriter, Box<dyn Error>> {
+    commands.iter().try_fold(RegWriter::new(), |writer, cmd| {
+        let path = format!("{}\\{}", key_path_string(&cmd.path), cmd.folder);
+
+        if !reg.key_exists(cmd.hive, &path) {
+            return Err(
+                format!("\"{}\" hasn't been applied to the registry yet", cmd.title).into(),
+            );
+        }
+
+        let name = match classes_path(cmd.hive, &path) {
+            Some(classes) => target.key_name(classes),
+            None => return Err(format!("{}\\{} is not a class key", cmd.hive, path).into()),
+        };
+
+        writer.with_subtree_as(reg, cmd.hive, path, name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{
+        DIRECTORY_SUBKEY, add_to_context_window, convert_subkey,
+    };
+    use crate::registry_io::memory::MemoryRegistry;
+    use crate::registry_io::reader::RegReader;
+
+    #[test]
+    fn exports_only_selected_verbs_under_target_hive() {
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+
+        let commands = ["Code", "Other"].map(|title| {
+            StdCommand::new(
+                title.to_string(),
+                "".to_string(),
+                format!("{}.exe \"%V\"", title),
+                title.to_string(),
+                convert_subkey(&DIRECTORY_SUBKEY),
+            )
+        });
+        commands
+            .iter()
+            .for_each(|cmd| add_to_context_window(cmd, &reg, Hive::ClassesRoot).unwrap());
+
+        let writer = export_verbs(&commands[..1], &reg, ExportTarget::CurrentUserClasses).unwrap();
+        let snippet = RegReader::from_registry(writer.into_registry());
+
+        let verbs = snippet.verbs();
+        assert_eq!(verbs.len(), 1);
+        assert_eq!(verbs[0].hive, Hive::CurrentUser);
+        assert_eq!(verbs[0].path(), "Software\\Classes\\Directory\\shell\\Code");
+
+        let tree = snippet
+            .tree(
+                Hive::CurrentUser,
+                "Software\\Classes\\Directory\\shell\\Code",
+            )
+            .unwrap();
+        assert_eq!(
+            tree.subkey("command")
+                .and_then(|command| command.value(""))
+                .and_then(|data| data.as_string())
+                .as_deref(),
+            Some("Code.exe \"%V\"")
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::Display;
+
+use crate::edit_context_lib::attributes::read_string;
+use crate::edit_context_lib::scope::find_classes_key;
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+/// How a verb hands its work to a COM object instead of running a command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandlerKind {
+    // command\DelegateExecute
+    DelegateExecute,
+    // DropTarget\Clsid
+    DropTarget,
+    // ExplorerCommandHandler on the verb itself
+    ExplorerCommand,
+    // A key under shellex\ContextMenuHandlers, loaded for every menu at that location
+    ShellExtension,
+}
+
+impl Display for HandlerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DelegateExecute => write!(f, "DelegateExecute"),
+            Self::DropTarget => write!(f, "DropTarget"),
+            Self::ExplorerCommand => write!(f, "ExplorerCommandHandler"),
+            Self::ShellExtension => write!(f, "ContextMenuHandler"),
+        }
+    }
+}
+
+/// The COM object behind a verb, as far as `CLSID\{...}` describes it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComHandler {
+    pub kind: HandlerKind,
+    pub clsid: String,
+    // None when the CLSID isn't registered or has no default value
+    pub name: Option<String>,
+    // The InprocServer32 DLL, or the LocalServer32 executable
+    pub server: Option<String>,
+}
+
+impl ComHandler {
+    pub fn is_registered(&self) -> bool {
+        self.name.is_some() || self.server.is_some()
+    }
+}
+
+impl Display for ComHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            self.kind,
+            self.name.as_deref().unwrap_or(&self.clsid)
+        )?;
+
+        match &self.server {
+            Some(server) => write!(f, " ({})", server),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(super) fn resolve(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    kind: HandlerKind,
+    clsid: String,
+) -> ComHandler {
+    let (hive, class) = find_classes_key(reg, hive, &format!("CLSID\\{}", clsid));
+
+    let name = read_string(reg, hive, &class, "").filter(|name| !name.is_empty());
+    let server = ["InprocServer32", "LocalServer32"]
+        .into_iter()
+        .find_map(|server| read_string(reg, hive, &format!("{}\\{}", class, server), ""))
+        .filter(|server| !server.is_empty());
+
+    ComHandler {
+        kind,
+        clsid,
+        name,
+        server,
+    }
+}
+
+/// The COM handler of the verb at `verb`, None for a verb that runs a command line
+pub fn read_handler(reg: &dyn RegistryBackend, hive: Hive, verb: &str) -> Option<ComHandler> {
+    [
+        (
+            HandlerKind::ExplorerCommand,
+            verb.to_string(),
+            "ExplorerCommandHandler",
+        ),
+        (
+            HandlerKind::DelegateExecute,
+            format!("{}\\command", verb),
+            "DelegateExecute",
+        ),
+        (
+            HandlerKind::DropTarget,
+            format!("{}\\DropTarget", verb),
+            "Clsid",
+        ),
+    ]
+    .into_iter()
+    .find_map(|(kind, key, value)| {
+        read_string(reg, hive, &key, value)
+            .map(|clsid| clsid.trim().to_string())
+            .filter(|clsid| !clsid.is_empty())
+            .map(|clsid| resolve(reg, hive, kind, clsid))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::get_current_context_window;
+    use crate::edit_context_lib::addtocontext::{DIRECTORY_SUBKEY, convert_subkey};
+    use crate::edit_context_lib::problem::VerbProblem;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    const CLSID: &str = "{11111111-2222-3333-4444-555555555555}";
+
+    #[test]
+    fn com_verbs_are_resolved_to_their_server() {
+        let reg = MemoryRegistry::new();
+        let root = Hive::ClassesRoot;
+        let class = format!("Software\\Classes\\CLSID\\{}", CLSID);
+
+        reg.create_key(Hive::LocalMachine, &format!("{}\\InprocServer32", class))
+            .unwrap();
+        reg.set_string(Hive::LocalMachine, &class, "", "Open in Terminal")
+            .unwrap();
+        reg.set_string(
+            Hive::LocalMachine,
+            &format!("{}\\InprocServer32", class),
+            "",
+            "C:\\Windows\\terminal.dll",
+        )
+        .unwrap();
+        // The merged view isn't built by the memory registry
+        reg.create_key(root, &format!("CLSID\\{}\\InprocServer32", CLSID))
+            .unwrap();
+        reg.set_string(root, &format!("CLSID\\{}", CLSID), "", "Open in Terminal")
+            .unwrap();
+        reg.set_string(
+            root,
+            &format!("CLSID\\{}\\InprocServer32", CLSID),
+            "",
+            "C:\\Windows\\terminal.dll",
+        )
+        .unwrap();
+
+        reg.create_key(root, "Directory\\shell\\Terminal\\command")
+            .unwrap();
+        reg.set_string(root, "Directory\\shell\\Terminal", "", "Terminal")
+            .unwrap();
+        reg.set_string(
+            root,
+            "Directory\\shell\\Terminal\\command",
+            "DelegateExecute",
+            CLSID,
+        )
+        .unwrap();
+        reg.create_key(root, "Directory\\shell\\Gone\\DropTarget")
+            .unwrap();
+        reg.set_string(root, "Directory\\shell\\Gone", "", "Gone")
+            .unwrap();
+        reg.set_string(root, "Directory\\shell\\Gone\\DropTarget", "Clsid", "{0}")
+            .unwrap();
+
+        let listed =
+            get_current_context_window(convert_subkey(&DIRECTORY_SUBKEY), &reg, root).unwrap();
+
+        let gone = listed[0].handler.as_ref().unwrap();
+        assert_eq!(gone.kind, HandlerKind::DropTarget);
+        assert!(!gone.is_registered());
+        assert_eq!(
+            listed[0].problems,
+            [
+                VerbProblem::NoIcon,
+                VerbProblem::UnknownClsid("{0}".to_string())
+            ]
+        );
+
+        let terminal = listed[1].handler.as_ref().unwrap();
+        assert_eq!(terminal.kind, HandlerKind::DelegateExecute);
+        assert_eq!(terminal.name.as_deref(), Some("Open in Terminal"));
+        assert_eq!(
+            terminal.server.as_deref(),
+            Some("C:\\Windows\\terminal.dll")
+        );
+        assert_eq!(listed[1].problems, [VerbProblem::NoIcon]);
+
+        // Per-user and machine verbs look in their own classes first
+        let machine = read_handler(
+            &reg,
+            Hive::LocalMachine,
+            "Software\\Classes\\Directory\\shell\\Missing",
+        );
+        assert!(machine.is_none());
+        assert_eq!(
+            resolve(
+                &reg,
+                Hive::LocalMachine,
+                HandlerKind::ExplorerCommand,
+                CLSID.to_string()
+            )
+            .server
+            .as_deref(),
+            Some("C:\\Windows\\terminal.dll")
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::tree::RegTree;
+
+// A key as it was before and after an edit, None when it didn't exist
+#[derive(Debug, Clone)]
+struct KeyState {
+    hive: Hive,
+    path: String,
+    before: Option<RegTree>,
+    after: Option<RegTree>,
+}
+
+fn read_key(reg: &dyn RegistryBackend, hive: Hive, path: &str) -> Option<RegTree> {
+    reg.key_exists(hive, path)
+        .then(|| RegTree::read(reg, hive, path).ok())
+        .flatten()
+}
+
+// Puts the key back exactly as the tree has it, anything else under it is removed
+fn write_key(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    path: &str,
+    tree: &Option<RegTree>,
+) -> NormalResult {
+    if reg.key_exists(hive, path) {
+        reg.delete_key_all(hive, path)?;
+    }
+
+    match tree {
+        Some(tree) => tree.write(reg, hive, path),
+        None => Ok(()),
+    }
+}
+
+/// One edit that was written to the registry, with every key it touched in full
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub label: String,
+    keys: Vec<KeyState>,
+}
+
+impl HistoryEntry {
+    /// Runs `edit` and keeps the whole subtree of each key from before and after it
+    pub fn record<F: FnOnce() -> NormalResult>(
+        label: String,
+        keys: &[(Hive, String)],
+        reg: &dyn RegistryBackend,
+        edit: F,
+    ) -> Result<Self, Box<dyn Error>> {
+        let before = keys
+            .iter()
+            .map(|(hive, path)| read_key(reg, *hive, path))
+            .collect::<Vec<Option<RegTree>>>();
+
+        edit()?;
+
+        let keys = keys
+            .iter()
+            .zip(before)
+            .map(|((hive, path), before)| KeyState {
+                hive: *hive,
+                path: path.clone(),
+                after: read_key(reg, *hive, path),
+                before,
+            })
+            .collect();
+
+        Ok(Self { label, keys })
+    }
+
+    // Writes one side of the edit back in one transaction. Keys that were changed
+    // since by something else are not overwritten.
+    fn put_back(&self, reg: &dyn RegistryBackend, undo: bool) -> NormalResult {
+        let changed = self.keys.iter().find(|key| {
+            let expected = if undo { &key.after } else { &key.before };
+
+            read_key(reg, key.hive, &key.path) != *expected
+        });
+
+        if let Some(key) = changed {
+            return Err(format!(
+                "{}\\{} has been changed since, it was left alone",
+                key.hive, key.path
+            )
+            .into());
+        }
+
+        reg.begin_transaction()?;
+
+        match self.keys.iter().try_for_each(|key| {
+            let tree = if undo { &key.before } else { &key.after };
+
+            write_key(reg, key.hive, &key.path, tree)
+        }) {
+            Ok(()) => reg.commit_transaction(),
+            Err(err) => {
+                reg.rollback_transaction()?;
+                Err(format!("{}, nothing was changed", err).into())
+            }
+        }
+    }
+}
+
+/// Edits that can be undone, and ones that were undone and can be redone
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+}
+
+impl History {
+    /// A new edit makes whatever was undone before it impossible to redo
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.undo.push(entry);
+        self.redo.clear();
+    }
+
+    pub fn next_undo(&self) -> Option<&str> {
+        self.undo.last().map(|entry| entry.label.as_str())
+    }
+
+    pub fn next_redo(&self) -> Option<&str> {
+        self.redo.last().map(|entry| entry.label.as_str())
+    }
+
+    /// Puts back what was there before the last edit, returns what was undone
+    pub fn undo(&mut self, reg: &dyn RegistryBackend) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(entry) = self.undo.pop() else {
+            return Ok(None);
+        };
+
+        if let Err(err) = entry.put_back(reg, true) {
+            self.undo.push(entry);
+            return Err(err);
+        }
+
+        let label = entry.label.clone();
+        self.redo.push(entry);
+        Ok(Some(label))
+    }
+
+    /// Makes the last undone edit again, returns what was redone
+    pub fn redo(&mut self, reg: &dyn RegistryBackend) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(entry) = self.redo.pop() else {
+            return Ok(None);
+        };
+
+        if let Err(err) = entry.put_back(reg, false) {
+            self.redo.push(entry);
+            return Err(err);
+        }
+
+        let label = entry.label.clone();
+        self.undo.push(entry);
+        Ok(Some(label))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::backend::{RegData, RegDataType};
+    use crate::registry_io::memory::MemoryRegistry;
+
+    const VERB: &str = "Directory\\shell\\Tool";
+
+    #[test]
+    fn undo_and_redo_restore_the_whole_subtree() {
+        let reg = MemoryRegistry::new();
+        let odd = RegData {
+            bytes: vec![9, 9],
+            vtype: RegDataType::Unknown(0x20),
+        };
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", VERB))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, VERB, "", "Tool").unwrap();
+        reg.set_value(Hive::ClassesRoot, VERB, "Odd", &odd).unwrap();
+
+        let keys = [(Hive::ClassesRoot, VERB.to_string())];
+        let mut history = History::default();
+
+        history.push(
+            HistoryEntry::record("Remove \"Tool\"".to_string(), &keys, &reg, || {
+                reg.delete_key_all(Hive::ClassesRoot, VERB)
+            })
+            .unwrap(),
+        );
+        assert!(!reg.key_exists(Hive::ClassesRoot, VERB));
+
+        assert_eq!(
+            history.undo(&reg).unwrap().as_deref(),
+            Some("Remove \"Tool\"")
+        );
+        assert_eq!(reg.get_value(Hive::ClassesRoot, VERB, "Odd").unwrap(), odd);
+        assert!(reg.key_exists(Hive::ClassesRoot, &format!("{}\\command", VERB)));
+        assert!(history.undo(&reg).unwrap().is_none());
+
+        history.redo(&reg).unwrap();
+        assert!(!reg.key_exists(Hive::ClassesRoot, VERB));
+
+        // Something else wrote the key after the edit, undo leaves it alone
+        reg.create_key(Hive::ClassesRoot, VERB).unwrap();
+        assert!(history.undo(&reg).is_err());
+        assert_eq!(history.next_undo(), Some("Remove \"Tool\""));
+    }
+}
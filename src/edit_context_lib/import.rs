@@ -0,0 +1,301 @@
+use std::error::Error;
+
+use crate::edit_context_lib::restore::{RestoreTarget, restore};
+use crate::edit_context_lib::scope::classes_path;
+use crate::edit_context_lib::staging::ChangePreview;
+use crate::edit_context_lib::types::{Key, KeyProps, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::reader::{RegReader, RegVerb};
+
+/// What to do with a verb from the file
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportChoice {
+    // Writes the verb as it is, replacing one with the same key
+    Import,
+    Skip,
+    // Writes the verb under a different key name
+    Rename(String),
+}
+
+/// A verb found in the file, `conflict` is set when a key with the same name already exists
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportVerb {
+    pub verb: RegVerb,
+    pub conflict: bool,
+    pub choice: ImportChoice,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportPlan {
+    pub verbs: Vec<ImportVerb>,
+    // Verbs outside of every configured location, these are never imported
+    pub ignored: Vec<RegVerb>,
+}
+
+fn key_matches(key: &Key, location: &str) -> bool {
+    let key_path = key_path_string(&key.path);
+    let Some(key_location) = classes_path(key.hive, &key_path) else {
+        return false;
+    };
+
+    let key_parts = key_location.split('\\').collect::<Vec<&str>>();
+    let parts = location.split('\\').collect::<Vec<&str>>();
+
+    key_parts.len() == parts.len()
+        && key_parts
+            .iter()
+            .zip(parts.iter())
+            .enumerate()
+            .all(|(index, (key_part, part))| {
+                // The first part of an extension key is whatever extension is typed in,
+                // the second of a perceived type key is any perceived type
+                (index == 0 && key.properties == KeyProps::HasExt && part.starts_with('.'))
+                    || (index == 1
+                        && key.properties == KeyProps::PerceivedType
+                        && !part.starts_with('.'))
+                    || key_part.eq_ignore_ascii_case(part)
+            })
+}
+
+// Locations are compared under the classes root, so a file written for one hive
+// still matches the configured locations in another
+fn is_configured(keys: &[Key], verb: &RegVerb) -> bool {
+    classes_path(verb.hive, &verb.location)
+        .is_some_and(|location| keys.iter().any(|key| key_matches(key, location)))
+}
+
+/// The first of `name (2)`, `name (3)`... that isn't taken yet
+pub fn suggest_name(reg: &dyn RegistryBackend, verb: &RegVerb) -> String {
+    (2..)
+        .map(|count| format!("{} ({})", verb.name, count))
+        .find(|name| !reg.key_exists(verb.hive, &format!("{}\\{}", verb.location, name)))
+        .unwrap_or_else(|| format!("{} ({})", verb.name, uuid::Uuid::new_v4()))
+}
+
+/// Sorts the verbs in a file into ones that can be imported, and which of those would
+/// overwrite an existing verb. Conflicts are skipped until a choice is made.
+pub fn plan_import(reader: &RegReader, keys: &[Key], reg: &dyn RegistryBackend) -> ImportPlan {
+    reader
+        .verbs()
+        .into_iter()
+        .fold(ImportPlan::default(), |mut plan, verb| {
+            if !is_configured(keys, &verb) {
+                plan.ignored.push(verb);
+                return plan;
+            }
+
+            let conflict = reg.key_exists(verb.hive, &verb.path());
+
+            plan.verbs.push(ImportVerb {
+                choice: if conflict {
+                    ImportChoice::Skip
+                } else {
+                    ImportChoice::Import
+                },
+                conflict,
+                verb,
+            });
+            plan
+        })
+}
+
+// Where the verb is written to, None when it's skipped
+fn import_path(
+    import: &ImportVerb,
+    reg: &dyn RegistryBackend,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let verb = &import.verb;
+
+    let path = match &import.choice {
+        ImportChoice::Skip => return Ok(None),
+        ImportChoice::Import => verb.path(),
+        ImportChoice::Rename(name) => {
+            let name = name.trim();
+
+            if name.is_empty() || name.contains('\\') {
+                return Err(format!("\"{}\" is not a valid key name", name).into());
+            }
+
+            let path = format!("{}\\{}", verb.location, name);
+
+            if reg.key_exists(verb.hive, &path) {
+                return Err(format!("{}\\{} already exists", verb.hive, path).into());
+            }
+
+            path
+        }
+    };
+
+    Ok(Some(path))
+}
+
+/// The keys importing `verbs` writes to
+pub fn import_keys(verbs: &[ImportVerb], reg: &dyn RegistryBackend) -> Vec<(Hive, String)> {
+    verbs
+        .iter()
+        .filter_map(|import| Some((import.verb.hive, import_path(import, reg).ok()??)))
+        .collect()
+}
+
+fn import_target(
+    reader: &RegReader,
+    import: &ImportVerb,
+    reg: &dyn RegistryBackend,
+) -> Result<Option<RestoreTarget>, Box<dyn Error>> {
+    let verb = &import.verb;
+    let Some(path) = import_path(import, reg)? else {
+        return Ok(None);
+    };
+
+    Ok(Some(RestoreTarget {
+        hive: verb.hive,
+        path,
+        tree: reader.tree(verb.hive, &verb.path())?,
+        replace: true,
+    }))
+}
+
+/// Writes every verb that wasn't skipped in one transaction, then reads them back.
+/// Whatever is returned is a difference between the registry and the file.
+pub fn apply_import(
+    reader: &RegReader,
+    verbs: &[ImportVerb],
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ChangePreview>, Box<dyn Error>> {
+    let targets = verbs
+        .iter()
+        .filter_map(|import| import_target(reader, import, reg).transpose())
+        .collect::<Result<Vec<RestoreTarget>, Box<dyn Error>>>()?;
+
+    restore(targets, reg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::scope::Scope;
+    use crate::registry_io::backend::Hive;
+    use crate::registry_io::memory::MemoryRegistry;
+    use crate::registry_io::writer::RegWriter;
+
+    fn add_verb(reg: &MemoryRegistry, hive: Hive, location: &str, name: &str, command: &str) {
+        let path = format!("{}\\{}", location, name);
+
+        reg.create_key(hive, &format!("{}\\command", path)).unwrap();
+        reg.set_string(hive, &format!("{}\\command", path), "", command)
+            .unwrap();
+    }
+
+    fn command_of(reg: &MemoryRegistry, hive: Hive, path: &str) -> Option<String> {
+        reg.get_string(hive, &format!("{}\\command", path), "").ok()
+    }
+
+    #[test]
+    fn resolves_conflicts_and_ignores_unknown_locations() {
+        let keys = [
+            Key::new("Directory").with_path("Directory/shell"),
+            Key::new("File").with_path("*/shell").with_props("HasExt"),
+        ]
+        .map(|key| key.in_scope(Scope::PerUser));
+
+        let file = MemoryRegistry::new();
+        add_verb(
+            &file,
+            Hive::ClassesRoot,
+            "Directory\\shell",
+            "Edit",
+            "new.exe",
+        );
+        add_verb(
+            &file,
+            Hive::ClassesRoot,
+            ".txt\\shell",
+            "Print",
+            "print.exe",
+        );
+        add_verb(
+            &file,
+            Hive::CurrentUser,
+            "Software\\Classes\\Directory\\shell",
+            "Mine",
+            "mine.exe",
+        );
+        add_verb(
+            &file,
+            Hive::ClassesRoot,
+            "Drive\\shell",
+            "Other",
+            "other.exe",
+        );
+
+        let writer = [
+            (Hive::ClassesRoot, "Directory"),
+            (Hive::ClassesRoot, ".txt"),
+            (Hive::ClassesRoot, "Drive"),
+            (Hive::CurrentUser, "Software"),
+        ]
+        .into_iter()
+        .try_fold(RegWriter::new(), |writer, (hive, path)| {
+            writer.with_all_subkeys(&file, hive, path.to_string())
+        })
+        .unwrap();
+        let reader = RegReader::from_registry(writer.into_registry());
+
+        let reg = MemoryRegistry::new();
+        add_verb(
+            &reg,
+            Hive::ClassesRoot,
+            "Directory\\shell",
+            "Edit",
+            "old.exe",
+        );
+
+        let mut plan = plan_import(&reader, &keys, &reg);
+        assert_eq!(plan.ignored.len(), 1);
+        assert_eq!(plan.ignored[0].name, "Other");
+
+        let conflicts = plan
+            .verbs
+            .iter()
+            .map(|import| (import.verb.name.as_str(), import.conflict))
+            .collect::<Vec<(&str, bool)>>();
+        assert_eq!(
+            conflicts,
+            [("Print", false), ("Edit", true), ("Mine", false)]
+        );
+
+        plan.verbs[1].choice = ImportChoice::Rename(suggest_name(&reg, &plan.verbs[1].verb));
+        assert!(apply_import(&reader, &plan.verbs, &reg).unwrap().is_empty());
+
+        assert_eq!(
+            command_of(&reg, Hive::ClassesRoot, "Directory\\shell\\Edit").as_deref(),
+            Some("old.exe")
+        );
+        assert_eq!(
+            command_of(&reg, Hive::ClassesRoot, "Directory\\shell\\Edit (2)").as_deref(),
+            Some("new.exe")
+        );
+        assert_eq!(
+            command_of(&reg, Hive::ClassesRoot, ".txt\\shell\\Print").as_deref(),
+            Some("print.exe")
+        );
+        assert!(reg.key_exists(
+            Hive::CurrentUser,
+            "Software\\Classes\\Directory\\shell\\Mine"
+        ));
+
+        plan.verbs[1].choice = ImportChoice::Import;
+        assert!(
+            apply_import(&reader, &plan.verbs[1..2], &reg)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            command_of(&reg, Hive::ClassesRoot, "Directory\\shell\\Edit").as_deref(),
+            Some("new.exe")
+        );
+
+        plan.verbs[1].choice = ImportChoice::Rename("Edit (2)".to_string());
+        assert!(apply_import(&reader, &plan.verbs[1..2], &reg).is_err());
+    }
+}
@@ -1,6 +1,25 @@
 pub mod addtocontext;
 pub mod addtopath;
+pub mod association;
+pub mod attributes;
 pub mod backup;
+pub mod catalogue;
+pub mod diff;
+pub mod export;
+pub mod handler;
+pub mod history;
+pub mod import;
+pub mod order;
+pub mod origin;
+pub mod perceived;
+pub mod placeholder;
+pub mod problem;
+pub mod restore;
+pub mod scope;
+pub mod shellex;
+pub mod simulate;
+pub mod staging;
+pub mod submenu;
 pub mod types;
 
 use super::resources::resources::get_resource_path;
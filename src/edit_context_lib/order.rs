@@ -0,0 +1,336 @@
+use crate::edit_context_lib::attributes::Position;
+use crate::edit_context_lib::origin::Origin;
+use crate::edit_context_lib::staging::PendingChange;
+use crate::edit_context_lib::types::StdCommand;
+use uuid::Uuid;
+
+// Explorer shows Top verbs first and Bottom verbs last, each group sorted by key name
+const GROUPS: [Option<Position>; 3] = [Some(Position::Top), None, Some(Position::Bottom)];
+
+fn group(position: Option<Position>) -> usize {
+    match position {
+        Some(Position::Top) => 0,
+        None => 1,
+        Some(Position::Bottom) => 2,
+    }
+}
+
+/// Sorting by this gives the order Explorer shows verbs of one location in
+pub fn explorer_key(cmd: &StdCommand) -> (usize, String) {
+    (group(cmd.attributes.position), cmd.folder.to_lowercase())
+}
+
+/// The changes that get Explorer to show verbs in a chosen order, and where it won't
+#[derive(Debug, Default)]
+pub struct OrderPlan {
+    pub changes: Vec<PendingChange>,
+    pub warnings: Vec<String>,
+}
+
+// Verbs Windows itself registers, other programs and Explorer refer to them by key name
+const STANDARD_VERBS: [&str; 13] = [
+    "open",
+    "opennew",
+    "openas",
+    "explore",
+    "find",
+    "edit",
+    "print",
+    "printto",
+    "play",
+    "preview",
+    "properties",
+    "runas",
+    "cmd",
+];
+
+// A key name without a prefix an earlier reorder added, such as "03-" or "105-".
+// Names that only look alike, such as "7-Zip", are left alone.
+fn strip_prefix(folder: &str) -> &str {
+    match folder.split_once('-') {
+        Some((number, rest))
+            if number.len() >= 2
+                && !rest.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            rest
+        }
+        _ => folder,
+    }
+}
+
+// Every way of giving `count` runs their own group, keeping the groups in order
+fn assignments(count: usize) -> Vec<Vec<usize>> {
+    (1u8..8)
+        .filter(|mask| mask.count_ones() as usize == count)
+        .map(|mask| (0..3).filter(|group| mask & (1 << group) != 0).collect())
+        .collect()
+}
+
+fn with_position(cmd: &StdCommand, position: Option<Position>) -> Option<PendingChange> {
+    (cmd.attributes.position != position).then(|| {
+        let mut cmd = cmd.clone();
+        cmd.attributes.position = position;
+        PendingChange::SetPosition(cmd)
+    })
+}
+
+fn renamed(cmd: &StdCommand, folder: String) -> StdCommand {
+    let mut renamed = cmd.clone();
+    renamed.folder = folder;
+    renamed
+}
+
+// Why the key name of `cmd` can't be numbered, None for verbs this app added
+fn keeps_name(cmd: &StdCommand, default_verbs: &[String]) -> Option<&'static str> {
+    if cmd.handler.is_some() {
+        Some("it runs a COM handler")
+    } else if cmd.origin == Some(Origin::Shadowed) {
+        Some("it's the machine-wide copy of a per-user verb")
+    } else if default_verbs
+        .iter()
+        .any(|verb| verb.eq_ignore_ascii_case(&cmd.folder))
+    {
+        Some("it's the default verb")
+    } else if STANDARD_VERBS
+        .iter()
+        .any(|verb| verb.eq_ignore_ascii_case(&cmd.folder))
+    {
+        Some("it's one of Windows' own verbs")
+    } else {
+        None
+    }
+}
+
+// Numbers the key names of the verbs that can be renamed so one group holds their order.
+// Renamed keys are moved to a temporary name first, a new name can still be taken by a key
+// that hasn't moved yet.
+fn prefixed(order: &[StdCommand], default_verbs: &[String]) -> OrderPlan {
+    let (kept, numbered): (Vec<&StdCommand>, Vec<&StdCommand>) = order
+        .iter()
+        .partition(|cmd| keeps_name(cmd, default_verbs).is_some());
+    let width = numbered.len().to_string().len().max(2);
+
+    let targets = numbered
+        .into_iter()
+        .enumerate()
+        .map(|(index, cmd)| {
+            let folder = format!("{:0width$}-{}", index + 1, strip_prefix(&cmd.folder));
+            (cmd, renamed(cmd, folder))
+        })
+        .collect::<Vec<(&StdCommand, StdCommand)>>();
+
+    let moving = targets
+        .iter()
+        .filter(|(cmd, target)| cmd.folder != target.folder)
+        .map(|(cmd, target)| {
+            let temporary = format!("{}.{}", target.folder, Uuid::new_v4().simple());
+            (cmd, renamed(cmd, temporary), target)
+        })
+        .collect::<Vec<(&&StdCommand, StdCommand, &StdCommand)>>();
+
+    let changes = moving
+        .iter()
+        .map(|(cmd, temporary, _)| PendingChange::Rename(temporary.clone(), cmd.folder.clone()))
+        .chain(moving.iter().map(|(_, temporary, target)| {
+            PendingChange::Rename((*target).clone(), temporary.folder.clone())
+        }))
+        .chain(
+            targets
+                .iter()
+                .filter_map(|(_, target)| with_position(target, None)),
+        )
+        .collect();
+
+    let warnings = kept
+        .into_iter()
+        .filter_map(|cmd| {
+            keeps_name(cmd, default_verbs).map(|reason| {
+                format!(
+                    "\"{}\" isn't renamed as {}, Explorer places it by its key name",
+                    cmd.title, reason
+                )
+            })
+        })
+        .chain([
+            "Key names are changed, anything else referring to the old names has to be updated"
+                .to_string(),
+        ])
+        .collect();
+
+    OrderPlan { changes, warnings }
+}
+
+/// Works out Position values that show `order` as it is. Explorer sorts by key name within
+/// the top, default and bottom groups, so only three runs of key names can be placed. With
+/// `prefixes` longer orders are kept by numbering the key names instead, apart from the
+/// verbs in `default_verbs` and others that are referred to by name.
+pub fn plan_order(order: &[StdCommand], prefixes: bool, default_verbs: &[String]) -> OrderPlan {
+    let runs = order
+        .chunk_by(|a, b| a.folder.to_lowercase() < b.folder.to_lowercase())
+        .collect::<Vec<&[StdCommand]>>();
+
+    if runs.len() > GROUPS.len() && prefixes {
+        return prefixed(order, default_verbs);
+    }
+
+    let cost = |groups: &Vec<usize>| {
+        runs.iter()
+            .zip(groups)
+            .map(|(run, group)| {
+                run.iter()
+                    .filter(|cmd| explorer_key(cmd).0 != *group)
+                    .count()
+            })
+            .sum::<usize>()
+    };
+
+    // Runs past the third all end up at the bottom
+    let groups = assignments(runs.len().min(GROUPS.len()))
+        .into_iter()
+        .min_by_key(cost)
+        .unwrap_or_default()
+        .into_iter()
+        .chain([2].repeat(runs.len().saturating_sub(GROUPS.len())))
+        .collect::<Vec<usize>>();
+
+    let warnings = runs
+        .windows(2)
+        .skip(GROUPS.len() - 1)
+        .filter_map(|pair| Some((pair[0].last()?, pair[1].first()?)))
+        .map(|(before, after)| {
+            format!(
+                "Explorer sorts by key name, \"{}\" will still be shown before \"{}\"",
+                after.title, before.title
+            )
+        })
+        .collect();
+
+    let changes = runs
+        .iter()
+        .zip(groups)
+        .flat_map(|(run, group)| run.iter().map(move |cmd| (cmd, GROUPS[group])))
+        .filter_map(|(cmd, position)| with_position(cmd, position))
+        .collect();
+
+    OrderPlan { changes, warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{DIRECTORY_SUBKEY, convert_subkey};
+    use crate::edit_context_lib::staging::apply_changes;
+    use crate::registry_io::backend::{Hive, RegistryBackend};
+    use crate::registry_io::memory::MemoryRegistry;
+
+    fn verbs(names: &[&str]) -> Vec<StdCommand> {
+        names
+            .iter()
+            .map(|name| {
+                StdCommand::new(
+                    name.to_string(),
+                    String::new(),
+                    String::new(),
+                    name.to_string(),
+                    convert_subkey(&DIRECTORY_SUBKEY),
+                )
+            })
+            .collect()
+    }
+
+    fn summary(plan: &OrderPlan) -> Vec<String> {
+        plan.changes.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn orders_become_positions_or_prefixes() {
+        assert!(
+            plan_order(&verbs(&["A", "B", "C"]), false, &[])
+                .changes
+                .is_empty()
+        );
+
+        let plan = plan_order(&verbs(&["C", "A", "B"]), false, &[]);
+        assert_eq!(summary(&plan), ["Show \"C\" at the top"]);
+        assert!(plan.warnings.is_empty());
+
+        let plan = plan_order(&verbs(&["D", "C", "B", "A"]), false, &[]);
+        assert_eq!(plan.warnings.len(), 1);
+
+        let plan = plan_order(&verbs(&["D", "C", "7-Zip", "02-A"]), true, &[]);
+        let renames = plan
+            .changes
+            .iter()
+            .filter_map(|change| Some((change.renamed_from()?, change.command().folder.as_str())))
+            .collect::<Vec<(&str, &str)>>();
+
+        // Every key is moved to a temporary name and then to its new one
+        assert_eq!(renames.len(), 8);
+        assert!(
+            renames[4..]
+                .iter()
+                .zip(&renames[..4])
+                .all(|(second, first)| second.0 == first.1)
+        );
+        assert_eq!(
+            renames[4..]
+                .iter()
+                .map(|(_, to)| *to)
+                .collect::<Vec<&str>>(),
+            ["01-D", "02-C", "03-7-Zip", "04-A"]
+        );
+    }
+
+    #[test]
+    fn prefixing_again_renames_keys_into_each_others_names() {
+        let reg = MemoryRegistry::new();
+        let location = "Directory\\shell";
+
+        reg.create_key(Hive::ClassesRoot, location).unwrap();
+        let order = verbs(&["04-X", "03-X", "02-X", "01-X"]);
+        order.iter().for_each(|cmd| {
+            reg.create_key(Hive::ClassesRoot, &format!("{}\\{}", location, cmd.folder))
+                .unwrap();
+            reg.set_string(
+                Hive::ClassesRoot,
+                &format!("{}\\{}", location, cmd.folder),
+                "Was",
+                &cmd.folder,
+            )
+            .unwrap();
+        });
+
+        apply_changes(&plan_order(&order, true, &[]).changes, &reg).unwrap();
+
+        let mut names = reg.enum_keys(Hive::ClassesRoot, location).unwrap();
+        names.sort();
+        assert_eq!(names, ["01-X", "02-X", "03-X", "04-X"]);
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, "Directory\\shell\\01-X", "Was")
+                .unwrap(),
+            "04-X"
+        );
+    }
+
+    #[test]
+    fn verbs_referred_to_by_name_keep_it() {
+        let mut order = verbs(&["D", "open", "C", "Main", "B", "A"]);
+        order[4].origin = Some(Origin::Shadowed);
+
+        let plan = plan_order(&order, true, &["main".to_string()]);
+        let renamed = plan
+            .changes
+            .iter()
+            .filter(|change| change.renamed_from().is_some())
+            .map(|change| change.command().folder.as_str())
+            .filter(|folder| !folder.contains('.'))
+            .collect::<Vec<&str>>();
+
+        assert_eq!(renamed, ["01-D", "02-C", "03-A"]);
+        assert_eq!(plan.warnings.len(), 4);
+
+        assert_eq!(strip_prefix("005-05-X"), "05-X");
+        assert_eq!(strip_prefix("7-Zip"), "7-Zip");
+    }
+}
@@ -0,0 +1,244 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::edit_context_lib::addtocontext::read_command;
+use crate::edit_context_lib::scope::{Scope, classes_path};
+use crate::edit_context_lib::types::{KeyPath, NormalResult, StdCommand, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::tree::RegTree;
+
+/// Where a verb is physically stored. HKEY_CLASSES_ROOT only shows the merged result,
+/// where a per-user key wins over a machine key with the same name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    PerUser,
+    Machine,
+    // Per-user, and hiding a machine verb with the same key
+    Overriding,
+    // Machine, and hidden by a per-user verb with the same key
+    Shadowed,
+}
+
+impl Origin {
+    pub fn hive(self) -> Hive {
+        match self {
+            Self::PerUser | Self::Overriding => Hive::CurrentUser,
+            Self::Machine | Self::Shadowed => Hive::LocalMachine,
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PerUser => write!(f, "User"),
+            Self::Machine => write!(f, "Machine"),
+            Self::Overriding => write!(f, "User, overrides Machine"),
+            Self::Shadowed => write!(f, "Machine, hidden by User"),
+        }
+    }
+}
+
+// The verb's key under the classes root
+fn verb_classes_path(cmd: &StdCommand) -> Option<String> {
+    let path = format!("{}\\{}", key_path_string(&cmd.path), cmd.folder);
+
+    classes_path(cmd.hive, &path).map(str::to_string)
+}
+
+// Where a classes path is stored in the per-user and machine hives
+fn stored_path(classes: &str) -> String {
+    Scope::PerUser.subkey(&[classes]).join("\\")
+}
+
+// The location holding a verb, moved into the machine classes
+fn machine_location(cmd: &StdCommand) -> Option<KeyPath> {
+    let location = key_path_string(&cmd.path);
+    let classes = classes_path(cmd.hive, &location)?;
+
+    Some(Rc::new(RefCell::new(
+        Scope::Machine
+            .subkey(&classes.split('\\').collect::<Vec<&str>>())
+            .into_iter()
+            .map(Box::from)
+            .collect(),
+    )))
+}
+
+/// Tags every verb with the hive it's stored in. In the merged view a machine verb hidden
+/// by a per-user one is added to the list as well, right after the verb hiding it.
+pub fn tag_origins(commands: Vec<StdCommand>, reg: &dyn RegistryBackend) -> Vec<StdCommand> {
+    commands
+        .into_iter()
+        .flat_map(|mut cmd| {
+            let Some(classes) = verb_classes_path(&cmd) else {
+                return vec![cmd];
+            };
+
+            let path = stored_path(&classes);
+            let user = reg.key_exists(Hive::CurrentUser, &path);
+            let machine = reg.key_exists(Hive::LocalMachine, &path);
+
+            cmd.origin = match (cmd.hive, user, machine) {
+                (Hive::CurrentUser, _, true) | (Hive::ClassesRoot, true, true) => {
+                    Some(Origin::Overriding)
+                }
+                (Hive::CurrentUser, _, false) | (Hive::ClassesRoot, true, false) => {
+                    Some(Origin::PerUser)
+                }
+                (Hive::LocalMachine, true, _) => Some(Origin::Shadowed),
+                (Hive::LocalMachine, false, _) | (Hive::ClassesRoot, false, true) => {
+                    Some(Origin::Machine)
+                }
+                (Hive::ClassesRoot, false, false) => None,
+            };
+
+            let shadowed = (cmd.hive == Hive::ClassesRoot && user && machine)
+                .then(|| machine_location(&cmd))
+                .flatten()
+                .and_then(|location| {
+                    read_command(location, cmd.folder.clone(), reg, Hive::LocalMachine).ok()
+                })
+                .map(|mut hidden| {
+                    hidden.origin = Some(Origin::Shadowed);
+                    hidden
+                });
+
+            [Some(cmd), shadowed].into_iter().flatten().collect()
+        })
+        .collect()
+}
+
+/// The keys a move reads and writes, the verb in both the per-user and machine classes
+pub fn move_keys(cmd: &StdCommand) -> Vec<(Hive, String)> {
+    verb_classes_path(cmd).map_or(Vec::new(), |classes| {
+        let path = stored_path(&classes);
+
+        vec![
+            (Hive::CurrentUser, path.clone()),
+            (Hive::LocalMachine, path),
+        ]
+    })
+}
+
+/// Copies the verb and everything under it, including values this app doesn't read,
+/// to the other hive and then deletes the original. Nothing changes if any step fails.
+pub fn move_verb(cmd: &StdCommand, to: Scope, reg: &dyn RegistryBackend) -> NormalResult {
+    let classes = verb_classes_path(cmd).ok_or("Only class keys can be moved")?;
+    let from = cmd
+        .origin
+        .map(Origin::hive)
+        .ok_or("Unable to tell which hive this entry is stored in")?;
+    let to = match to {
+        Scope::Merged => return Err("Entries can only be moved to per-user or machine".into()),
+        _ => to.hive(),
+    };
+
+    if from == to {
+        return Err(format!("\"{}\" is already in {}", cmd.title, to).into());
+    }
+
+    let path = stored_path(&classes);
+
+    if reg.key_exists(to, &path) {
+        return Err(format!(
+            "{}\\{} already exists, remove one of the two first",
+            to, path
+        )
+        .into());
+    }
+
+    let tree = RegTree::read(reg, from, &path)?;
+
+    reg.begin_transaction()?;
+
+    let moved = tree
+        .write(reg, to, &path)
+        .and_then(|()| reg.delete_key_all(from, &path));
+
+    match moved {
+        Ok(()) => reg.commit_transaction(),
+        Err(err) => {
+            reg.rollback_transaction()?;
+            Err(format!("{}, nothing was moved", err).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{
+        DIRECTORY_SUBKEY, convert_subkey, get_current_context_window,
+    };
+    use crate::registry_io::backend::{RegData, RegDataType};
+    use crate::registry_io::memory::MemoryRegistry;
+
+    // The memory registry doesn't merge hives, so the merged view is written out by hand
+    fn add_verb(reg: &MemoryRegistry, hive: Hive, name: &str, command: &str) {
+        let path = match hive {
+            Hive::ClassesRoot => format!("Directory\\shell\\{}", name),
+            _ => format!("Software\\Classes\\Directory\\shell\\{}", name),
+        };
+
+        reg.create_key(hive, &format!("{}\\command", path)).unwrap();
+        reg.set_string(hive, &path, "", name).unwrap();
+        reg.set_string(hive, &path, "Icon", "").unwrap();
+        reg.set_string(hive, &format!("{}\\command", path), "", command)
+            .unwrap();
+    }
+
+    #[test]
+    fn tags_origins_and_moves_whole_subtrees() {
+        let reg = MemoryRegistry::new();
+        add_verb(&reg, Hive::LocalMachine, "Tool", "tool.exe");
+        add_verb(&reg, Hive::ClassesRoot, "Tool", "tool.exe");
+        add_verb(&reg, Hive::LocalMachine, "Both", "machine.exe");
+        add_verb(&reg, Hive::CurrentUser, "Both", "user.exe");
+        add_verb(&reg, Hive::ClassesRoot, "Both", "user.exe");
+
+        let extra = RegData {
+            bytes: vec![1, 2, 3],
+            vtype: RegDataType::Binary,
+        };
+        reg.set_value(
+            Hive::LocalMachine,
+            "Software\\Classes\\Directory\\shell\\Tool",
+            "Unknown",
+            &extra,
+        )
+        .unwrap();
+
+        let listed =
+            get_current_context_window(convert_subkey(&DIRECTORY_SUBKEY), &reg, Hive::ClassesRoot)
+                .unwrap();
+        let tagged = tag_origins(listed, &reg);
+
+        let origins = tagged
+            .iter()
+            .map(|cmd| (cmd.command.as_str(), cmd.hive, cmd.origin))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            origins,
+            [
+                ("user.exe", Hive::ClassesRoot, Some(Origin::Overriding)),
+                ("machine.exe", Hive::LocalMachine, Some(Origin::Shadowed)),
+                ("tool.exe", Hive::ClassesRoot, Some(Origin::Machine)),
+            ]
+        );
+
+        assert!(move_verb(&tagged[1], Scope::PerUser, &reg).is_err());
+
+        move_verb(&tagged[2], Scope::PerUser, &reg).unwrap();
+        let moved = "Software\\Classes\\Directory\\shell\\Tool";
+        assert!(!reg.key_exists(Hive::LocalMachine, moved));
+        assert_eq!(
+            reg.get_string(Hive::CurrentUser, &format!("{}\\command", moved), "")
+                .unwrap(),
+            "tool.exe"
+        );
+        assert_eq!(
+            reg.get_value(Hive::CurrentUser, moved, "Unknown").unwrap(),
+            extra
+        );
+    }
+}
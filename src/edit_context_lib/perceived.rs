@@ -0,0 +1,117 @@
+use std::error::Error;
+
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+/// Verbs under `SystemFileAssociations\<perceived type>\shell` are shown for every
+/// extension of that type
+pub const SYSTEM_FILE_ASSOCIATIONS: &str = "SystemFileAssociations";
+
+/// A perceived type and the extensions whose `PerceivedType` value names it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerceivedType {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+fn add_extension(
+    mut types: Vec<PerceivedType>,
+    name: &str,
+    extension: Option<String>,
+) -> Vec<PerceivedType> {
+    let index = match types
+        .iter()
+        .position(|existing| existing.name.eq_ignore_ascii_case(name))
+    {
+        Some(index) => index,
+        None => {
+            types.push(PerceivedType {
+                name: name.to_lowercase(),
+                extensions: Vec::new(),
+            });
+            types.len() - 1
+        }
+    };
+
+    types[index].extensions.extend(extension);
+    types
+}
+
+/// The perceived types in HKEY_CLASSES_ROOT, the ones extensions name and the ones that
+/// already have a key under SystemFileAssociations, sorted by name
+pub fn read_perceived_types(
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<PerceivedType>, Box<dyn Error>> {
+    let from_extensions = reg
+        .enum_keys(Hive::ClassesRoot, "")?
+        .into_iter()
+        .filter(|name| name.starts_with('.'))
+        .filter_map(|extension| {
+            reg.get_string(Hive::ClassesRoot, &extension, "PerceivedType")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .map(|name| (name, extension))
+        })
+        .fold(Vec::new(), |types, (name, extension)| {
+            add_extension(types, &name, Some(extension))
+        });
+
+    // Keys for single extensions are under SystemFileAssociations as well
+    let mut types = reg
+        .enum_keys(Hive::ClassesRoot, SYSTEM_FILE_ASSOCIATIONS)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| !name.starts_with('.'))
+        .fold(from_extensions, |types, name| {
+            add_extension(types, &name, None)
+        });
+
+    types.sort_by(|a, b| a.name.cmp(&b.name));
+    types
+        .iter_mut()
+        .for_each(|perceived| perceived.extensions.sort_by_key(|ext| ext.to_lowercase()));
+
+    Ok(types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    #[test]
+    fn perceived_types_come_from_extensions_and_system_file_associations() {
+        let reg = MemoryRegistry::new();
+
+        [".png", ".JPG", ".txt", ".xyz", "txtfile"]
+            .iter()
+            .for_each(|key| reg.create_key(Hive::ClassesRoot, key).unwrap());
+        [(".png", "image"), (".JPG", "Image"), (".txt", "text")]
+            .iter()
+            .for_each(|(extension, name)| {
+                reg.set_string(Hive::ClassesRoot, extension, "PerceivedType", name)
+                    .unwrap()
+            });
+        reg.create_key(Hive::ClassesRoot, "SystemFileAssociations\\video\\shell")
+            .unwrap();
+        reg.create_key(Hive::ClassesRoot, "SystemFileAssociations\\.txt\\shell")
+            .unwrap();
+
+        assert_eq!(
+            read_perceived_types(&reg).unwrap(),
+            [
+                PerceivedType {
+                    name: "image".to_string(),
+                    extensions: vec![".JPG".to_string(), ".png".to_string()],
+                },
+                PerceivedType {
+                    name: "text".to_string(),
+                    extensions: vec![".txt".to_string()],
+                },
+                PerceivedType {
+                    name: "video".to_string(),
+                    extensions: Vec::new(),
+                },
+            ]
+        );
+    }
+}
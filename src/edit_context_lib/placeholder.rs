@@ -0,0 +1,194 @@
+use std::fmt::Display;
+
+use crate::edit_context_lib::catalogue::KnownLocation;
+
+/// A placeholder in a command that won't be given what it's expected to hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaceholderWarning {
+    // As written in the command
+    pub placeholder: String,
+    pub problem: String,
+    // The placeholder to use instead
+    pub suggestion: Option<&'static str>,
+}
+
+impl Display for PlaceholderWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.suggestion {
+            Some(suggestion) => write!(
+                f,
+                "{} {}, use {}",
+                self.placeholder, self.problem, suggestion
+            ),
+            None => write!(f, "{} {}", self.placeholder, self.problem),
+        }
+    }
+}
+
+// Given by Explorer but not meant for command lines, or only in rare cases
+const SPECIAL: [char; 4] = ['I', 'H', 'S', '~'];
+
+// Where each placeholder starts, `%%` and environment variables such as `%USERPROFILE%`
+// are skipped
+fn scan(chars: &[char]) -> Vec<(usize, String)> {
+    let (found, _) = (0..chars.len()).fold((Vec::new(), 0), |(mut found, skip_to), index| {
+        if index < skip_to || chars[index] != '%' {
+            return (found, skip_to);
+        }
+
+        match chars.get(index + 1) {
+            Some('%') => (found, index + 2),
+            Some(c) if c.is_ascii_alphabetic() => {
+                // A name followed by another % is an environment variable
+                let name_end = chars[index + 1..]
+                    .iter()
+                    .position(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '(' | ')')))
+                    .map_or(chars.len(), |len| index + 1 + len);
+
+                if name_end - index > 2 && chars.get(name_end) == Some(&'%') {
+                    (found, name_end + 1)
+                } else {
+                    found.push((index, format!("%{}", c)));
+                    (found, index + 2)
+                }
+            }
+            Some(c) if c.is_ascii_digit() || matches!(c, '*' | '~') => {
+                found.push((index, format!("%{}", c)));
+                (found, index + 2)
+            }
+            _ => (found, index + 1),
+        }
+    });
+
+    found
+}
+
+/// The placeholders in a command in the order they're written
+pub fn placeholders(command: &str) -> Vec<String> {
+    scan(&command.chars().collect::<Vec<char>>())
+        .into_iter()
+        .map(|(_, placeholder)| placeholder)
+        .collect()
+}
+
+// %0 to %9 are the selected items, all given where %1 is
+fn family(placeholder: &str) -> String {
+    match placeholder.chars().nth(1) {
+        Some(c) if c.is_ascii_digit() => "%1".to_string(),
+        _ => placeholder.to_uppercase(),
+    }
+}
+
+/// The placeholder a new command at `location` is given
+pub fn default_placeholder(location: Option<&KnownLocation>) -> &'static str {
+    location
+        .and_then(|location| location.placeholders.first().copied())
+        .unwrap_or("%1")
+}
+
+/// Warns about placeholders in `command` that Explorer doesn't know or leaves empty at
+/// `location`. Without a location only unknown placeholders are found.
+pub fn check_placeholders(
+    command: &str,
+    location: Option<&KnownLocation>,
+) -> Vec<PlaceholderWarning> {
+    let checked = placeholders(command).into_iter().fold(
+        Vec::new(),
+        |mut checked: Vec<String>, placeholder| {
+            if !checked.contains(&placeholder) {
+                checked.push(placeholder);
+            }
+            checked
+        },
+    );
+
+    checked
+        .into_iter()
+        .filter_map(|placeholder| {
+            let family = family(&placeholder);
+            let letter = family.chars().nth(1).unwrap_or_default();
+
+            let (problem, suggestion) = if letter == 'I' {
+                (
+                    "is an item ID list, a command line can't use it".to_string(),
+                    Some(default_placeholder(location)),
+                )
+            } else if SPECIAL.contains(&letter) {
+                return None;
+            } else if !matches!(letter, '1' | '*' | 'L' | 'V' | 'W' | 'D') {
+                (
+                    "isn't a placeholder Explorer knows".to_string(),
+                    Some(default_placeholder(location)),
+                )
+            } else {
+                let location = location?;
+
+                if location.placeholders.contains(&family.as_str()) {
+                    return None;
+                }
+
+                (
+                    format!("is left empty for {}", location.name),
+                    Some(default_placeholder(Some(location))),
+                )
+            };
+
+            Some(PlaceholderWarning {
+                placeholder,
+                problem,
+                suggestion,
+            })
+        })
+        .collect()
+}
+
+/// `command` with every `placeholder` written as `suggestion`
+pub fn replace_placeholder(command: &str, placeholder: &str, suggestion: &str) -> String {
+    let chars = command.chars().collect::<Vec<char>>();
+
+    let (mut replaced, rest) = scan(&chars)
+        .into_iter()
+        .filter(|(_, found)| found == placeholder)
+        .fold((String::new(), 0), |(mut replaced, from), (index, _)| {
+            replaced.extend(&chars[from..index]);
+            replaced.push_str(suggestion);
+            (replaced, index + 2)
+        });
+
+    replaced.extend(&chars[rest..]);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::catalogue::find_location;
+
+    #[test]
+    fn placeholders_are_checked_against_the_location() {
+        let command = "\"%ProgramFiles%\\tool.exe\" \"%1\" %W 100%% %Q";
+
+        assert_eq!(placeholders(command), ["%1", "%W", "%Q"]);
+
+        let background = find_location("Background");
+        let warnings = check_placeholders(command, background);
+
+        assert_eq!(
+            warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>(),
+            [
+                "%1 is left empty for Background, use %V",
+                "%Q isn't a placeholder Explorer knows, use %V"
+            ]
+        );
+        assert!(check_placeholders("tool.exe \"%1\" %W", find_location("Directory")).is_empty());
+        assert_eq!(check_placeholders("tool.exe %Q", None).len(), 1);
+
+        assert_eq!(
+            replace_placeholder(command, "%1", "%V"),
+            "\"%ProgramFiles%\\tool.exe\" \"%V\" %W 100%% %Q"
+        );
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt::Display;
+
+/// Why a verb couldn't be read in full. Explorer may still show it, so it's listed anyway.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerbProblem {
+    // Neither a default value nor MUIVerb, Explorer shows the key name
+    NoTitle,
+    NoIcon,
+    NoCommandKey,
+    // The command subkey is there but has no default value
+    EmptyCommand,
+    // A COM handler points to a CLSID that isn't registered
+    UnknownClsid(String),
+    // Reading the key failed altogether
+    Unreadable(String),
+}
+
+impl Display for VerbProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoTitle => write!(f, "title missing, the key name is shown"),
+            Self::NoIcon => write!(f, "icon value missing"),
+            Self::NoCommandKey => write!(f, "no command subkey"),
+            Self::EmptyCommand => write!(f, "command subkey has no default value"),
+            Self::UnknownClsid(clsid) => write!(f, "handler {} is not registered", clsid),
+            Self::Unreadable(err) => write!(f, "unreadable: {}", err),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::error::Error;
+
+use crate::edit_context_lib::staging::{ChangePreview, diff_tree};
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::reader::{RegReader, RegVerb};
+use crate::registry_io::tree::RegTree;
+
+// A key to put back the way the backup has it. Verbs are replaced outright, locations
+// only get their own values back so verbs added since the backup are kept.
+pub(super) struct RestoreTarget {
+    pub hive: Hive,
+    pub path: String,
+    pub tree: RegTree,
+    pub replace: bool,
+}
+
+impl RestoreTarget {
+    fn write(&self, reg: &dyn RegistryBackend) -> NormalResult {
+        if self.replace && reg.key_exists(self.hive, &self.path) {
+            reg.delete_key_all(self.hive, &self.path)?;
+        }
+
+        self.tree.write(reg, self.hive, &self.path)
+    }
+
+    fn verify(&self, reg: &dyn RegistryBackend, output: &mut Vec<ChangePreview>) {
+        let live = RegTree::read(reg, self.hive, &self.path).ok().map(|tree| {
+            if self.replace {
+                tree
+            } else {
+                RegTree {
+                    values: tree.values,
+                    subkeys: Vec::new(),
+                }
+            }
+        });
+
+        diff_tree(
+            &format!("{}\\{}", self.hive, self.path),
+            live.as_ref(),
+            Some(&self.tree),
+            output,
+        );
+    }
+}
+
+fn verb_target(reader: &RegReader, verb: &RegVerb) -> Result<RestoreTarget, Box<dyn Error>> {
+    Ok(RestoreTarget {
+        hive: verb.hive,
+        path: verb.path(),
+        tree: reader.tree(verb.hive, &verb.path())?,
+        replace: true,
+    })
+}
+
+fn location_target(
+    reader: &RegReader,
+    hive: Hive,
+    path: String,
+) -> Result<RestoreTarget, Box<dyn Error>> {
+    let tree = RegTree {
+        values: reader.tree(hive, &path)?.values,
+        subkeys: Vec::new(),
+    };
+
+    Ok(RestoreTarget {
+        hive,
+        path,
+        tree,
+        replace: false,
+    })
+}
+
+// Writes every target in one transaction, then reads them back. Whatever is returned
+// is a difference between the registry and the backup that survived the restore.
+pub(super) fn restore(
+    targets: Vec<RestoreTarget>,
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ChangePreview>, Box<dyn Error>> {
+    reg.begin_transaction()?;
+
+    match targets.iter().try_for_each(|target| target.write(reg)) {
+        Ok(()) => reg.commit_transaction()?,
+        Err(err) => {
+            reg.rollback_transaction()?;
+            return Err(format!("{}, nothing was changed", err).into());
+        }
+    }
+
+    Ok(targets.iter().fold(Vec::new(), |mut output, target| {
+        target.verify(reg, &mut output);
+        output
+    }))
+}
+
+/// The keys restoring `verbs`, or the whole backup when None, writes to
+pub fn restore_keys(reader: &RegReader, verbs: Option<&[RegVerb]>) -> Vec<(Hive, String)> {
+    let verb_key = |verb: &RegVerb| (verb.hive, verb.path());
+
+    match verbs {
+        Some(verbs) => verbs.iter().map(verb_key).collect(),
+        None => reader
+            .locations()
+            .into_iter()
+            .chain(reader.verbs().iter().map(verb_key))
+            .collect(),
+    }
+}
+
+/// Puts the chosen verbs back exactly as the backup has them, other verbs are left alone
+pub fn restore_verbs(
+    reader: &RegReader,
+    verbs: &[RegVerb],
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ChangePreview>, Box<dyn Error>> {
+    let targets = verbs
+        .iter()
+        .map(|verb| verb_target(reader, verb))
+        .collect::<Result<Vec<RestoreTarget>, Box<dyn Error>>>()?;
+
+    restore(targets, reg)
+}
+
+/// Restores every verb in the backup along with the values on the keys that hold them
+pub fn restore_all(
+    reader: &RegReader,
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ChangePreview>, Box<dyn Error>> {
+    let targets = reader
+        .locations()
+        .into_iter()
+        .map(|(hive, path)| location_target(reader, hive, path))
+        .chain(reader.verbs().iter().map(|verb| verb_target(reader, verb)))
+        .collect::<Result<Vec<RestoreTarget>, Box<dyn Error>>>()?;
+
+    restore(targets, reg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::history::{History, HistoryEntry};
+    use crate::registry_io::memory::MemoryRegistry;
+    use crate::registry_io::writer::RegWriter;
+
+    const SHELL: &str = "Directory\\shell";
+
+    fn add_verb(reg: &MemoryRegistry, name: &str, command: &str) {
+        let path = format!("{}\\{}", SHELL, name);
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", path))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, &path, "", name).unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("{}\\command", path),
+            "",
+            command,
+        )
+        .unwrap();
+    }
+
+    fn command_of(reg: &MemoryRegistry, name: &str) -> Option<String> {
+        reg.get_string(
+            Hive::ClassesRoot,
+            &format!("{}\\{}\\command", SHELL, name),
+            "",
+        )
+        .ok()
+    }
+
+    #[test]
+    fn restores_selected_verbs_and_keeps_newer_ones() {
+        let reg = MemoryRegistry::new();
+        add_verb(&reg, "Edit", "edit.exe");
+        add_verb(&reg, "View", "view.exe");
+
+        let file =
+            std::env::temp_dir().join(format!("context-editor-{}.reg", uuid::Uuid::new_v4()));
+        let reader = RegWriter::new()
+            .with_all_subkeys(&reg, Hive::ClassesRoot, SHELL.to_string())
+            .and_then(|writer| writer.write_to(&file))
+            .and_then(|_| RegReader::try_read_file(&file));
+        // Removed before anything can fail, so no file is left behind
+        let _ = std::fs::remove_file(&file);
+        let reader = reader.unwrap();
+
+        let verbs = reader.verbs();
+        assert_eq!(verbs.len(), 2);
+        assert_eq!(verbs[0].title.as_deref(), Some("Edit"));
+
+        reg.delete_key_all(Hive::ClassesRoot, &format!("{}\\Edit", SHELL))
+            .unwrap();
+        add_verb(&reg, "View", "other.exe");
+        reg.set_string(Hive::ClassesRoot, &format!("{}\\View", SHELL), "Extra", "1")
+            .unwrap();
+        add_verb(&reg, "New", "new.exe");
+
+        let mismatches = restore_verbs(&reader, &verbs[..1], &reg).unwrap();
+        assert!(mismatches.is_empty());
+        assert_eq!(command_of(&reg, "Edit").as_deref(), Some("edit.exe"));
+        assert_eq!(command_of(&reg, "View").as_deref(), Some("other.exe"));
+
+        let mut history = History::default();
+        let mut mismatches = Vec::new();
+        history.push(
+            HistoryEntry::record(
+                "Restore a backup".to_string(),
+                &restore_keys(&reader, None),
+                &reg,
+                || {
+                    mismatches = restore_all(&reader, &reg)?;
+                    Ok(())
+                },
+            )
+            .unwrap(),
+        );
+        assert!(mismatches.is_empty());
+        assert_eq!(command_of(&reg, "View").as_deref(), Some("view.exe"));
+        assert!(
+            reg.get_string(Hive::ClassesRoot, &format!("{}\\View", SHELL), "Extra")
+                .is_err()
+        );
+        assert_eq!(command_of(&reg, "New").as_deref(), Some("new.exe"));
+
+        // The restore can be undone like any other edit
+        history.undo(&reg).unwrap();
+        assert_eq!(command_of(&reg, "View").as_deref(), Some("other.exe"));
+        assert_eq!(command_of(&reg, "New").as_deref(), Some("new.exe"));
+    }
+
+    #[test]
+    fn verbs_only_listed_through_their_subkeys_are_found() {
+        let file =
+            std::env::temp_dir().join(format!("context-editor-{}.reg", uuid::Uuid::new_v4()));
+        let text = "Windows Registry Editor Version 5.00\r\n\r\n\
+            [HKEY_CLASSES_ROOT\\Directory\\shell\\Foo\\command]\r\n\
+            @=\"foo.exe\"\r\n";
+        let reader = std::fs::write(&file, text)
+            .map_err(Into::into)
+            .and_then(|_| RegReader::try_read_file(&file));
+        let _ = std::fs::remove_file(&file);
+        let reader = reader.unwrap();
+
+        let verbs = reader.verbs();
+        assert_eq!(verbs.len(), 1);
+        assert_eq!(verbs[0].name, "Foo");
+        assert_eq!(verbs[0].title, None);
+
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, SHELL).unwrap();
+        assert!(restore_verbs(&reader, &verbs, &reg).unwrap().is_empty());
+        assert_eq!(command_of(&reg, "Foo").as_deref(), Some("foo.exe"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+const CLASSES: [&str; 2] = ["Software", "Classes"];
+
+/// Which set of classes the app reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scope {
+    // HKEY_CURRENT_USER\Software\Classes, no admin rights needed
+    #[default]
+    PerUser,
+    // HKEY_LOCAL_MACHINE\Software\Classes, every user sees it
+    Machine,
+    // HKEY_CLASSES_ROOT, what Explorer shows. Windows decides where writes end up.
+    Merged,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Self::PerUser, Self::Machine, Self::Merged];
+
+    pub fn hive(self) -> Hive {
+        match self {
+            Self::PerUser => Hive::CurrentUser,
+            Self::Machine => Hive::LocalMachine,
+            Self::Merged => Hive::ClassesRoot,
+        }
+    }
+
+    /// The keys in front of a classes path in this scope's hive
+    pub fn prefix(self) -> &'static [&'static str] {
+        match self {
+            Self::PerUser | Self::Machine => &CLASSES,
+            Self::Merged => &[],
+        }
+    }
+
+    /// A path relative to HKEY_CLASSES_ROOT moved into this scope
+    pub fn subkey<'a>(self, path: &[&'a str]) -> Vec<&'a str> {
+        self.prefix().iter().chain(path).copied().collect()
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PerUser => write!(f, "Per-user"),
+            Self::Machine => write!(f, "Machine"),
+            Self::Merged => write!(f, "Merged view"),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str
+            .trim()
+            .to_lowercase()
+            .replace(['-', ' ', '_'], "")
+            .as_str()
+        {
+            "peruser" | "user" => Ok(Self::PerUser),
+            "machine" => Ok(Self::Machine),
+            "merged" | "mergedview" => Ok(Self::Merged),
+            _ => Err(format!("Unknown scope \"{}\"", str)),
+        }
+    }
+}
+
+/// The part of a path under `Software\Classes` (or the whole path for HKEY_CLASSES_ROOT),
+/// so the same location can be recognised in any hive
+pub fn classes_path(hive: Hive, path: &str) -> Option<&str> {
+    let prefix = format!("{}\\", CLASSES.join("\\"));
+
+    match hive {
+        Hive::ClassesRoot => Some(path),
+        Hive::CurrentUser | Hive::LocalMachine => path
+            .get(..prefix.len())
+            .filter(|start| start.eq_ignore_ascii_case(&prefix))
+            .map(|_| &path[prefix.len()..]),
+    }
+}
+
+/// Where a key under the classes root is found from `hive`, in the hive's own classes
+/// when it's there and in HKEY_CLASSES_ROOT otherwise
+pub fn find_classes_key(reg: &dyn RegistryBackend, hive: Hive, classes: &str) -> (Hive, String) {
+    let scope = match hive {
+        Hive::CurrentUser => Scope::PerUser,
+        Hive::LocalMachine => Scope::Machine,
+        Hive::ClassesRoot => return (hive, classes.to_string()),
+    };
+    let path = scope.subkey(&[classes]).join("\\");
+
+    if reg.key_exists(hive, &path) {
+        (hive, path)
+    } else {
+        (Hive::ClassesRoot, classes.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{
+        add_to_context_window, get_current_context_window,
+    };
+    use crate::edit_context_lib::types::{Key, StdCommand, key_path_string};
+    use crate::registry_io::memory::MemoryRegistry;
+
+    #[test]
+    fn keys_follow_the_scope_unless_their_hive_is_fixed() {
+        let file = Key::new("File")
+            .with_path("*/shell")
+            .with_props("HasExt")
+            .in_scope(Scope::PerUser)
+            .with_extension(".txt");
+        assert_eq!(file.hive, Hive::CurrentUser);
+        assert_eq!(
+            key_path_string(&file.path),
+            "Software\\Classes\\.txt\\shell"
+        );
+
+        let fixed = Key::new("Machine")
+            .with_path("HKLM/Software/Classes/Directory/shell")
+            .in_scope(Scope::PerUser);
+        assert_eq!(fixed.hive, Hive::LocalMachine);
+        assert_eq!(
+            key_path_string(&fixed.path),
+            "Software\\Classes\\Directory\\shell"
+        );
+
+        let merged = Key::new("Directory")
+            .with_path("Directory/shell")
+            .in_scope(Scope::Merged);
+        assert_eq!(merged.hive, Hive::ClassesRoot);
+        assert_eq!(key_path_string(&merged.path), "Directory\\shell");
+    }
+
+    #[test]
+    fn per_user_locations_are_created_on_first_add() {
+        let reg = MemoryRegistry::new();
+        let key = Key::new("Directory")
+            .with_path("Directory/shell")
+            .in_scope(Scope::PerUser);
+
+        assert!(
+            get_current_context_window(key.clone_path(), &reg, key.hive)
+                .unwrap()
+                .is_empty()
+        );
+
+        let cmd = StdCommand::new(
+            "Tool".to_string(),
+            "".to_string(),
+            "tool.exe".to_string(),
+            "Tool".to_string(),
+            key.clone_path(),
+        )
+        .with_hive(key.hive);
+        add_to_context_window(&cmd, &reg, cmd.hive).unwrap();
+
+        assert!(reg.key_exists(
+            Hive::CurrentUser,
+            "Software\\Classes\\Directory\\shell\\Tool\\command"
+        ));
+        assert!(!reg.key_exists(Hive::ClassesRoot, "Directory\\shell"));
+    }
+}
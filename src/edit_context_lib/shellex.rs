@@ -0,0 +1,197 @@
+use std::error::Error;
+
+use crate::edit_context_lib::handler::{ComHandler, HandlerKind, resolve};
+use crate::edit_context_lib::scope::Scope;
+use crate::edit_context_lib::types::{Key, NormalResult, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+/// Shell extensions whose CLSID is a value name here aren't loaded by Explorer
+pub const BLOCKED: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Shell Extensions\\Blocked";
+
+const BLOCKABLE: [Hive; 2] = [Hive::CurrentUser, Hive::LocalMachine];
+
+/// A context menu handler registered under a `shellex\ContextMenuHandlers` key
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellExtension {
+    pub name: String,
+    pub hive: Hive,
+    // The ContextMenuHandlers key the handler is under
+    pub location: String,
+    // None when neither the key name nor its default value is a CLSID
+    pub handler: Option<ComHandler>,
+    // The hives whose Blocked list has the CLSID
+    pub blocked: Vec<Hive>,
+}
+
+impl ShellExtension {
+    pub fn is_enabled(&self) -> bool {
+        self.blocked.is_empty()
+    }
+
+    fn clsid(&self) -> Result<&str, Box<dyn Error>> {
+        self.handler
+            .as_ref()
+            .map(|handler| handler.clsid.as_str())
+            .ok_or_else(|| format!("\"{}\" has no CLSID, it can't be blocked", self.name).into())
+    }
+}
+
+fn is_clsid(str: &str) -> bool {
+    str.len() == 38 && str.starts_with('{') && str.ends_with('}')
+}
+
+/// Which Blocked list the scope adds to. The merged view has no hive of its own,
+/// the per-user list is used as it needs no admin rights.
+pub fn block_hive(scope: Scope) -> Hive {
+    match scope {
+        Scope::Machine => Hive::LocalMachine,
+        Scope::PerUser | Scope::Merged => Hive::CurrentUser,
+    }
+}
+
+/// Lists the handlers at `key`, which points to a ContextMenuHandlers key
+pub fn read_extensions(
+    key: &Key,
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ShellExtension>, Box<dyn Error>> {
+    let location = key_path_string(&key.path);
+
+    if !reg.key_exists(key.hive, &location) {
+        return Ok(Vec::new());
+    }
+
+    Ok(reg
+        .enum_keys(key.hive, &location)?
+        .into_iter()
+        .map(|name| {
+            let path = format!("{}\\{}", location, name);
+            let clsid = reg
+                .get_string(key.hive, &path, "")
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| is_clsid(value))
+                .or_else(|| is_clsid(&name).then(|| name.clone()));
+
+            let blocked = clsid.as_ref().map_or(Vec::new(), |clsid| {
+                BLOCKABLE
+                    .into_iter()
+                    .filter(|hive| reg.get_value(*hive, BLOCKED, clsid).is_ok())
+                    .collect()
+            });
+
+            ShellExtension {
+                handler: clsid
+                    .map(|clsid| resolve(reg, key.hive, HandlerKind::ShellExtension, clsid)),
+                name,
+                hive: key.hive,
+                location: location.clone(),
+                blocked,
+            }
+        })
+        .collect())
+}
+
+/// The keys a toggle reads and writes
+pub fn toggle_keys(extension: &ShellExtension, hive: Hive) -> Vec<(Hive, String)> {
+    BLOCKABLE
+        .into_iter()
+        .filter(|blockable| *blockable == hive || extension.blocked.contains(blockable))
+        .map(|hive| (hive, BLOCKED.to_string()))
+        .collect()
+}
+
+/// Disabling adds the CLSID to the Blocked list in `hive`, the handler's key is left as it is.
+/// Enabling takes it off every list it's on.
+pub fn set_enabled(
+    extension: &ShellExtension,
+    enabled: bool,
+    hive: Hive,
+    reg: &dyn RegistryBackend,
+) -> NormalResult {
+    let clsid = extension.clsid()?;
+
+    reg.begin_transaction()?;
+
+    let result = if enabled {
+        extension
+            .blocked
+            .iter()
+            .filter(|hive| reg.get_value(**hive, BLOCKED, clsid).is_ok())
+            .try_for_each(|hive| reg.delete_value(*hive, BLOCKED, clsid))
+    } else {
+        reg.create_key(hive, BLOCKED)
+            .and_then(|()| reg.set_string(hive, BLOCKED, clsid, &extension.name))
+    };
+
+    match result {
+        Ok(()) => reg.commit_transaction(),
+        Err(err) => {
+            reg.rollback_transaction()?;
+            Err(format!("{}, nothing was changed", err).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    const CLSID: &str = "{11111111-2222-3333-4444-555555555555}";
+
+    #[test]
+    fn handlers_are_blocked_and_unblocked_by_clsid() {
+        let reg = MemoryRegistry::new();
+        let key = Key::new("Directory").with_path("Directory/shellex/ContextMenuHandlers");
+        let location = "Directory\\shellex\\ContextMenuHandlers";
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\Sharing", location))
+            .unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("{}\\Sharing", location),
+            "",
+            CLSID,
+        )
+        .unwrap();
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\Broken", location))
+            .unwrap();
+        reg.create_key(
+            Hive::ClassesRoot,
+            &format!("CLSID\\{}\\InprocServer32", CLSID),
+        )
+        .unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("CLSID\\{}\\InprocServer32", CLSID),
+            "",
+            "sharing.dll",
+        )
+        .unwrap();
+        reg.create_key(Hive::LocalMachine, BLOCKED).unwrap();
+        reg.set_string(Hive::LocalMachine, BLOCKED, CLSID, "")
+            .unwrap();
+
+        let listed = read_extensions(&key, &reg).unwrap();
+        assert_eq!(listed[0].name, "Broken");
+        assert!(listed[0].handler.is_none());
+        assert!(set_enabled(&listed[0], false, Hive::CurrentUser, &reg).is_err());
+
+        let sharing = &listed[1];
+        assert_eq!(
+            sharing.handler.as_ref().unwrap().server.as_deref(),
+            Some("sharing.dll")
+        );
+        assert_eq!(sharing.blocked, [Hive::LocalMachine]);
+
+        set_enabled(sharing, true, Hive::CurrentUser, &reg).unwrap();
+        let sharing = &read_extensions(&key, &reg).unwrap()[1];
+        assert!(sharing.is_enabled());
+
+        set_enabled(sharing, false, Hive::CurrentUser, &reg).unwrap();
+        let sharing = &read_extensions(&key, &reg).unwrap()[1];
+        assert_eq!(sharing.blocked, [Hive::CurrentUser]);
+        // The handler itself is never touched
+        assert!(reg.key_exists(Hive::ClassesRoot, &format!("{}\\Sharing", location)));
+    }
+}
@@ -0,0 +1,438 @@
+use std::{error::Error, fmt::Display};
+
+use crate::edit_context_lib::addtocontext::{convert_subkey, get_current_context_window};
+use crate::edit_context_lib::association::{AssociationSource, resolve_associations};
+use crate::edit_context_lib::order::explorer_key;
+use crate::edit_context_lib::perceived::SYSTEM_FILE_ASSOCIATIONS;
+use crate::edit_context_lib::types::{Key, StdCommand, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+
+/// What was right-clicked
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TargetKind {
+    #[default]
+    File,
+    Folder,
+    // The empty space of an open folder
+    Background,
+    Drive,
+}
+
+impl TargetKind {
+    pub const ALL: [TargetKind; 4] = [Self::File, Self::Folder, Self::Background, Self::Drive];
+
+    // The classes besides a file's associations, in the order Explorer reads them
+    fn classes(self) -> &'static [&'static str] {
+        match self {
+            Self::File => &["*", "AllFilesystemObjects"],
+            Self::Folder => &["Directory", "Folder", "AllFilesystemObjects"],
+            Self::Background => &["Directory\\Background"],
+            Self::Drive => &["Drive", "Folder"],
+        }
+    }
+}
+
+impl Display for TargetKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File => write!(f, "File"),
+            Self::Folder => write!(f, "Folder"),
+            Self::Background => write!(f, "Background"),
+            Self::Drive => write!(f, "Drive"),
+        }
+    }
+}
+
+/// A verb from one of the keys that make up the menu
+#[derive(Debug, Clone)]
+pub struct EffectiveVerb {
+    pub verb: StdCommand,
+    // The key the verb is under, such as `txtfile\shell`
+    pub source: String,
+    // Set when the verb is shown but couldn't be checked in full
+    pub note: Option<String>,
+}
+
+/// The menu Explorer builds for one item, and the verbs it leaves out with the reason
+#[derive(Debug, Clone, Default)]
+pub struct Simulation {
+    pub shown: Vec<EffectiveVerb>,
+    pub hidden: Vec<(EffectiveVerb, String)>,
+}
+
+// The parts of the item an AppliesTo condition can look at
+struct Target<'a> {
+    path: &'a str,
+    name: &'a str,
+    // With the dot, empty for folders and files without one
+    extension: &'a str,
+    kind: TargetKind,
+}
+
+impl<'a> Target<'a> {
+    fn new(path: &'a str, kind: TargetKind) -> Self {
+        let trimmed = path.trim_end_matches(['\\', '/']);
+        let name = trimmed.rsplit(['\\', '/']).next().unwrap_or(trimmed);
+        let extension = match kind {
+            TargetKind::File => name.rfind('.').map_or("", |index| &name[index..]),
+            _ => "",
+        };
+
+        Target {
+            path,
+            name,
+            extension,
+            kind,
+        }
+    }
+
+    fn property(&self, name: &str) -> Option<&str> {
+        match name.to_lowercase().as_str() {
+            "system.filename" | "system.itemname" | "system.itemnamedisplay" => Some(self.name),
+            "system.fileextension" => Some(self.extension),
+            "system.itemtype" => Some(match self.kind {
+                TargetKind::File => self.extension,
+                _ => "Directory",
+            }),
+            "system.itempathdisplay" | "system.parsingpath" => Some(self.path),
+            _ => None,
+        }
+    }
+}
+
+// `*` and `?` wildcards, without case as file names are compared
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            wildcard_match(&pattern[1..], text)
+                || (!text.is_empty() && wildcard_match(pattern, &text[1..]))
+        }
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(t)) if p.to_lowercase().eq(t.to_lowercase()) => {
+            wildcard_match(&pattern[1..], &text[1..])
+        }
+        _ => false,
+    }
+}
+
+// Splits on spaces that aren't in quotes
+fn tokens(query: &str) -> Vec<String> {
+    let (mut tokens, last, _) = query.chars().fold(
+        (Vec::new(), String::new(), false),
+        |(mut tokens, mut current, quoted), c| match c {
+            '"' => (tokens, current, !quoted),
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                (tokens, current, quoted)
+            }
+            c => {
+                current.push(c);
+                (tokens, current, quoted)
+            }
+        },
+    );
+
+    if !last.is_empty() {
+        tokens.push(last);
+    }
+
+    tokens
+}
+
+// One `Property:value` condition, None when it can't be checked
+fn term(token: &str, target: &Target) -> Option<bool> {
+    let (property, condition) = token.split_once(':')?;
+    let value = target.property(property)?;
+
+    let (negated, expected) = match condition.strip_prefix("<>") {
+        Some(expected) => (true, expected),
+        None => (false, condition.strip_prefix('=').unwrap_or(condition)),
+    };
+    let expected = expected.chars().collect::<Vec<char>>();
+
+    Some(wildcard_match(&expected, &value.chars().collect::<Vec<char>>()) != negated)
+}
+
+/// Checks an AppliesTo condition made of `Property:value` terms joined with AND, OR and NOT.
+/// None when it uses anything else, Explorer would have to be asked then.
+pub fn applies_to(query: &str, path: &str, kind: TargetKind) -> Option<bool> {
+    let target = Target::new(path, kind);
+
+    // AND binds tighter than OR, terms next to each other are ANDed
+    tokens(query)
+        .split(|token| token == "OR")
+        .map(|group| {
+            group
+                .iter()
+                .filter(|token| *token != "AND")
+                .try_fold((true, false), |(all, negate), token| {
+                    if token == "NOT" {
+                        Some((all, !negate))
+                    } else if token.contains(['(', ')']) {
+                        None
+                    } else {
+                        term(token, &target).map(|matched| (all && (matched != negate), false))
+                    }
+                })
+                .map(|(all, _)| all)
+        })
+        .try_fold(false, |any, group| group.map(|matched| any || matched))
+}
+
+// Every key that gives verbs to the item with where they came from, in the order Explorer
+// reads them. An earlier key wins when two have a verb with the same key name.
+fn sources(reg: &dyn RegistryBackend, target: &Target) -> Vec<(String, String)> {
+    let associations = match (target.kind, target.extension) {
+        (TargetKind::File, extension) if !extension.is_empty() => {
+            let key = Key::new("File")
+                .with_path("*/shell")
+                .with_props("HasExt")
+                .with_extension(extension);
+            let mut associations = resolve_associations(reg, &key);
+
+            // The program the user picked comes before the extension's own ProgID
+            associations.sort_by_key(|association| match association.source {
+                AssociationSource::UserChoice(_) => 0,
+                AssociationSource::ProgId(..) => 1,
+                AssociationSource::Extension => 2,
+                AssociationSource::SystemFileAssociations => 3,
+            });
+
+            let perceived = reg
+                .get_string(Hive::ClassesRoot, extension, "PerceivedType")
+                .ok()
+                .filter(|perceived| !perceived.is_empty())
+                .map(|perceived| {
+                    (
+                        format!("{}\\{}\\shell", SYSTEM_FILE_ASSOCIATIONS, perceived),
+                        format!("Perceived type {}", perceived),
+                    )
+                });
+
+            associations
+                .into_iter()
+                .map(|association| {
+                    (
+                        key_path_string(&association.key.path),
+                        association.source.to_string(),
+                    )
+                })
+                .chain(perceived)
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    associations
+        .into_iter()
+        .chain(
+            target
+                .kind
+                .classes()
+                .iter()
+                .map(|class| (format!("{}\\shell", class), class.to_string())),
+        )
+        .filter(|(path, _)| reg.key_exists(Hive::ClassesRoot, path))
+        .collect()
+}
+
+// Why Explorer leaves a verb out of the menu, if it does
+fn hidden_reason(
+    verb: &EffectiveVerb,
+    shown: &[EffectiveVerb],
+    target: &Target,
+    extended: bool,
+) -> Result<Option<String>, String> {
+    let attributes = &verb.verb.attributes;
+
+    if let Some(earlier) = shown
+        .iter()
+        .find(|earlier| earlier.verb.folder.eq_ignore_ascii_case(&verb.verb.folder))
+    {
+        return Ok(Some(format!(
+            "Overridden by the verb in {}",
+            earlier.source
+        )));
+    }
+
+    if attributes.disabled {
+        Ok(Some("Disabled with LegacyDisable".to_string()))
+    } else if attributes.programmatic_access_only {
+        Ok(Some(
+            "ProgrammaticAccessOnly, only programs can call it".to_string(),
+        ))
+    } else if attributes.extended && !extended {
+        Ok(Some("Extended, only shown with Shift held".to_string()))
+    } else {
+        match attributes
+            .applies_to
+            .as_ref()
+            .map(|query| (query, applies_to(query, target.path, target.kind)))
+        {
+            Some((query, Some(false))) => Ok(Some(format!("AppliesTo {} doesn't match", query))),
+            Some((query, None)) => Err(format!("AppliesTo {} couldn't be checked", query)),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// The verbs Explorer shows for `path` in the merged view, `extended` as if Shift is held.
+/// Verbs are in the order their keys are read, each key's sorted as Explorer sorts them,
+/// with Top and Bottom verbs moved to either end.
+pub fn simulate(
+    reg: &dyn RegistryBackend,
+    path: &str,
+    kind: TargetKind,
+    extended: bool,
+) -> Result<Simulation, Box<dyn Error>> {
+    let target = Target::new(path, kind);
+
+    let mut simulation = sources(reg, &target).into_iter().try_fold(
+        Simulation::default(),
+        |simulation, (location, source)| {
+            let parts = location.split('\\').collect::<Vec<&str>>();
+            let mut verbs =
+                get_current_context_window(convert_subkey(&parts), reg, Hive::ClassesRoot)?;
+            verbs.sort_by_key(|verb| explorer_key(verb).1);
+
+            Ok::<_, Box<dyn Error>>(verbs.into_iter().fold(simulation, |mut simulation, verb| {
+                let verb = EffectiveVerb {
+                    verb,
+                    source: source.clone(),
+                    note: None,
+                };
+
+                match hidden_reason(&verb, &simulation.shown, &target, extended) {
+                    Ok(Some(reason)) => simulation.hidden.push((verb, reason)),
+                    Ok(None) => simulation.shown.push(verb),
+                    Err(note) => simulation.shown.push(EffectiveVerb {
+                        note: Some(note),
+                        ..verb
+                    }),
+                }
+
+                simulation
+            }))
+        },
+    )?;
+
+    simulation
+        .shown
+        .sort_by_key(|shown| explorer_key(&shown.verb).0);
+
+    Ok(simulation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    fn verb(reg: &MemoryRegistry, location: &str, name: &str, values: &[(&str, &str)]) {
+        let key = format!("{}\\{}", location, name);
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", key))
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, &key, "", name).unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("{}\\command", key),
+            "",
+            "tool.exe",
+        )
+        .unwrap();
+        values.iter().for_each(|(value, data)| {
+            reg.set_string(Hive::ClassesRoot, &key, value, data)
+                .unwrap()
+        });
+    }
+
+    #[test]
+    fn the_menu_merges_every_key_and_filters_what_explorer_hides() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, ".rs").unwrap();
+        reg.set_string(Hive::ClassesRoot, ".rs", "", "RustFile")
+            .unwrap();
+        reg.set_string(Hive::ClassesRoot, ".rs", "PerceivedType", "text")
+            .unwrap();
+
+        verb(&reg, "RustFile\\shell", "open", &[]);
+        verb(&reg, "*\\shell", "open", &[]);
+        verb(&reg, "*\\shell", "Zip", &[("Position", "Top")]);
+        verb(&reg, "*\\shell", "Shred", &[("Extended", "")]);
+        verb(
+            &reg,
+            "*\\shell",
+            "Hidden",
+            &[("ProgrammaticAccessOnly", "")],
+        );
+        verb(
+            &reg,
+            "*\\shell",
+            "Build",
+            &[("AppliesTo", "System.FileName:\"*.rs\"")],
+        );
+        verb(
+            &reg,
+            "*\\shell",
+            "Print",
+            &[("AppliesTo", "System.FileExtension:=.txt")],
+        );
+        verb(&reg, "SystemFileAssociations\\text\\shell", "edit", &[]);
+        verb(&reg, "AllFilesystemObjects\\shell", "Copy", &[]);
+
+        let simulation = simulate(&reg, "C:\\repo\\main.rs", TargetKind::File, false).unwrap();
+        let shown = simulation
+            .shown
+            .iter()
+            .map(|shown| (shown.verb.folder.as_str(), shown.source.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+
+        assert_eq!(
+            shown,
+            [
+                ("Zip", "*"),
+                ("open", "ProgID RustFile"),
+                ("edit", "Perceived type text"),
+                ("Build", "*"),
+                ("Copy", "AllFilesystemObjects"),
+            ]
+        );
+        assert_eq!(simulation.hidden.len(), 4);
+
+        let extended = simulate(&reg, "C:\\repo\\main.rs", TargetKind::File, true).unwrap();
+        assert!(
+            extended
+                .shown
+                .iter()
+                .any(|shown| shown.verb.folder == "Shred")
+        );
+
+        let folder = simulate(&reg, "C:\\repo", TargetKind::Folder, false).unwrap();
+        assert_eq!(folder.shown.len(), 1);
+    }
+
+    #[test]
+    fn applies_to_handles_and_or_and_not() {
+        let query =
+            "System.FileName:\"*.rs\" OR NOT System.ItemType:=Directory AND System.FileName:a*";
+
+        assert_eq!(
+            applies_to(query, "C:\\main.rs", TargetKind::File),
+            Some(true)
+        );
+        assert_eq!(applies_to(query, "C:\\a.txt", TargetKind::File), Some(true));
+        assert_eq!(
+            applies_to(query, "C:\\b.txt", TargetKind::File),
+            Some(false)
+        );
+        assert_eq!(
+            applies_to("System.Size:>10", "C:\\a.txt", TargetKind::File),
+            None
+        );
+    }
+}
@@ -0,0 +1,581 @@
+use std::{collections::HashSet, error::Error, fmt::Display};
+
+use crate::edit_context_lib::addtocontext::{add_to_context_window, remove_from_context_window};
+use crate::edit_context_lib::attributes::{write_disabled, write_position};
+use crate::edit_context_lib::types::{KeyPath, NormalResult, StdCommand, key_path_string};
+use crate::registry_io::backend::{Hive, RegistryBackend};
+use crate::registry_io::memory::MemoryRegistry;
+use crate::registry_io::tree::RegTree;
+use uuid::Uuid;
+
+/// An edit that has been made in the UI but not written to the registry yet
+#[derive(Debug, Clone)]
+pub enum PendingChange {
+    Add(StdCommand),
+    Edit(StdCommand),
+    Remove(StdCommand),
+    // Only writes LegacyDisable, as the command's attributes have it
+    SetDisabled(StdCommand),
+    // Only writes Position, as the command's attributes have it
+    SetPosition(StdCommand),
+    // Moves the whole key from the folder given to the command's folder
+    Rename(StdCommand, String),
+    // Moves the whole key of the command, as it is now, into the location given
+    Move(StdCommand, KeyPath),
+}
+
+impl PendingChange {
+    pub fn command(&self) -> &StdCommand {
+        match self {
+            Self::Add(cmd)
+            | Self::Edit(cmd)
+            | Self::Remove(cmd)
+            | Self::SetDisabled(cmd)
+            | Self::SetPosition(cmd)
+            | Self::Rename(cmd, _)
+            | Self::Move(cmd, _) => cmd,
+        }
+    }
+
+    fn apply(&self, reg: &dyn RegistryBackend) -> NormalResult {
+        match self {
+            Self::Add(cmd) | Self::Edit(cmd) => add_to_context_window(cmd, reg, cmd.hive),
+            Self::Remove(cmd) => remove_from_context_window(cmd, reg, cmd.hive),
+            Self::SetDisabled(cmd) => {
+                write_disabled(reg, cmd.hive, &self.verb_key(), cmd.attributes.disabled)
+            }
+            Self::SetPosition(cmd) => {
+                write_position(reg, cmd.hive, &self.verb_key(), cmd.attributes.position)
+            }
+            Self::Rename(cmd, from) => rename_key(
+                reg,
+                cmd.hive,
+                &format!("{}\\{}", self.location(), from),
+                &self.verb_key(),
+            ),
+            Self::Move(cmd, _) => rename_key(
+                reg,
+                cmd.hive,
+                &self.verb_key(),
+                &self.moved_to().unwrap_or_default(),
+            ),
+        }
+    }
+
+    // Where a move puts the verb's key
+    fn moved_to(&self) -> Option<String> {
+        match self {
+            Self::Move(cmd, to) => Some(format!("{}\\{}", key_path_string(to), cmd.folder)),
+            _ => None,
+        }
+    }
+
+    /// The folder the verb had before this change, if the change renames it
+    pub fn renamed_from(&self) -> Option<&str> {
+        match self {
+            Self::Rename(_, from) => Some(from),
+            _ => None,
+        }
+    }
+
+    fn hive(&self) -> Hive {
+        self.command().hive
+    }
+
+    /// The verb's keys, everything the change writes is under them
+    pub fn keys(&self) -> Vec<(Hive, String)> {
+        self.verb_keys()
+            .into_iter()
+            .map(|key| (self.hive(), key))
+            .collect()
+    }
+
+    fn location(&self) -> String {
+        key_path_string(&self.command().path)
+    }
+
+    fn verb_key(&self) -> String {
+        format!("{}\\{}", self.location(), self.command().folder)
+    }
+
+    // A rename touches the old key as well, a move the new one
+    fn verb_keys(&self) -> Vec<String> {
+        let old = self
+            .renamed_from()
+            .map(|from| format!("{}\\{}", self.location(), from));
+
+        [Some(self.verb_key()), old, self.moved_to()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// Copies the key and everything under it to `to`, then deletes it.
+/// Refuses when `to` already exists, nothing is overwritten.
+pub fn rename_key(reg: &dyn RegistryBackend, hive: Hive, from: &str, to: &str) -> NormalResult {
+    // Key names ignore case, a new case is given by going through another name
+    if from.eq_ignore_ascii_case(to) {
+        if from == to {
+            return Ok(());
+        }
+
+        let temporary = format!("{}.{}", to, Uuid::new_v4().simple());
+        rename_key(reg, hive, from, &temporary)?;
+        return rename_key(reg, hive, &temporary, to);
+    }
+
+    if reg.key_exists(hive, to) {
+        return Err(format!("{}\\{} already exists", hive, to).into());
+    }
+
+    RegTree::read(reg, hive, from)?.write(reg, hive, to)?;
+    reg.delete_key_all(hive, from)
+}
+
+impl Display for PendingChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Add(cmd) => write!(f, "Add \"{}\"", cmd.title),
+            Self::Edit(cmd) => write!(f, "Edit \"{}\"", cmd.title),
+            Self::Remove(cmd) => write!(f, "Remove \"{}\"", cmd.title),
+            Self::SetDisabled(cmd) if cmd.attributes.disabled => {
+                write!(f, "Disable \"{}\"", cmd.title)
+            }
+            Self::SetDisabled(cmd) => write!(f, "Enable \"{}\"", cmd.title),
+            Self::SetPosition(cmd) => match cmd.attributes.position {
+                Some(position) => write!(
+                    f,
+                    "Show \"{}\" at the {}",
+                    cmd.title,
+                    position.to_string().to_lowercase()
+                ),
+                None => write!(f, "Clear the position of \"{}\"", cmd.title),
+            },
+            Self::Rename(cmd, from) => write!(f, "Rename {} to {}", from, cmd.folder),
+            Self::Move(cmd, to) => write!(f, "Move \"{}\" to {}", cmd.title, key_path_string(to)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreviewAction {
+    Create,
+    Overwrite,
+    Delete,
+}
+
+/// A single key or value that applying the pending changes would touch
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangePreview {
+    pub action: PreviewAction,
+    pub key: String,
+    // None when the line is about the key itself
+    pub value: Option<String>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl Display for ChangePreview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {}", self.action, self.key)?;
+
+        if let Some(value) = &self.value {
+            let value = if value.is_empty() { "(Default)" } else { value };
+            write!(f, " [{}]", value)?;
+        }
+
+        match (&self.old, &self.new) {
+            (Some(old), Some(new)) => write!(f, ": {} -> {}", old, new),
+            (None, Some(new)) => write!(f, ": {}", new),
+            (Some(old), None) => write!(f, ": was {}", old),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn line(
+    action: PreviewAction,
+    key: &str,
+    value: Option<&str>,
+    old: Option<String>,
+    new: Option<String>,
+) -> ChangePreview {
+    ChangePreview {
+        action,
+        key: key.to_string(),
+        value: value.map(str::to_string),
+        old,
+        new,
+    }
+}
+
+/// Lists what has to be created, overwritten or deleted to turn `before` into `after`
+pub fn diff_tree(
+    key: &str,
+    before: Option<&RegTree>,
+    after: Option<&RegTree>,
+    output: &mut Vec<ChangePreview>,
+) {
+    match (before, after) {
+        (None, Some(_)) => output.push(line(PreviewAction::Create, key, None, None, None)),
+        (Some(_), None) => output.push(line(PreviewAction::Delete, key, None, None, None)),
+        _ => {}
+    }
+
+    let empty = RegTree::default();
+    let before = before.unwrap_or(&empty);
+    let after = after.unwrap_or(&empty);
+
+    after
+        .values
+        .iter()
+        .for_each(|(name, new)| match before.value(name) {
+            None => output.push(line(
+                PreviewAction::Create,
+                key,
+                Some(name),
+                None,
+                Some(new.to_string()),
+            )),
+            Some(old) if old != new => output.push(line(
+                PreviewAction::Overwrite,
+                key,
+                Some(name),
+                Some(old.to_string()),
+                Some(new.to_string()),
+            )),
+            _ => {}
+        });
+
+    before
+        .values
+        .iter()
+        .filter(|(name, _)| after.value(name).is_none())
+        .for_each(|(name, old)| {
+            output.push(line(
+                PreviewAction::Delete,
+                key,
+                Some(name),
+                Some(old.to_string()),
+                None,
+            ))
+        });
+
+    after.subkeys.iter().for_each(|(name, sub)| {
+        diff_tree(
+            &format!("{}\\{}", key, name),
+            before.subkey(name),
+            Some(sub),
+            output,
+        )
+    });
+
+    before
+        .subkeys
+        .iter()
+        .filter(|(name, _)| after.subkey(name).is_none())
+        .for_each(|(name, sub)| diff_tree(&format!("{}\\{}", key, name), Some(sub), None, output));
+}
+
+// Copies the parts of the live registry a change touches into the simulation. Each verb is
+// only copied the first time, after that the simulation holds the result of earlier changes.
+fn load_into(
+    sim: &MemoryRegistry,
+    reg: &dyn RegistryBackend,
+    change: &PendingChange,
+    loaded: &mut HashSet<(Hive, String)>,
+) -> NormalResult {
+    let hive = change.hive();
+    let location = change.location();
+
+    if reg.key_exists(hive, &location) {
+        sim.create_key(hive, &location)?;
+    }
+
+    change
+        .verb_keys()
+        .into_iter()
+        .filter(|verb_key| loaded.insert((hive, verb_key.to_lowercase())))
+        .filter(|verb_key| reg.key_exists(hive, verb_key))
+        .try_for_each(|verb_key| RegTree::read(reg, hive, &verb_key)?.write(sim, hive, &verb_key))
+}
+
+/// Works out every key and value the changes would create, overwrite or delete,
+/// by playing them against an in-memory copy of the keys they touch.
+/// The live registry is only read from.
+pub fn preview_changes(
+    changes: &[PendingChange],
+    reg: &dyn RegistryBackend,
+) -> Result<Vec<ChangePreview>, Box<dyn Error>> {
+    let sim = MemoryRegistry::new();
+    let mut loaded = HashSet::new();
+
+    changes.iter().try_fold(Vec::new(), |mut output, change| {
+        load_into(&sim, reg, change, &mut loaded)?;
+
+        let hive = change.hive();
+        let verb_keys = change.verb_keys();
+        let before = verb_keys
+            .iter()
+            .map(|verb_key| RegTree::read(&sim, hive, verb_key).ok())
+            .collect::<Vec<Option<RegTree>>>();
+
+        change.apply(&sim)?;
+
+        verb_keys.iter().zip(before).for_each(|(verb_key, before)| {
+            let after = RegTree::read(&sim, hive, verb_key).ok();
+
+            diff_tree(
+                &format!("{}\\{}", hive, verb_key),
+                before.as_ref(),
+                after.as_ref(),
+                &mut output,
+            )
+        });
+
+        Ok(output)
+    })
+}
+
+/// Applies every change inside one transaction, if any of them fails none are kept
+pub fn apply_changes(changes: &[PendingChange], reg: &dyn RegistryBackend) -> NormalResult {
+    reg.begin_transaction()?;
+
+    match changes.iter().try_for_each(|change| change.apply(reg)) {
+        Ok(()) => reg.commit_transaction(),
+        Err(err) => {
+            reg.rollback_transaction()?;
+            Err(format!("{}, no changes were made", err).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{DIRECTORY_SUBKEY, convert_subkey};
+    use crate::registry_io::backend::{RegData, RegDataType};
+
+    fn command(title: &str, command: &str) -> StdCommand {
+        StdCommand::new(
+            title.to_string(),
+            "cmd.exe".to_string(),
+            command.to_string(),
+            title.replace(" ", ""),
+            convert_subkey(&DIRECTORY_SUBKEY),
+        )
+    }
+
+    #[test]
+    fn preview_lists_created_overwritten_and_deleted_entries() {
+        let reg = MemoryRegistry::new();
+        let existing = command("Old Tool", "old.exe");
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+        add_to_context_window(&existing, &reg, Hive::ClassesRoot).unwrap();
+
+        let mut edited = existing.clone();
+        edited.command = "new.exe".to_string();
+
+        let changes = [
+            PendingChange::Add(command("New Tool", "tool.exe")),
+            PendingChange::Edit(edited),
+            PendingChange::Remove(existing),
+        ];
+
+        let preview = preview_changes(&changes, &reg).unwrap();
+
+        assert!(preview.contains(&line(
+            PreviewAction::Create,
+            "HKEY_CLASSES_ROOT\\Directory\\shell\\NewTool\\command",
+            None,
+            None,
+            None,
+        )));
+        assert!(preview.contains(&line(
+            PreviewAction::Overwrite,
+            "HKEY_CLASSES_ROOT\\Directory\\shell\\OldTool\\command",
+            Some(""),
+            Some("\"old.exe\"".to_string()),
+            Some("\"new.exe\"".to_string()),
+        )));
+        assert!(preview.contains(&line(
+            PreviewAction::Delete,
+            "HKEY_CLASSES_ROOT\\Directory\\shell\\OldTool",
+            None,
+            None,
+            None,
+        )));
+
+        // Previewing never touches the real registry
+        assert!(!reg.key_exists(Hive::ClassesRoot, "Directory\\shell\\NewTool"));
+        assert!(reg.key_exists(Hive::ClassesRoot, "Directory\\shell\\OldTool"));
+    }
+
+    #[test]
+    fn failed_apply_rolls_back_every_change() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+
+        let mut missing_location = command("Broken", "broken.exe");
+        missing_location.path = convert_subkey(&["Nowhere", "shell"]);
+
+        let changes = [
+            PendingChange::Add(command("New Tool", "tool.exe")),
+            PendingChange::Add(missing_location),
+        ];
+
+        assert!(apply_changes(&changes, &reg).is_err());
+        assert!(!reg.key_exists(Hive::ClassesRoot, "Directory\\shell\\NewTool"));
+
+        apply_changes(&changes[..1], &reg).unwrap();
+        assert!(reg.key_exists(Hive::ClassesRoot, "Directory\\shell\\NewTool"));
+    }
+
+    #[test]
+    fn disabling_only_writes_legacy_disable() {
+        let reg = MemoryRegistry::new();
+        let verb = "Directory\\shell\\Tool";
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+        let mut tool = command("Tool", "tool.exe");
+        add_to_context_window(&tool, &reg, Hive::ClassesRoot).unwrap();
+        reg.set_string(Hive::ClassesRoot, verb, "Unknown", "kept")
+            .unwrap();
+
+        tool.attributes.disabled = true;
+        apply_changes(&[PendingChange::SetDisabled(tool.clone())], &reg).unwrap();
+        assert!(
+            reg.get_value(Hive::ClassesRoot, verb, "LegacyDisable")
+                .is_ok()
+        );
+
+        tool.attributes.disabled = false;
+        apply_changes(&[PendingChange::SetDisabled(tool)], &reg).unwrap();
+        assert!(
+            reg.get_value(Hive::ClassesRoot, verb, "LegacyDisable")
+                .is_err()
+        );
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, verb, "Unknown").unwrap(),
+            "kept"
+        );
+    }
+
+    #[test]
+    fn rename_moves_the_whole_key_and_never_overwrites() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell")
+            .unwrap();
+        let tool = command("Tool", "tool.exe");
+        add_to_context_window(&tool, &reg, Hive::ClassesRoot).unwrap();
+        add_to_context_window(&command("Taken", "taken.exe"), &reg, Hive::ClassesRoot).unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            "Directory\\shell\\Tool",
+            "Unknown",
+            "kept",
+        )
+        .unwrap();
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\Tool\\DropTarget")
+            .unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            "Directory\\shell\\Tool\\DropTarget",
+            "CLSID",
+            "{nested}",
+        )
+        .unwrap();
+
+        let mut taken = tool.clone();
+        taken.folder = "Taken".to_string();
+        assert!(apply_changes(&[PendingChange::Rename(taken, "Tool".to_string())], &reg).is_err());
+
+        let mut renamed = tool.clone();
+        renamed.folder = "01-Tool".to_string();
+        apply_changes(&[PendingChange::Rename(renamed, "Tool".to_string())], &reg).unwrap();
+
+        assert!(!reg.key_exists(Hive::ClassesRoot, "Directory\\shell\\Tool"));
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, "Directory\\shell\\01-Tool", "Unknown")
+                .unwrap(),
+            "kept"
+        );
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, "Directory\\shell\\01-Tool\\command", "")
+                .unwrap(),
+            "tool.exe"
+        );
+        assert_eq!(
+            reg.get_string(
+                Hive::ClassesRoot,
+                "Directory\\shell\\01-Tool\\DropTarget",
+                "CLSID"
+            )
+            .unwrap(),
+            "{nested}"
+        );
+    }
+
+    #[test]
+    fn renames_move_the_whole_key_and_never_overwrite() {
+        let reg = MemoryRegistry::new();
+        let tool = "Directory\\shell\\mytool";
+        let odd = RegData {
+            bytes: vec![1, 2, 3],
+            vtype: RegDataType::Unknown(0x30),
+        };
+
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\command", tool))
+            .unwrap();
+        reg.create_key(Hive::ClassesRoot, &format!("{}\\DropTarget", tool))
+            .unwrap();
+        reg.set_value(Hive::ClassesRoot, tool, "Odd", &odd).unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            &format!("{}\\command", tool),
+            "",
+            "tool.exe",
+        )
+        .unwrap();
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\Other")
+            .unwrap();
+
+        let mut cmd = command("My Tool", "tool.exe");
+        cmd.folder = "MyTool".to_string();
+        apply_changes(
+            &[PendingChange::Rename(cmd.clone(), "mytool".to_string())],
+            &reg,
+        )
+        .unwrap();
+
+        // Only the case changed, the key keeps everything it held
+        assert_eq!(
+            reg.enum_keys(Hive::ClassesRoot, "Directory\\shell")
+                .unwrap()
+                .into_iter()
+                .filter(|name| name.eq_ignore_ascii_case("mytool"))
+                .collect::<Vec<String>>(),
+            ["MyTool"]
+        );
+        let moved = "Directory\\shell\\MyTool";
+        assert_eq!(reg.get_value(Hive::ClassesRoot, moved, "Odd").unwrap(), odd);
+        assert!(reg.key_exists(Hive::ClassesRoot, &format!("{}\\DropTarget", moved)));
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, &format!("{}\\command", moved), "")
+                .unwrap(),
+            "tool.exe"
+        );
+
+        let mut onto = cmd.clone();
+        onto.folder = "Other".to_string();
+        assert!(apply_changes(&[PendingChange::Rename(onto, "MyTool".to_string())], &reg).is_err());
+        assert!(reg.key_exists(Hive::ClassesRoot, moved));
+    }
+}
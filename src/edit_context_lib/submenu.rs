@@ -0,0 +1,497 @@
+use std::{cell::RefCell, fmt::Display, rc::Rc};
+
+use crate::edit_context_lib::addtocontext::{add_to_context_window, read_command_at, unreadable};
+use crate::edit_context_lib::attributes::{read_string, write_value};
+use crate::edit_context_lib::scope::find_classes_key;
+use crate::edit_context_lib::types::{Key, KeyPath, NormalResult, StdCommand, key_path_string};
+use crate::registry_io::backend::{Hive, RegData, RegistryBackend};
+
+const SUB_COMMANDS: &str = "SubCommands";
+const EXTENDED_SUB_COMMANDS_KEY: &str = "ExtendedSubCommandsKey";
+
+/// Where `SubCommands` lists point to, always in the machine hive
+pub const COMMAND_STORE: [&str; 7] = [
+    "Software",
+    "Microsoft",
+    "Windows",
+    "CurrentVersion",
+    "Explorer",
+    "CommandStore",
+    "shell",
+];
+
+// Submenus can point at each other, reading stops this far down
+const MAX_DEPTH: usize = 8;
+
+/// Where the entries of a cascading menu are kept
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubMenuSource {
+    // SubCommands set to "", with the entries in a shell key under the verb
+    Nested,
+    // ExtendedSubCommandsKey, a key under the classes root with a shell key of its own
+    Shared(String),
+    // SubCommands listing entries of the CommandStore by name
+    CommandStore(Vec<String>),
+}
+
+impl Display for SubMenuSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Nested => write!(f, "Submenu"),
+            Self::Shared(key) => write!(f, "Submenu from {}", key),
+            Self::CommandStore(_) => write!(f, "Submenu from the CommandStore"),
+        }
+    }
+}
+
+/// A cascading menu along with the entries it shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubMenu {
+    pub source: SubMenuSource,
+    pub children: Vec<StdCommand>,
+}
+
+impl SubMenu {
+    /// Only nested entries belong to the verb, the others are shared with other menus
+    pub fn is_editable(&self) -> bool {
+        self.source == SubMenuSource::Nested
+    }
+}
+
+fn to_key_path<'a>(parts: impl Iterator<Item = &'a str>) -> KeyPath {
+    Rc::new(RefCell::new(parts.map(Box::from).collect()))
+}
+
+/// The shell key holding the nested entries of `cmd`
+pub fn nested_path(cmd: &StdCommand) -> KeyPath {
+    let path = cmd.path.borrow();
+
+    to_key_path(
+        path.iter()
+            .map(AsRef::as_ref)
+            .chain([cmd.folder.as_str(), "shell"]),
+    )
+}
+
+fn source(reg: &dyn RegistryBackend, hive: Hive, verb: &str) -> Option<SubMenuSource> {
+    let shared = read_string(reg, hive, verb, EXTENDED_SUB_COMMANDS_KEY)
+        .filter(|shared| !shared.is_empty())
+        .map(SubMenuSource::Shared);
+
+    shared.or_else(|| {
+        read_string(reg, hive, verb, SUB_COMMANDS).map(|list| match list.trim() {
+            "" => SubMenuSource::Nested,
+            list => SubMenuSource::CommandStore(
+                list.split(';')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+        })
+    })
+}
+
+// A shared key is relative to the classes root
+fn shared_location(reg: &dyn RegistryBackend, hive: Hive, shared: &str) -> (Hive, KeyPath) {
+    let (hive, path) = find_classes_key(reg, hive, &format!("{}\\shell", shared));
+
+    (hive, to_key_path(path.split('\\')))
+}
+
+fn read_children(
+    path: KeyPath,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    depth: usize,
+) -> Vec<StdCommand> {
+    let location = key_path_string(&path);
+
+    reg.enum_keys(hive, &location)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| {
+            read_command_at(Rc::clone(&path), name.clone(), reg, hive, depth)
+                .unwrap_or_else(|err| unreadable(Rc::clone(&path), name, hive, err))
+        })
+        .collect()
+}
+
+/// Reads the submenu of `cmd`, None for a plain verb
+pub(super) fn read_submenu(
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    cmd: &StdCommand,
+    depth: usize,
+) -> Option<SubMenu> {
+    let verb = format!("{}\\{}", key_path_string(&cmd.path), cmd.folder);
+    let source = source(reg, hive, &verb)?;
+
+    let children = match &source {
+        _ if depth >= MAX_DEPTH => Vec::new(),
+        SubMenuSource::Nested => read_children(nested_path(cmd), reg, hive, depth + 1),
+        SubMenuSource::Shared(shared) => {
+            let (hive, path) = shared_location(reg, hive, shared);
+            read_children(path, reg, hive, depth + 1)
+        }
+        SubMenuSource::CommandStore(names) => {
+            let store = to_key_path(COMMAND_STORE.into_iter());
+
+            names
+                .iter()
+                .map(|name| {
+                    read_command_at(
+                        Rc::clone(&store),
+                        name.clone(),
+                        reg,
+                        Hive::LocalMachine,
+                        depth + 1,
+                    )
+                    .unwrap_or_else(|err| {
+                        unreadable(Rc::clone(&store), name.clone(), Hive::LocalMachine, err)
+                    })
+                })
+                .collect()
+        }
+    };
+
+    Some(SubMenu { source, children })
+}
+
+/// Writes the values pointing to the submenu. Nested entries are written under the verb and
+/// nested keys that aren't in the list anymore are removed. Shared entries are left alone.
+pub(super) fn write_submenu(
+    submenu: Option<&SubMenu>,
+    reg: &dyn RegistryBackend,
+    hive: Hive,
+    verb: &str,
+) -> NormalResult {
+    let (sub_commands, shared) = match submenu.map(|submenu| &submenu.source) {
+        None => (None, None),
+        Some(SubMenuSource::Nested) => (Some(String::new()), None),
+        Some(SubMenuSource::Shared(shared)) => (None, Some(shared.clone())),
+        Some(SubMenuSource::CommandStore(names)) => (Some(names.join(";")), None),
+    };
+
+    write_value(reg, hive, verb, SUB_COMMANDS, sub_commands.map(RegData::sz))?;
+    write_value(
+        reg,
+        hive,
+        verb,
+        EXTENDED_SUB_COMMANDS_KEY,
+        shared.map(RegData::sz),
+    )?;
+
+    let Some(submenu) = submenu.filter(|submenu| submenu.is_editable()) else {
+        return Ok(());
+    };
+
+    let shell = format!("{}\\shell", verb);
+    reg.create_key(hive, &shell)?;
+
+    reg.enum_keys(hive, &shell)?
+        .into_iter()
+        .filter(|name| {
+            !submenu
+                .children
+                .iter()
+                .any(|child| child.folder.eq_ignore_ascii_case(name))
+        })
+        .try_for_each(|name| reg.delete_key_all(hive, &format!("{}\\{}", shell, name)))?;
+
+    // Entries moved in from elsewhere still point to where they came from
+    let shell = to_key_path(shell.split('\\'));
+
+    submenu
+        .children
+        .iter()
+        .filter(|child| {
+            read_command_at(Rc::clone(&shell), child.folder.clone(), reg, hive, 0)
+                .map_or(true, |written| !same_entry(&written, child))
+        })
+        .try_for_each(|child| {
+            let mut child = child.clone();
+            child.path = Rc::clone(&shell);
+
+            add_to_context_window(&child, reg, hive)
+        })
+}
+
+// Whether `a` and `b` would write the same values, entries are otherwise compared by id
+fn same_entry(a: &StdCommand, b: &StdCommand) -> bool {
+    let same_submenu = match (&a.submenu, &b.submenu) {
+        (None, None) => true,
+        (Some(a), Some(b)) => {
+            a.source == b.source
+                && a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(&b.children)
+                    .all(|(a, b)| a.folder == b.folder && same_entry(a, b))
+        }
+        _ => false,
+    };
+
+    a.title == b.title
+        && a.icon == b.icon
+        && a.command == b.command
+        && a.attributes == b.attributes
+        && same_submenu
+}
+
+/// A new submenu at `location` listing `verbs`, their keys are moved into it separately
+pub fn group_into_submenu(
+    title: String,
+    folder: String,
+    location: &Key,
+    verbs: &[StdCommand],
+) -> StdCommand {
+    let mut group = StdCommand::new(
+        title,
+        String::new(),
+        String::new(),
+        folder,
+        location.clone_path(),
+    )
+    .with_hive(location.hive);
+
+    let shell = nested_path(&group);
+    let children = verbs
+        .iter()
+        .cloned()
+        .map(|mut cmd| {
+            cmd.path = Rc::clone(&shell);
+            cmd.hive = location.hive;
+            cmd.origin = None;
+            cmd
+        })
+        .collect();
+
+    group.submenu = Some(SubMenu {
+        source: SubMenuSource::Nested,
+        children,
+    });
+    group
+}
+
+/// Adds `child` to the nested entries of `parent`, turning it into a submenu if needed
+pub fn with_child(parent: &StdCommand, mut child: StdCommand) -> StdCommand {
+    let mut parent = parent.clone();
+    child.path = nested_path(&parent);
+    child.hive = parent.hive;
+
+    match parent.submenu.as_mut() {
+        Some(submenu) => submenu.children.push(child),
+        None => {
+            parent.submenu = Some(SubMenu {
+                source: SubMenuSource::Nested,
+                children: vec![child],
+            })
+        }
+    }
+
+    parent
+}
+
+/// `parent` without the nested entry found by following `at` down the tree
+pub fn without_child(parent: &StdCommand, at: &[usize]) -> StdCommand {
+    let mut parent = parent.clone();
+
+    if let Some((last, path)) = at.split_last() {
+        let submenu = path
+            .iter()
+            .try_fold(parent.submenu.as_mut(), |submenu, index| {
+                submenu?
+                    .children
+                    .get_mut(*index)
+                    .map(|child| child.submenu.as_mut())
+            });
+
+        if let Some(Some(submenu)) = submenu
+            && *last < submenu.children.len()
+        {
+            submenu.children.remove(*last);
+        }
+    }
+
+    parent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit_context_lib::addtocontext::{get_current_context_window, read_command};
+    use crate::edit_context_lib::problem::VerbProblem;
+    use crate::edit_context_lib::staging::{PendingChange, apply_changes};
+    use crate::registry_io::backend::RegDataType;
+    use crate::registry_io::memory::MemoryRegistry;
+
+    fn add_verb(reg: &MemoryRegistry, hive: Hive, path: &str, title: &str) {
+        reg.create_key(hive, &format!("{}\\command", path)).unwrap();
+        reg.set_string(hive, path, "", title).unwrap();
+        reg.set_string(hive, path, "Icon", "").unwrap();
+        reg.set_string(hive, &format!("{}\\command", path), "", "tool.exe")
+            .unwrap();
+    }
+
+    fn titles(cmd: &StdCommand) -> Vec<&str> {
+        cmd.submenu
+            .iter()
+            .flat_map(|submenu| &submenu.children)
+            .map(|child| child.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn reads_every_kind_of_submenu_and_groups_verbs() {
+        let reg = MemoryRegistry::new();
+        let root = Hive::ClassesRoot;
+        let store = COMMAND_STORE.join("\\");
+        add_verb(
+            &reg,
+            Hive::LocalMachine,
+            &format!("{}\\Stored", store),
+            "Stored",
+        );
+
+        for (name, value, data) in [
+            ("Nested", SUB_COMMANDS, ""),
+            (
+                "Shared",
+                EXTENDED_SUB_COMMANDS_KEY,
+                "Directory\\Menus\\Tools",
+            ),
+            ("Store", SUB_COMMANDS, "Stored;Missing"),
+        ] {
+            let path = format!("Directory\\shell\\{}", name);
+            reg.create_key(root, &path).unwrap();
+            reg.set_string(root, &path, "", name).unwrap();
+            reg.set_string(root, &path, "Icon", "").unwrap();
+            reg.set_string(root, &path, value, data).unwrap();
+        }
+        add_verb(
+            &reg,
+            root,
+            "Directory\\shell\\Nested\\shell\\Inner",
+            "Inner",
+        );
+        add_verb(&reg, root, "Directory\\Menus\\Tools\\shell\\Tool", "Tool");
+
+        let location = Key::new("Directory").with_path("Directory/shell");
+        let listed = get_current_context_window(location.clone_path(), &reg, root).unwrap();
+
+        assert_eq!(listed.len(), 3);
+        assert_eq!(titles(&listed[0]), ["Inner"]);
+        assert_eq!(titles(&listed[1]), ["Tool"]);
+        assert_eq!(titles(&listed[2]), ["Stored", "Missing"]);
+        assert!(matches!(
+            listed[2].submenu.as_ref().unwrap().children[1].problems[..],
+            [VerbProblem::Unreadable(_)]
+        ));
+
+        let group = group_into_submenu(
+            "Group".to_string(),
+            "Group".to_string(),
+            &location,
+            &listed[..1],
+        );
+        let group = with_child(&group, listed[2].clone());
+        add_to_context_window(&group, &reg, root).unwrap();
+
+        let read = read_command(location.clone_path(), "Group".to_string(), &reg, root).unwrap();
+        assert_eq!(titles(&read), ["Nested", "Store"]);
+        assert_eq!(
+            reg.get_string(root, "Directory\\shell\\Group", "MUIVerb")
+                .unwrap(),
+            "Group"
+        );
+        assert!(reg.key_exists(
+            root,
+            "Directory\\shell\\Group\\shell\\Nested\\shell\\Inner\\command"
+        ));
+
+        // Removing a nested entry removes its key
+        add_to_context_window(&without_child(&read, &[0, 0]), &reg, root).unwrap();
+        assert!(!reg.key_exists(root, "Directory\\shell\\Group\\shell\\Nested\\shell\\Inner"));
+    }
+
+    #[test]
+    fn saving_a_submenu_leaves_untouched_entries_alone() {
+        let reg = MemoryRegistry::new();
+        let root = Hive::ClassesRoot;
+        let path = "Directory\\shell\\Menu";
+        reg.create_key(root, path).unwrap();
+        reg.set_string(root, path, "", "Menu").unwrap();
+        reg.set_string(root, path, SUB_COMMANDS, "").unwrap();
+        for name in ["Kept", "Edited"] {
+            let child = format!("{}\\shell\\{}", path, name);
+            add_verb(&reg, root, &child, name);
+            reg.set_value(
+                root,
+                &child,
+                "Icon",
+                &RegData::expand_sz("%SystemRoot%\\a.ico"),
+            )
+            .unwrap();
+        }
+
+        let location = Key::new("Directory").with_path("Directory/shell");
+        let mut menu = read_command(location.clone_path(), "Menu".to_string(), &reg, root).unwrap();
+        menu.submenu.as_mut().unwrap().children[0].title = "Changed".to_string();
+        add_to_context_window(&menu, &reg, root).unwrap();
+
+        let icon_type = |name: &str| {
+            reg.get_value(root, &format!("{}\\shell\\{}", path, name), "Icon")
+                .unwrap()
+                .vtype
+        };
+        assert_eq!(
+            titles(&read_command(location.clone_path(), "Menu".to_string(), &reg, root).unwrap()),
+            ["Changed", "Kept"]
+        );
+        assert_eq!(icon_type("Kept"), RegDataType::ExpandSz);
+        assert_eq!(icon_type("Edited"), RegDataType::ExpandSz);
+    }
+
+    #[test]
+    fn grouping_moves_whole_keys() {
+        let reg = MemoryRegistry::new();
+        let root = Hive::ClassesRoot;
+        let tool = "Directory\\shell\\Tool";
+        add_verb(&reg, root, tool, "Tool");
+        reg.set_value(
+            root,
+            tool,
+            "Icon",
+            &RegData::expand_sz("%SystemRoot%\\a.ico"),
+        )
+        .unwrap();
+        reg.set_string(root, tool, "Unknown", "kept").unwrap();
+        reg.create_key(root, &format!("{}\\DropTarget", tool))
+            .unwrap();
+        reg.set_string(root, &format!("{}\\DropTarget", tool), "CLSID", "{0}")
+            .unwrap();
+
+        let location = Key::new("Directory").with_path("Directory/shell");
+        let verbs = get_current_context_window(location.clone_path(), &reg, root).unwrap();
+        let group = group_into_submenu("Group".to_string(), "Group".to_string(), &location, &verbs);
+
+        let shell = nested_path(&group);
+        let changes = verbs
+            .into_iter()
+            .map(|verb| PendingChange::Move(verb, Rc::clone(&shell)))
+            .chain([PendingChange::Add(group)])
+            .collect::<Vec<PendingChange>>();
+        apply_changes(&changes, &reg).unwrap();
+
+        let moved = "Directory\\shell\\Group\\shell\\Tool";
+        assert!(!reg.key_exists(root, tool));
+        assert_eq!(reg.get_string(root, moved, "Unknown").unwrap(), "kept");
+        assert_eq!(
+            reg.get_value(root, moved, "Icon").unwrap().vtype,
+            RegDataType::ExpandSz
+        );
+        assert!(reg.key_exists(root, &format!("{}\\DropTarget", moved)));
+        assert!(reg.key_exists(root, &format!("{}\\command", moved)));
+    }
+}
@@ -1,31 +1,44 @@
 use std::{cell::RefCell, error::Error, ffi::OsStr, fmt::Display, rc::Rc};
 
 use uuid::Uuid;
-use winreg::types::ToRegValue;
+
+use crate::edit_context_lib::attributes::VerbAttributes;
+use crate::edit_context_lib::handler::ComHandler;
+use crate::edit_context_lib::origin::Origin;
+use crate::edit_context_lib::problem::VerbProblem;
+use crate::edit_context_lib::scope::{Scope, classes_path};
+use crate::edit_context_lib::submenu::SubMenu;
+use crate::registry_io::backend::Hive;
 
 pub type NormalResult = Result<(), Box<dyn Error>>;
 pub type StdCommand = ContextCommandInfo<String, String, String, String>;
 pub type StdCommandList = Vec<StdCommand>;
 pub type KeyPath = Rc<RefCell<Box<[Box<str>]>>>;
 
+pub fn key_path_string(path: &KeyPath) -> String {
+    path.borrow().join("\\")
+}
+
 #[derive(Debug, Default, Clone)]
-pub struct ContextCommandInfo<
-    T: ToRegValue + Display,
-    U: ToRegValue + Display,
-    V: ToRegValue + Display,
-    W: AsRef<OsStr>,
-> {
+pub struct ContextCommandInfo<T: Display, U: Display, V: Display, W: AsRef<OsStr>> {
     id: Uuid,
     pub title: T,
     pub icon: U,
     pub command: V,
     pub folder: W,
     pub path: KeyPath,
+    pub hive: Hive,
+    // Only known once the verb has been read back from the registry
+    pub origin: Option<Origin>,
+    pub attributes: VerbAttributes,
+    pub submenu: Option<SubMenu>,
+    // Set for verbs that run a COM object, those can't be edited here
+    pub handler: Option<ComHandler>,
+    // Empty when the verb was read in full
+    pub problems: Vec<VerbProblem>,
 }
 
-impl<T: ToRegValue + Display, U: ToRegValue + Display, V: ToRegValue + Display, W: AsRef<OsStr>>
-    ContextCommandInfo<T, U, V, W>
-{
+impl<T: Display, U: Display, V: Display, W: AsRef<OsStr>> ContextCommandInfo<T, U, V, W> {
     pub fn new(title: T, icon: U, command: V, folder: W, path: KeyPath) -> Self {
         ContextCommandInfo {
             id: Uuid::new_v4(),
@@ -34,28 +47,35 @@ impl<T: ToRegValue + Display, U: ToRegValue + Display, V: ToRegValue + Display,
             command,
             folder,
             path,
+            hive: Hive::ClassesRoot,
+            origin: None,
+            attributes: VerbAttributes::default(),
+            submenu: None,
+            handler: None,
+            problems: Vec::new(),
         }
     }
+
+    pub fn with_hive(mut self, hive: Hive) -> Self {
+        self.hive = hive;
+        self
+    }
 }
 
-impl<T: ToRegValue + Display, U: ToRegValue + Display, V: ToRegValue + Display, W: AsRef<OsStr>>
-    Display for ContextCommandInfo<T, U, V, W>
+impl<T: Display, U: Display, V: Display, W: AsRef<OsStr>> Display
+    for ContextCommandInfo<T, U, V, W>
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Title: {}, Command: {}", self.title, self.command)
     }
 }
 
-impl<T: ToRegValue + Display, U: ToRegValue + Display, V: ToRegValue + Display, W: AsRef<OsStr>>
-    PartialEq for ContextCommandInfo<T, U, V, W>
+impl<T: Display, U: Display, V: Display, W: AsRef<OsStr>> PartialEq
+    for ContextCommandInfo<T, U, V, W>
 {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
     }
-
-    fn ne(&self, other: &Self) -> bool {
-        self.id != other.id
-    }
 }
 
 #[derive(Default, Clone, PartialEq, Debug)]
@@ -63,12 +83,15 @@ pub enum KeyProps {
     #[default]
     None,
     HasExt,
+    // Under SystemFileAssociations, the key after it is a perceived type such as `image`
+    PerceivedType,
 }
 
 impl<'a, T: Into<&'a str>> From<T> for KeyProps {
     fn from(value: T) -> Self {
         match value.into() {
             "HasExt" => Self::HasExt,
+            "PerceivedType" => Self::PerceivedType,
             _ => Self::None,
         }
     }
@@ -77,6 +100,9 @@ impl<'a, T: Into<&'a str>> From<T> for KeyProps {
 #[derive(Default, Clone, PartialEq, Debug)]
 pub struct Key {
     pub name: String,
+    pub hive: Hive,
+    // Set when the config names the hive, the scope setting then leaves the key alone
+    pub fixed_hive: bool,
     pub path: KeyPath,
     pub properties: KeyProps,
 }
@@ -89,13 +115,19 @@ impl Key {
         }
     }
 
+    /// Paths may start with a hive such as `HKCU/Software/Classes/Directory/shell`,
+    /// otherwise they're relative to HKEY_CLASSES_ROOT
     pub fn with_path<T: Into<Box<str>>>(mut self, path: T) -> Self {
-        self.path = Rc::new(RefCell::new(
-            path.into()
-                .split("/")
-                .map(Box::from)
-                .collect::<Box<[Box<str>]>>(),
-        ));
+        let path: Box<str> = path.into();
+        let mut parts = path.split("/").peekable();
+
+        if let Some(hive) = parts.peek().and_then(|first| Hive::from_name(first)) {
+            parts.next();
+            self.hive = hive;
+            self.fixed_hive = true;
+        }
+
+        self.path = Rc::new(RefCell::new(parts.map(Box::from).collect()));
         self
     }
 
@@ -108,20 +140,114 @@ impl Key {
         Rc::clone(&self.path)
     }
 
+    /// The key moved into the scope's hive, unless the config gave it a hive of its own
+    pub fn in_scope(&self, scope: Scope) -> Self {
+        if self.fixed_hive {
+            return self.deep_clone();
+        }
+
+        let path = self.path.borrow();
+
+        Key {
+            hive: scope.hive(),
+            path: Rc::new(RefCell::new(
+                scope
+                    .prefix()
+                    .iter()
+                    .map(|part| Box::from(*part))
+                    .chain(path.iter().cloned())
+                    .collect(),
+            )),
+            ..self.deep_clone()
+        }
+    }
+
+    // The extension is the first key under the classes root
+    fn extension_index(&self) -> usize {
+        let path = key_path_string(&self.path);
+
+        classes_path(self.hive, &path).map_or(0, |classes| {
+            path[..path.len() - classes.len()].matches('\\').count()
+        })
+    }
+
+    // The part of the path the user picks, for keys that have one
+    fn variable_index(&self) -> Option<usize> {
+        match self.properties {
+            KeyProps::None => None,
+            KeyProps::HasExt => Some(self.extension_index()),
+            KeyProps::PerceivedType => Some(self.extension_index() + 1),
+        }
+    }
+
+    fn variable_part(&self) -> Box<str> {
+        self.variable_index()
+            .and_then(|index| self.path.borrow().get(index).cloned())
+            .unwrap_or_default()
+    }
+
+    fn with_variable_part(self, part: Box<str>) -> Self {
+        if let Some(index) = self.variable_index()
+            && let Some(current) = self.path.borrow_mut().get_mut(index)
+        {
+            *current = part;
+        }
+
+        self
+    }
+
+    /// The extension currently typed in, only meaningful for `HasExt` keys
+    pub fn extension(&self) -> Box<str> {
+        self.path
+            .borrow()
+            .get(self.extension_index())
+            .cloned()
+            .unwrap_or_default()
+    }
+
     pub fn with_extension<T: Into<Box<str>>>(self, new_extension: T) -> Self {
         match self.properties {
-            KeyProps::None => self,
-            KeyProps::HasExt => {
-                self.path.borrow_mut()[0] = new_extension.into();
-                self
-            }
+            KeyProps::HasExt => self.with_variable_part(new_extension.into()),
+            _ => self,
+        }
+    }
+
+    /// The perceived type picked, only meaningful for `PerceivedType` keys
+    pub fn perceived_type(&self) -> Box<str> {
+        match self.properties {
+            KeyProps::PerceivedType => self.variable_part(),
+            _ => Box::default(),
+        }
+    }
+
+    pub fn with_perceived_type<T: Into<Box<str>>>(self, perceived_type: T) -> Self {
+        match self.properties {
+            KeyProps::PerceivedType => self.with_variable_part(perceived_type.into()),
+            _ => self,
+        }
+    }
+
+    /// The same location under another class, such as the extension's ProgID.
+    /// The result is no longer an extension key.
+    pub fn for_class<T: Into<Box<str>>>(&self, class: T) -> Self {
+        let key = Key {
+            properties: KeyProps::None,
+            ..self.deep_clone()
+        };
+
+        if let Some(part) = key.path.borrow_mut().get_mut(self.extension_index()) {
+            *part = class.into();
         }
+
+        key
     }
 
     pub fn deep_clone(&self) -> Self {
         Key {
             name: self.name.clone(),
-            path: Rc::new(RefCell::new(self.path.borrow().clone().into())),
+            hive: self.hive,
+            fixed_hive: self.fixed_hive,
+            path: Rc::new(RefCell::new(self.path.borrow().clone())),
             properties: self.properties.clone(),
         }
     }
@@ -1,24 +1,33 @@
 use egui::ColorImage;
 use std::error::Error;
+#[cfg(windows)]
 use std::fs;
-use windows::Win32::Graphics::Gdi::BITMAPINFO;
-use windows::Win32::Graphics::Gdi::BITMAPINFOHEADER;
-use windows::Win32::Graphics::Gdi::CreateCompatibleDC;
-use windows::Win32::Graphics::Gdi::DIB_RGB_COLORS;
-use windows::Win32::Graphics::Gdi::DeleteDC;
-use windows::Win32::Graphics::Gdi::DeleteObject;
-use windows::Win32::Graphics::Gdi::GetDIBits;
-use windows::Win32::Graphics::Gdi::SelectObject;
+#[cfg(windows)]
+use windows::Win32::Graphics::Gdi::{
+    BITMAPINFO, BITMAPINFOHEADER, CreateCompatibleDC, DIB_RGB_COLORS, DeleteDC, DeleteObject,
+    GetDIBits, SelectObject,
+};
+#[cfg(windows)]
 use windows::Win32::UI::Shell::ExtractIconExW;
-use windows::Win32::UI::WindowsAndMessaging::DestroyIcon;
-use windows::Win32::UI::WindowsAndMessaging::GetIconInfoExW;
-use windows::Win32::UI::WindowsAndMessaging::HICON;
-use windows::Win32::UI::WindowsAndMessaging::ICONINFOEXW;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{DestroyIcon, GetIconInfoExW, HICON, ICONINFOEXW};
+#[cfg(windows)]
 use windows::core::PCWSTR;
 
+#[cfg(windows)]
 use crate::edit_context_lib::addtopath::check_in_path;
 
-pub fn get_images_from_exe<'a>(executable_path: &'a str) -> Result<ColorImage, Box<dyn Error>> {
+#[cfg(not(windows))]
+pub fn get_images_from_exe(executable_path: &str) -> Result<ColorImage, Box<dyn Error>> {
+    Err(format!(
+        "Cannot extract icons from {} outside of Windows",
+        executable_path
+    )
+    .into())
+}
+
+#[cfg(windows)]
+pub fn get_images_from_exe(executable_path: &str) -> Result<ColorImage, Box<dyn Error>> {
     let path: Box<str> = match fs::exists(executable_path) {
         Ok(true) => executable_path.into(),
         _ => check_in_path(executable_path)?,
@@ -50,7 +59,8 @@ pub fn get_images_from_exe<'a>(executable_path: &'a str) -> Result<ColorImage, B
     }
 }
 
-pub fn convert_hicon_to_rgba_image<'a>(hicon: HICON) -> Result<ColorImage, Box<dyn Error>> {
+#[cfg(windows)]
+pub fn convert_hicon_to_rgba_image(hicon: HICON) -> Result<ColorImage, Box<dyn Error>> {
     unsafe {
         let mut icon_info = ICONINFOEXW::default();
         icon_info.cbSize = std::mem::size_of::<ICONINFOEXW>() as u32;
@@ -132,6 +142,7 @@ pub fn convert_hicon_to_rgba_image<'a>(hicon: HICON) -> Result<ColorImage, Box<d
     }
 }
 
+#[cfg(windows)]
 fn bgra_to_rgba(bgra: Vec<u8>) -> Vec<u8> {
     // Expect length to be multiple of 4 (B,G,R,A per pixel). If not, extra bytes copied as-is.
     let mut out = Vec::with_capacity(bgra.len());
@@ -141,7 +152,7 @@ fn bgra_to_rgba(bgra: Vec<u8>) -> Vec<u8> {
         // bgra[i+0]=B, i+1=G, i+2=R, i+3=A => push R,G,B,A
         out.push(bgra[i + 2]);
         out.push(bgra[i + 1]);
-        out.push(bgra[i + 0]);
+        out.push(bgra[i]);
         out.push(bgra[i + 3]);
         i += 4;
     }
@@ -3,19 +3,20 @@ use std::env::current_dir;
 use std::error::Error;
 use std::io::{BufRead, stdin};
 use std::path::Path;
-use winreg::enums::HKEY_CLASSES_ROOT;
 
 use crate::edit_context_lib::addtocontext::{
     BACKGROUND_SUBKEY, DIRECTORY_SUBKEY, add_to_context_window, convert_subkey,
 };
 use crate::edit_context_lib::addtopath::{add_to_path, valid_path};
+use crate::edit_context_lib::scope::Scope;
 use crate::edit_context_lib::types::{ContextCommandInfo, NormalResult};
+use crate::registry_io::backend::RegistryBackend;
 
 const DEFAULT_INSTALL_PATH: &str = "C:\\ATPW";
 const ICON: &str = "cmd.exe";
 const TITLE: &str = "Add To Path";
 
-pub fn install() -> NormalResult {
+pub fn install(reg: &dyn RegistryBackend, scope: Scope) -> NormalResult {
     // Get desired path from user
     let install_path = get_install_path()?;
 
@@ -25,10 +26,10 @@ pub fn install() -> NormalResult {
     copy_current_to_path(&install_path)?;
 
     // Add path to path
-    add_to_path(&install_path)?;
+    add_to_path(&install_path, reg)?;
 
     // Install program to context window
-    add_command_to_window(&install_path)?;
+    add_command_to_window(&install_path, reg, scope)?;
 
     Ok(())
 }
@@ -45,11 +46,11 @@ fn get_install_path() -> Result<String, Box<dyn Error>> {
 
     input.lock().read_line(&mut result)?;
 
-    if result.trim().len() == 0 {
+    if result.trim().is_empty() {
         result = DEFAULT_INSTALL_PATH.to_string();
     }
 
-    return Ok(result);
+    Ok(result)
 }
 
 fn copy_current_to_path<T: AsRef<Path>>(dir: &T) -> NormalResult {
@@ -60,14 +61,18 @@ fn copy_current_to_path<T: AsRef<Path>>(dir: &T) -> NormalResult {
     Ok(())
 }
 
-fn add_command_to_window<T: Into<String>>(dir_into: T) -> NormalResult {
+fn add_command_to_window<T: Into<String>>(
+    dir_into: T,
+    reg: &dyn RegistryBackend,
+    scope: Scope,
+) -> NormalResult {
     let dir: String = dir_into.into();
 
     if !valid_path(&dir) {
         return Err("Directory does not exist".into());
     }
 
-    // Opens: HKEY_CLASSES_ROOT\Directory\shell\ATPW\command
+    // Opens: <scope's classes>\Directory\shell\ATPW\command
     let command = format!("\"{}\\add_to_path_window.exe\" -a \"%V\"", dir);
 
     add_to_context_window(
@@ -76,9 +81,10 @@ fn add_command_to_window<T: Into<String>>(dir_into: T) -> NormalResult {
             ICON,
             command.clone(),
             &"APTW",
-            convert_subkey(&DIRECTORY_SUBKEY).into(),
+            convert_subkey(&scope.subkey(&DIRECTORY_SUBKEY)),
         ),
-        HKEY_CLASSES_ROOT,
+        reg,
+        scope.hive(),
     )?;
 
     add_to_context_window(
@@ -87,9 +93,10 @@ fn add_command_to_window<T: Into<String>>(dir_into: T) -> NormalResult {
             ICON,
             command,
             &"APTW",
-            convert_subkey(&BACKGROUND_SUBKEY),
+            convert_subkey(&scope.subkey(&BACKGROUND_SUBKEY)),
         ),
-        HKEY_CLASSES_ROOT,
+        reg,
+        scope.hive(),
     )?;
 
     Ok(())
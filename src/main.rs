@@ -1,6 +1,8 @@
 mod app;
 mod edit_context_lib;
 mod icon;
+// Left over from the command line installer, the GUI does not call into it
+#[allow(dead_code)]
 mod install;
 mod parsing;
 mod registry_io;
@@ -8,27 +10,26 @@ mod resources;
 mod ui;
 
 use std::error::Error;
-use std::io;
-use winreg::enums::HKEY_CLASSES_ROOT;
+use std::{env, io};
 
 use log::debug;
 
 use crate::app::App;
 use crate::edit_context_lib::backup::backup_paths;
-use crate::edit_context_lib::types::Key;
+use crate::edit_context_lib::diff::{diff_registries, diff_to_json, diff_to_text, snapshot};
+use crate::registry_io::backend::RegistryBackend;
+use crate::registry_io::reader::RegReader;
+use crate::registry_io::system_registry;
 use crate::resources::config::AppConfig;
 use crate::resources::resources::load_config_ini;
 
 fn main() {
     env_logger::init();
 
-    match run() {
-        Err(err) => {
-            println!("Critical error:{}", err);
-            let input = io::stdin();
-            input.read_line(&mut String::new()).unwrap();
-        }
-        _ => {}
+    if let Err(err) = run() {
+        println!("Critical error:{}", err);
+        let input = io::stdin();
+        input.read_line(&mut String::new()).unwrap();
     }
 }
 
@@ -39,6 +40,42 @@ fn main() {
     }
 }*/
 
+// --diff <old.reg> [new.reg] [--json]
+// Without a second file the backup is compared against the configured locations
+fn run_diff(
+    args: &[String],
+    config: &AppConfig,
+    registry: &dyn RegistryBackend,
+) -> Result<(), Box<dyn Error>> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let files = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<&String>>();
+
+    let (old, new) = match files.as_slice() {
+        [old] => (
+            RegReader::try_read_file(old)?,
+            snapshot(&config.scoped_keys(), registry)?,
+        ),
+        [old, new] => (
+            RegReader::try_read_file(old)?,
+            RegReader::try_read_file(new)?,
+        ),
+        _ => return Err("Usage: --diff <old.reg> [new.reg] [--json]".into()),
+    };
+
+    let diffs = diff_registries(&old, &new)?;
+
+    if json {
+        println!("{}", diff_to_json(&diffs)?);
+    } else {
+        println!("{}", diff_to_text(&diffs));
+    }
+
+    Ok(())
+}
+
 fn run() -> Result<(), Box<dyn Error>> {
     let config: AppConfig = load_config_ini()?.into();
 
@@ -51,14 +88,28 @@ fn run() -> Result<(), Box<dyn Error>> {
 
     debug!("Running...");
 
-    if config.auto_backup {
-        backup_paths(
-            config.keys.iter().map(Key::clone_path).collect(), // Clone is cheap (Rc::clone)
-            HKEY_CLASSES_ROOT,
+    let registry = system_registry();
+
+    // The in-memory registry starts out empty, give it the configured locations so the app is usable
+    #[cfg(not(windows))]
+    for key in config.keys.iter() {
+        registry.create_key(
+            key.hive,
+            &edit_context_lib::types::key_path_string(&key.path),
         )?;
     }
 
-    let mut app = App::new(config);
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    if args.first().is_some_and(|arg| arg == "--diff") {
+        return run_diff(&args[1..], &config, registry.as_ref());
+    }
+
+    if config.auto_backup {
+        backup_paths(&config.scoped_keys(), registry.as_ref(), &config.backup)?;
+    }
+
+    let mut app = App::new(config, registry);
 
     app.reload_items();
 
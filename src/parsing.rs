@@ -3,10 +3,59 @@ use std::env;
 use egui::ColorImage;
 use log::debug;
 
-use crate::{edit_context_lib::types::StdCommand, icon::get_images_from_exe};
+use crate::{
+    edit_context_lib::types::{NormalResult, StdCommand},
+    icon::get_images_from_exe,
+};
 
-pub fn folderize_title<'a>(s: &'a String) -> String {
-    s.replace(" ", "")
+// Subkeys a verb or its submenu already has a use for
+const RESERVED_KEY_NAMES: [&str; 4] = ["command", "DropTarget", "shell", "shellex"];
+const MAX_KEY_NAME: usize = 255;
+
+/// A key name made from a title. Spaces and control characters are dropped and
+/// backslashes, which would nest keys, become dashes.
+pub fn folderize_title(s: &str) -> String {
+    let name = s
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .map(|c| if c == '\\' { '-' } else { c })
+        .take(MAX_KEY_NAME - 4)
+        .collect::<String>();
+
+    if name.is_empty() || is_reserved(&name) {
+        name + "Verb"
+    } else {
+        name
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    RESERVED_KEY_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+/// Whether `name` can be used as a verb's key name as it is
+pub fn check_key_name(name: &str) -> NormalResult {
+    if name.trim().is_empty() {
+        Err("the key name is empty".into())
+    } else if name.contains('\\') || name.chars().any(char::is_control) {
+        Err(format!("\"{}\" has characters a key name can't have", name).into())
+    } else if name.chars().count() > MAX_KEY_NAME {
+        Err(format!("key names are at most {} characters", MAX_KEY_NAME).into())
+    } else if is_reserved(name) {
+        Err(format!("\"{}\" is reserved", name).into())
+    } else {
+        Ok(())
+    }
+}
+
+/// `name`, or the first of `name2`, `name3`... that isn't taken yet
+pub fn unique_key_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    std::iter::once(name.to_string())
+        .chain((2..).map(|count| format!("{}{}", name, count)))
+        .find(|name| !taken(name))
+        .unwrap_or_default()
 }
 
 pub fn sanitize_path(path: String) -> String {
@@ -29,14 +78,10 @@ pub fn sanitize_path(path: String) -> String {
     result.to_string()
 }
 
-pub fn sanitize_command(cmd: StdCommand) -> StdCommand {
-    StdCommand::new(
-        sanitize_path(cmd.title),
-        sanitize_path(cmd.icon),
-        cmd.command,
-        cmd.folder,
-        cmd.path,
-    )
+pub fn sanitize_command(mut cmd: StdCommand) -> StdCommand {
+    cmd.title = sanitize_path(cmd.title);
+    cmd.icon = sanitize_path(cmd.icon);
+    cmd
 }
 
 pub fn command_with_icon(item: StdCommand) -> (StdCommand, ColorImage) {
@@ -50,3 +95,26 @@ pub fn command_with_icon(item: StdCommand) -> (StdCommand, ColorImage) {
 
     (item, color_image)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_become_usable_and_unique_key_names() {
+        assert_eq!(folderize_title("My Tool"), "MyTool");
+        assert_eq!(folderize_title("Open\\Here"), "Open-Here");
+        assert_eq!(folderize_title("Command"), "CommandVerb");
+        assert_eq!(folderize_title(" "), "Verb");
+
+        assert!(check_key_name("Open\\Here").is_err());
+        assert!(check_key_name("shell").is_err());
+        assert!(check_key_name("OpenHere").is_ok());
+
+        let taken = ["MyTool", "mytool2"];
+        let name = unique_key_name("MyTool", |name| {
+            taken.iter().any(|taken| taken.eq_ignore_ascii_case(name))
+        });
+        assert_eq!(name, "MyTool3");
+    }
+}
@@ -0,0 +1,244 @@
+use std::{error::Error, fmt::Display, io};
+
+use crate::edit_context_lib::types::NormalResult;
+
+/// The predefined registry roots the app knows how to work with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Hive {
+    #[default]
+    ClassesRoot,
+    CurrentUser,
+    LocalMachine,
+}
+
+impl Hive {
+    /// Accepts the full root name as written in .reg files, or its HKCR style abbreviation
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "HKEY_CLASSES_ROOT" | "HKCR" => Some(Self::ClassesRoot),
+            "HKEY_CURRENT_USER" | "HKCU" => Some(Self::CurrentUser),
+            "HKEY_LOCAL_MACHINE" | "HKLM" => Some(Self::LocalMachine),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Hive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ClassesRoot => write!(f, "HKEY_CLASSES_ROOT"),
+            Self::CurrentUser => write!(f, "HKEY_CURRENT_USER"),
+            Self::LocalMachine => write!(f, "HKEY_LOCAL_MACHINE"),
+        }
+    }
+}
+
+/// Registry value types, mirroring the REG_* constants
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegDataType {
+    None,
+    Sz,
+    ExpandSz,
+    Binary,
+    Dword,
+    DwordBigEndian,
+    Link,
+    MultiSz,
+    ResourceList,
+    FullResourceDescriptor,
+    ResourceRequirementsList,
+    Qword,
+    // Windows accepts any number as a type, these are kept as they are
+    Unknown(u32),
+}
+
+impl RegDataType {
+    pub fn from_raw(raw: u32) -> Self {
+        match raw {
+            0 => Self::None,
+            1 => Self::Sz,
+            2 => Self::ExpandSz,
+            3 => Self::Binary,
+            4 => Self::Dword,
+            5 => Self::DwordBigEndian,
+            6 => Self::Link,
+            7 => Self::MultiSz,
+            8 => Self::ResourceList,
+            9 => Self::FullResourceDescriptor,
+            10 => Self::ResourceRequirementsList,
+            11 => Self::Qword,
+            raw => Self::Unknown(raw),
+        }
+    }
+
+    /// The REG_* number for this type
+    pub fn raw(self) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Sz => 1,
+            Self::ExpandSz => 2,
+            Self::Binary => 3,
+            Self::Dword => 4,
+            Self::DwordBigEndian => 5,
+            Self::Link => 6,
+            Self::MultiSz => 7,
+            Self::ResourceList => 8,
+            Self::FullResourceDescriptor => 9,
+            Self::ResourceRequirementsList => 10,
+            Self::Qword => 11,
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
+
+/// A raw registry value, the bytes are laid out exactly as Windows stores them
+/// (UTF-16LE strings with a trailing null, little endian integers)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegData {
+    pub bytes: Vec<u8>,
+    pub vtype: RegDataType,
+}
+
+fn str_to_utf16_bytes(str: &str) -> Vec<u8> {
+    str.encode_utf16()
+        .chain([0_u16])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+impl RegData {
+    pub fn sz<T: AsRef<str>>(str: T) -> Self {
+        Self {
+            bytes: str_to_utf16_bytes(str.as_ref()),
+            vtype: RegDataType::Sz,
+        }
+    }
+
+    pub fn expand_sz<T: AsRef<str>>(str: T) -> Self {
+        Self {
+            bytes: str_to_utf16_bytes(str.as_ref()),
+            vtype: RegDataType::ExpandSz,
+        }
+    }
+
+    pub fn dword(value: u32) -> Self {
+        Self {
+            bytes: value.to_le_bytes().to_vec(),
+            vtype: RegDataType::Dword,
+        }
+    }
+
+    /// Reads a DWORD, big endian ones included
+    pub fn as_dword(&self) -> Option<u32> {
+        match (self.vtype, self.bytes.as_slice()) {
+            (RegDataType::Dword, [a, b, c, d]) => Some(u32::from_le_bytes([*a, *b, *c, *d])),
+            (RegDataType::DwordBigEndian, [a, b, c, d]) => {
+                Some(u32::from_be_bytes([*a, *b, *c, *d]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads string types as a string, trailing nulls are dropped
+    pub fn as_string(&self) -> Option<String> {
+        match self.vtype {
+            RegDataType::Sz | RegDataType::ExpandSz | RegDataType::Link => {
+                let words = self
+                    .bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect::<Vec<u16>>();
+
+                Some(
+                    String::from_utf16_lossy(&words)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                )
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for RegData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(str) = self.as_string() {
+            return write!(f, "\"{}\"", str);
+        }
+
+        match (self.vtype, self.bytes.as_slice()) {
+            (RegDataType::Dword, [a, b, c, d]) => {
+                write!(f, "dword:{:08x}", u32::from_le_bytes([*a, *b, *c, *d]))
+            }
+            _ => write!(
+                f,
+                "{:?}:{}",
+                self.vtype,
+                self.bytes
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<Vec<String>>()
+                    .join(",")
+            ),
+        }
+    }
+}
+
+/// Everything the app needs from a registry, paths are relative to the hive and
+/// separated with backslashes.
+pub trait RegistryBackend {
+    fn key_exists(&self, hive: Hive, path: &str) -> bool;
+
+    /// Creates the key and any missing parents, does nothing if it already exists
+    fn create_key(&self, hive: Hive, path: &str) -> NormalResult;
+
+    /// Deletes the key along with all of its subkeys and values
+    fn delete_key_all(&self, hive: Hive, path: &str) -> NormalResult;
+
+    fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Box<dyn Error>>;
+
+    fn enum_values(&self, hive: Hive, path: &str)
+    -> Result<Vec<(String, RegData)>, Box<dyn Error>>;
+
+    /// An empty name is the key's default value
+    fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegData, Box<dyn Error>>;
+
+    fn set_value(&self, hive: Hive, path: &str, name: &str, value: &RegData) -> NormalResult;
+
+    fn delete_value(&self, hive: Hive, path: &str, name: &str) -> NormalResult;
+
+    /// Until the transaction is committed or rolled back, every change is held back and
+    /// applied all at once
+    fn begin_transaction(&self) -> NormalResult;
+
+    fn commit_transaction(&self) -> NormalResult;
+
+    fn rollback_transaction(&self) -> NormalResult;
+
+    fn get_string(&self, hive: Hive, path: &str, name: &str) -> Result<String, Box<dyn Error>> {
+        match self.get_value(hive, path, name)?.as_string() {
+            Some(str) => Ok(str),
+            None => Err(format!("Value \"{}\" in {}\\{} is not a string", name, hive, path).into()),
+        }
+    }
+
+    fn set_string(&self, hive: Hive, path: &str, name: &str, value: &str) -> NormalResult {
+        self.set_value(hive, path, name, &RegData::sz(value))
+    }
+}
+
+pub(super) fn key_not_found(hive: Hive, path: &str) -> Box<dyn Error> {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Key not found: {}\\{}", hive, path),
+    )
+    .into()
+}
+
+pub(super) fn value_not_found(hive: Hive, path: &str, name: &str) -> Box<dyn Error> {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("Value \"{}\" not found in {}\\{}", name, hive, path),
+    )
+    .into()
+}
@@ -0,0 +1,246 @@
+use std::{cell::RefCell, collections::BTreeMap, error::Error};
+
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegData, RegistryBackend, key_not_found, value_not_found};
+
+// Key and value names are case insensitive in the real registry, so both maps are indexed
+// by the lowercase name while the original casing is kept alongside for enumeration
+#[derive(Debug, Default, Clone)]
+struct MemKey {
+    name: String,
+    values: BTreeMap<String, (String, RegData)>,
+    subkeys: BTreeMap<String, MemKey>,
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('\\').filter(|part| !part.is_empty())
+}
+
+impl MemKey {
+    fn find(&self, path: &str) -> Option<&MemKey> {
+        split_path(path).try_fold(self, |key, next| key.subkeys.get(&next.to_lowercase()))
+    }
+
+    fn find_mut(&mut self, path: &str) -> Option<&mut MemKey> {
+        split_path(path).try_fold(self, |key, next| key.subkeys.get_mut(&next.to_lowercase()))
+    }
+
+    fn find_or_create(&mut self, path: &str) -> &mut MemKey {
+        split_path(path).fold(self, |key, next| {
+            key.subkeys
+                .entry(next.to_lowercase())
+                .or_insert_with(|| MemKey {
+                    name: next.to_string(),
+                    ..Self::default()
+                })
+        })
+    }
+}
+
+/// A registry that only lives in memory, used off Windows and for testing
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    hives: RefCell<BTreeMap<Hive, MemKey>>,
+    // The state to go back to if the open transaction is rolled back
+    snapshot: RefCell<Option<BTreeMap<Hive, MemKey>>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RegistryBackend for MemoryRegistry {
+    fn key_exists(&self, hive: Hive, path: &str) -> bool {
+        self.hives
+            .borrow()
+            .get(&hive)
+            .and_then(|root| root.find(path))
+            .is_some()
+    }
+
+    fn create_key(&self, hive: Hive, path: &str) -> NormalResult {
+        self.hives
+            .borrow_mut()
+            .entry(hive)
+            .or_default()
+            .find_or_create(path);
+
+        Ok(())
+    }
+
+    fn delete_key_all(&self, hive: Hive, path: &str) -> NormalResult {
+        let (parent, name) = match path.trim_end_matches('\\').rsplit_once('\\') {
+            Some((parent, name)) => (parent, name),
+            None => ("", path),
+        };
+
+        let mut hives = self.hives.borrow_mut();
+
+        match hives
+            .get_mut(&hive)
+            .and_then(|root| root.find_mut(parent))
+            .and_then(|key| key.subkeys.remove(&name.to_lowercase()))
+        {
+            Some(_) => Ok(()),
+            None => Err(key_not_found(hive, path)),
+        }
+    }
+
+    fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let hives = self.hives.borrow();
+
+        match hives.get(&hive).and_then(|root| root.find(path)) {
+            Some(key) => Ok(key.subkeys.values().map(|sub| sub.name.clone()).collect()),
+            None => Err(key_not_found(hive, path)),
+        }
+    }
+
+    fn enum_values(
+        &self,
+        hive: Hive,
+        path: &str,
+    ) -> Result<Vec<(String, RegData)>, Box<dyn Error>> {
+        let hives = self.hives.borrow();
+
+        match hives.get(&hive).and_then(|root| root.find(path)) {
+            Some(key) => Ok(key.values.values().cloned().collect()),
+            None => Err(key_not_found(hive, path)),
+        }
+    }
+
+    fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegData, Box<dyn Error>> {
+        let hives = self.hives.borrow();
+
+        let key = match hives.get(&hive).and_then(|root| root.find(path)) {
+            Some(key) => key,
+            None => return Err(key_not_found(hive, path)),
+        };
+
+        match key.values.get(&name.to_lowercase()) {
+            Some((_, data)) => Ok(data.clone()),
+            None => Err(value_not_found(hive, path, name)),
+        }
+    }
+
+    fn set_value(&self, hive: Hive, path: &str, name: &str, value: &RegData) -> NormalResult {
+        let mut hives = self.hives.borrow_mut();
+
+        match hives.get_mut(&hive).and_then(|root| root.find_mut(path)) {
+            Some(key) => {
+                key.values
+                    .insert(name.to_lowercase(), (name.to_string(), value.clone()));
+                Ok(())
+            }
+            None => Err(key_not_found(hive, path)),
+        }
+    }
+
+    fn delete_value(&self, hive: Hive, path: &str, name: &str) -> NormalResult {
+        let mut hives = self.hives.borrow_mut();
+
+        let key = match hives.get_mut(&hive).and_then(|root| root.find_mut(path)) {
+            Some(key) => key,
+            None => return Err(key_not_found(hive, path)),
+        };
+
+        match key.values.remove(&name.to_lowercase()) {
+            Some(_) => Ok(()),
+            None => Err(value_not_found(hive, path, name)),
+        }
+    }
+
+    fn begin_transaction(&self) -> NormalResult {
+        let mut snapshot = self.snapshot.borrow_mut();
+
+        if snapshot.is_some() {
+            return Err("A transaction is already in progress".into());
+        }
+
+        *snapshot = Some(self.hives.borrow().clone());
+        Ok(())
+    }
+
+    fn commit_transaction(&self) -> NormalResult {
+        match self.snapshot.borrow_mut().take() {
+            Some(_) => Ok(()),
+            None => Err("No transaction to commit".into()),
+        }
+    }
+
+    fn rollback_transaction(&self) -> NormalResult {
+        match self.snapshot.borrow_mut().take() {
+            Some(hives) => {
+                *self.hives.borrow_mut() = hives;
+                Ok(())
+            }
+            None => Err("No transaction to roll back".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_values_are_case_insensitive() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, "Directory\\shell\\MyTool")
+            .unwrap();
+        reg.set_string(
+            Hive::ClassesRoot,
+            "directory\\SHELL\\mytool",
+            "Icon",
+            "cmd.exe",
+        )
+        .unwrap();
+
+        assert!(reg.key_exists(Hive::ClassesRoot, "DIRECTORY\\Shell"));
+        assert!(!reg.key_exists(Hive::CurrentUser, "Directory\\shell"));
+        assert_eq!(
+            reg.enum_keys(Hive::ClassesRoot, "Directory\\shell")
+                .unwrap(),
+            vec!["MyTool".to_string()]
+        );
+        assert_eq!(
+            reg.get_string(Hive::ClassesRoot, "Directory\\shell\\MyTool", "icon")
+                .unwrap(),
+            "cmd.exe"
+        );
+    }
+
+    #[test]
+    fn delete_key_all_removes_subtree() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, "*\\shell\\Open\\command")
+            .unwrap();
+        reg.delete_key_all(Hive::ClassesRoot, "*\\shell\\Open")
+            .unwrap();
+
+        assert!(reg.key_exists(Hive::ClassesRoot, "*\\shell"));
+        assert!(!reg.key_exists(Hive::ClassesRoot, "*\\shell\\Open\\command"));
+        assert!(
+            reg.delete_key_all(Hive::ClassesRoot, "*\\shell\\Open")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rollback_restores_state_before_transaction() {
+        let reg = MemoryRegistry::new();
+
+        reg.create_key(Hive::ClassesRoot, "*\\shell").unwrap();
+        reg.begin_transaction().unwrap();
+        reg.create_key(Hive::ClassesRoot, "*\\shell\\Open").unwrap();
+        reg.delete_key_all(Hive::ClassesRoot, "*\\shell").unwrap();
+        reg.rollback_transaction().unwrap();
+
+        assert!(reg.key_exists(Hive::ClassesRoot, "*\\shell"));
+        assert!(!reg.key_exists(Hive::ClassesRoot, "*\\shell\\Open"));
+        assert!(reg.commit_transaction().is_err());
+    }
+}
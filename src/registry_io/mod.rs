@@ -1,2 +1,20 @@
-//pub mod reader;
+pub mod backend;
+pub mod memory;
+pub mod reader;
+pub mod tree;
+#[cfg(windows)]
+pub mod winreg_backend;
 pub mod writer;
+
+use crate::registry_io::backend::RegistryBackend;
+
+/// The registry the app edits, the live one on Windows and an empty in-memory one elsewhere
+#[cfg(windows)]
+pub fn system_registry() -> Box<dyn RegistryBackend> {
+    Box::new(winreg_backend::WinRegistry::new())
+}
+
+#[cfg(not(windows))]
+pub fn system_registry() -> Box<dyn RegistryBackend> {
+    Box::new(memory::MemoryRegistry::new())
+}
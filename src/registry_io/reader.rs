@@ -1,181 +1,365 @@
-use std::{error::Error, path::Path};
+use std::{collections::HashMap, error::Error, fs, path::Path};
 
-use regashii::{KeyName, Kind, Registry};
-use winreg::{
-    HKEY, RegKey, RegValue,
-    enums::{
-        HKEY_CLASSES_ROOT,
-        RegType::{self},
-    },
-    transaction::Transaction,
-};
+use regashii::{KeyName, Registry, ValueName};
 
-use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegData, RegDataType};
+use crate::registry_io::tree::RegTree;
 
-const U32SIZE: usize = 4;
-const U64SIZE: usize = 8;
+fn multi_sz_bytes(items: &[String]) -> Vec<u8> {
+    items
+        .iter()
+        .flat_map(|item| item.encode_utf16().chain([0_u16]))
+        .chain([0_u16])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
 
-fn u32_to_u8s(val: u32) -> [u8; U32SIZE] {
-    let mut output = [0_u8; U32SIZE];
+pub fn regashii_to_regdata(value: regashii::Value) -> Option<RegData> {
+    use regashii::Value::*;
 
-    let mut mask = 1_u32 << 8 - 1;
+    match value {
+        Delete => None,
+        Sz(str) => Some(RegData::sz(str)),
+        ExpandSz(str) => Some(RegData::expand_sz(str)),
+        Binary(items) => Some(RegData {
+            bytes: items,
+            vtype: RegDataType::Binary,
+        }),
+        Dword(val) => Some(RegData {
+            bytes: val.to_le_bytes().to_vec(),
+            vtype: RegDataType::Dword,
+        }),
+        DwordBigEndian(val) => Some(RegData {
+            bytes: val.to_be_bytes().to_vec(),
+            vtype: RegDataType::DwordBigEndian,
+        }),
+        MultiSz(items) => Some(RegData {
+            bytes: multi_sz_bytes(&items),
+            vtype: RegDataType::MultiSz,
+        }),
+        Qword(val) => Some(RegData {
+            bytes: val.to_le_bytes().to_vec(),
+            vtype: RegDataType::Qword,
+        }),
+        Hex { kind, bytes } => Some(RegData {
+            bytes,
+            vtype: RegDataType::from_raw(u8::from(kind).into()),
+        }),
+    }
+}
+
+// (key, value name), both in lowercase
+type RawValues = HashMap<(String, String), RegData>;
 
-    for i in 0..U32SIZE {
-        output[i] = ((val & mask) >> (i * 8)) as u8;
-        mask <<= 8;
+fn decode_reg_file(bytes: Vec<u8>) -> Result<String, Box<dyn Error>> {
+    match bytes.as_slice() {
+        [0xff, 0xfe, rest @ ..] => {
+            let words = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect::<Vec<u16>>();
+
+            Ok(String::from_utf16(&words)?)
+        }
+        _ => Ok(String::from_utf8(bytes)?
+            .trim_start_matches('\u{feff}')
+            .to_string()),
     }
+}
 
-    output
+// Joins hex data that was wrapped onto the next line with a trailing backslash
+fn logical_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .fold(Vec::new(), |mut lines: Vec<String>, line| {
+            match lines.last_mut() {
+                Some(last) if last.ends_with('\\') => {
+                    last.pop();
+                    last.push_str(line.trim_start());
+                }
+                _ => lines.push(line.to_string()),
+            }
+            lines
+        })
 }
 
-fn u64_to_u8s(val: u64) -> [u8; U64SIZE] {
-    let mut output = [0_u8; U64SIZE];
+// Splits `"name"=data` or `@=data` into the unescaped name, the name as written and the data
+fn split_value_line(line: &str) -> Option<(String, &str, &str)> {
+    let (name, rest) = match line.strip_prefix('@') {
+        Some(rest) => (String::new(), rest),
+        None => {
+            let mut name = String::new();
+            let mut chars = line.strip_prefix('"')?.char_indices();
 
-    let mut mask = 1_u64 << 8 - 1;
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') => name.push(chars.next()?.1),
+                    // One past the opening quote and one past the closing one
+                    (index, '"') => break index + 2,
+                    (_, char) => name.push(char),
+                }
+            };
 
-    for i in 0..U64SIZE {
-        output[i] = ((val & mask) >> (i * 8)) as u8;
-        mask <<= 8;
-    }
+            (name, &line[end..])
+        }
+    };
 
-    output
+    let data = rest.trim_start().strip_prefix('=')?.trim_start();
+
+    Some((name, &line[..line.len() - rest.len()], data))
 }
 
-fn kind_to_regtype(kind: Kind) -> Option<RegType> {
-    use winreg::enums::RegType::*;
-    match kind {
-        Kind::None => None,
-        Kind::Sz => Some(REG_SZ),
-        Kind::ExpandSz => Some(REG_EXPAND_SZ),
-        Kind::Binary => Some(REG_BINARY),
-        Kind::Dword => Some(REG_DWORD),
-        Kind::DwordBigEndian => Some(REG_DWORD_BIG_ENDIAN),
-        Kind::Link => Some(REG_LINK),
-        Kind::MultiSz => Some(REG_MULTI_SZ),
-        Kind::ResourceList => Some(REG_RESOURCE_LIST),
-        Kind::FullResourceList => Some(REG_FULL_RESOURCE_DESCRIPTOR),
-        Kind::ResourceRequirementsList => Some(REG_RESOURCE_REQUIREMENTS_LIST),
-        Kind::Qword => Some(REG_QWORD),
-        Kind::Unknown(_) => None,
-    }
+// `hex:` is REG_BINARY, `hex(N):` carries any other type as a hex number
+fn parse_hex_value(data: &str) -> Option<(RegData, &str)> {
+    let rest = data.strip_prefix("hex")?;
+
+    let (vtype, text) = match rest.strip_prefix(':') {
+        Some(text) => (RegDataType::Binary, text),
+        None => {
+            let (kind, text) = rest.strip_prefix('(')?.split_once("):")?;
+            (
+                RegDataType::from_raw(u32::from_str_radix(kind, 16).ok()?),
+                text,
+            )
+        }
+    };
+
+    let bytes = text
+        .split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some((RegData { bytes, vtype }, text))
 }
 
-fn regasii_to_winreg(value: regashii::Value) -> Option<RegValue> {
-    use regashii::Value::*;
-    use winreg::enums::RegType::*;
+// regashii turns the hex values it recognises into strings and numbers, which loses anything
+// after a string's first null along with the big endian DWORD type, and it panics on a big
+// endian DWORD shorter than four bytes. The exact bytes of every hex value are kept to one
+// side and regashii only gets to see them as plain binary.
+fn split_raw_values(text: &str) -> (String, RawValues) {
+    let mut key: Option<String> = None;
 
-    match value {
-        Delete => None,
-        Sz(str) => Some(RegValue {
-            bytes: str.bytes().collect(),
-            vtype: REG_SZ,
-        }),
-        ExpandSz(str) => Some(RegValue {
-            bytes: str.bytes().collect(),
-            vtype: REG_EXPAND_SZ,
-        }),
-        Binary(items) => Some(RegValue {
-            bytes: items,
-            vtype: REG_BINARY,
-        }),
-        Dword(val) => Some(RegValue {
-            bytes: u32_to_u8s(val).to_vec(),
-            vtype: REG_DWORD,
-        }),
-        DwordBigEndian(val) => Some(RegValue {
-            bytes: u32_to_u8s(val).to_vec(),
-            vtype: REG_DWORD_BIG_ENDIAN,
-        }),
-        MultiSz(items) => Some(RegValue {
-            bytes: items
-                .into_iter()
-                .reduce(|acc, next| acc + &next)?
-                .bytes()
-                .collect(),
-            vtype: REG_MULTI_SZ,
-        }),
-        Qword(val) => Some(RegValue {
-            bytes: u64_to_u8s(val).to_vec(),
-            vtype: REG_QWORD,
-        }),
-        Hex { kind, bytes } => Some(RegValue {
-            bytes,
-            vtype: kind_to_regtype(kind)?,
-        }),
+    logical_lines(text).into_iter().fold(
+        (String::new(), RawValues::new()),
+        |(mut out, mut raw), line| {
+            let trimmed = line.trim();
+
+            if trimmed.starts_with('[') {
+                key = trimmed
+                    .strip_prefix('[')
+                    .and_then(|name| name.strip_suffix(']'))
+                    .map(str::to_lowercase);
+            } else if let Some(key) = &key
+                && let Some((name, written_name, data)) = split_value_line(trimmed)
+                && let Some((value, bytes)) = parse_hex_value(data)
+            {
+                raw.insert((key.clone(), name.to_lowercase()), value);
+                out.push_str(&format!("{}=hex:{}\n", written_name, bytes));
+                return (out, raw);
+            }
+
+            out.push_str(&line);
+            out.push('\n');
+            (out, raw)
+        },
+    )
+}
+
+fn value_name_str(name: &ValueName) -> &str {
+    match name {
+        ValueName::Default => "",
+        ValueName::Named(name) => name,
     }
 }
 
-fn keyname_to_regkey(name: &KeyName, hkey: HKEY) -> Result<RegKey, Box<dyn Error>> {
-    Ok(name.raw().split("\\").skip(1).fold(
-        Ok(RegKey::predef(hkey)),
-        |acc: Result<RegKey, Box<dyn Error>>, next| Ok(acc?.open_subkey(next)?),
-    )?)
+/// Splits `HKEY_CLASSES_ROOT\Directory\shell` into its hive and the path below it
+pub fn split_key_name(name: &KeyName) -> Option<(Hive, String)> {
+    let (hive, path) = name.raw().split_once('\\').unwrap_or((name.raw(), ""));
+
+    Some((Hive::from_name(hive)?, path.to_string()))
+}
+
+/// A context menu verb found in a .reg file, the key is `location\name`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegVerb {
+    pub hive: Hive,
+    pub location: String,
+    pub name: String,
+    pub title: Option<String>,
 }
 
-fn keyname_to_regkey_transacted(
-    name: &KeyName,
-    hkey: HKEY,
-    tr: &Transaction,
-) -> Result<RegKey, Box<dyn Error>> {
-    Ok(name.raw().split("\\").skip(1).fold(
-        Ok(RegKey::predef(hkey)),
-        |acc: Result<RegKey, Box<dyn Error>>, next| Ok(acc?.open_subkey_transacted(next, tr)?),
-    )?)
+impl RegVerb {
+    pub fn path(&self) -> String {
+        format!("{}\\{}", self.location, self.name)
+    }
+}
+
+// Verbs are the keys directly under the first `shell` key in a path,
+// anything deeper (command, nested menus) belongs to that verb
+fn verb_of(path: &str) -> Option<(String, String)> {
+    let parts = path.split('\\').collect::<Vec<&str>>();
+    let shell = parts
+        .iter()
+        .position(|part| part.eq_ignore_ascii_case("shell"))?;
+    let name = parts.get(shell + 1)?;
+
+    Some((parts[..=shell].join("\\"), name.to_string()))
 }
+
+#[derive(Debug)]
 pub struct RegReader {
     reg: Registry,
+    raw: RawValues,
 }
 
 impl RegReader {
     pub fn try_read_file<T: AsRef<Path>>(path: T) -> Result<Self, Box<dyn Error>> {
+        let (text, raw) = split_raw_values(&decode_reg_file(fs::read(path)?)?);
+
         Ok(Self {
-            reg: Registry::deserialize_file(path)?,
+            reg: Registry::deserialize(&text)?,
+            raw,
         })
     }
 
-    pub unsafe fn load_all_immediate(&self) -> NormalResult {
+    pub fn from_registry(reg: Registry) -> Self {
+        Self {
+            reg,
+            raw: RawValues::new(),
+        }
+    }
+
+    fn value_data(
+        &self,
+        key: &KeyName,
+        name: &ValueName,
+        value: &regashii::Value,
+    ) -> Option<RegData> {
+        self.raw
+            .get(&(
+                key.raw().to_lowercase(),
+                value_name_str(name).to_lowercase(),
+            ))
+            .cloned()
+            .or_else(|| regashii_to_regdata(value.clone()))
+    }
+
+    /// Every verb in the file, in the order their keys appear. A verb counts even when
+    /// the file only lists keys below it, such as `shell\Foo\command`.
+    pub fn verbs(&self) -> Vec<RegVerb> {
         self.reg
             .keys()
-            .iter()
-            .fold(Ok(()), |acc: NormalResult, (name, key)| {
-                let regkey = keyname_to_regkey(name, HKEY_CLASSES_ROOT)?;
-
-                key.values()
-                    .iter()
-                    .fold(Ok(()), |acc: NormalResult, (vname, value)| {
-                        if let Some(regvalue) = regasii_to_winreg(value.clone()) {
-                            regkey.set_raw_value::<&str>(vname.raw(), &regvalue)?;
-                            acc
-                        } else {
-                            Err(format!("Key has invalid value:{:?}, {:?}", name, vname).into())
-                        }
-                    })?;
-
-                acc
+            .keys()
+            .filter_map(|name| {
+                let (hive, path) = split_key_name(name)?;
+                let (location, verb) = verb_of(&path)?;
+                Some((hive, location, verb))
             })
+            .fold(
+                Vec::new(),
+                |mut verbs: Vec<RegVerb>, (hive, location, name)| {
+                    let path = format!("{}\\{}", location, name);
+                    let listed = verbs
+                        .iter()
+                        .any(|verb| verb.hive == hive && verb.path().eq_ignore_ascii_case(&path));
+
+                    if !listed {
+                        let title = self
+                            .own_key(hive, &path)
+                            .and_then(|(key_name, key)| {
+                                let value = key.values().get(&ValueName::Default)?;
+                                self.value_data(key_name, &ValueName::Default, value)
+                            })
+                            .and_then(|data| data.as_string());
+
+                        verbs.push(RegVerb {
+                            hive,
+                            location,
+                            name,
+                            title,
+                        });
+                    }
+
+                    verbs
+                },
+            )
     }
 
-    pub fn load_all_transacted(&self, tr: &Transaction) -> NormalResult {
+    // The key's own `[...]` section, None when the file only lists keys below it
+    fn own_key(&self, hive: Hive, path: &str) -> Option<(&KeyName, &regashii::Key)> {
+        self.reg.keys().iter().find(|(name, _)| {
+            split_key_name(name).is_some_and(|(h, p)| h == hive && p.eq_ignore_ascii_case(path))
+        })
+    }
+
+    /// Keys in the file that hold verbs rather than belong to one, such as `Directory\shell`
+    pub fn locations(&self) -> Vec<(Hive, String)> {
         self.reg
             .keys()
-            .iter()
-            .fold(Ok(()), |acc: NormalResult, (name, key)| {
-                let regkey = keyname_to_regkey_transacted(name, HKEY_CLASSES_ROOT, &tr)?;
+            .keys()
+            .filter_map(split_key_name)
+            .filter(|(_, path)| {
+                verb_of(path).is_none()
+                    && path
+                        .rsplit('\\')
+                        .next()
+                        .is_some_and(|last| last.eq_ignore_ascii_case("shell"))
+            })
+            .collect()
+    }
+
+    /// Builds the tree the file describes for a key and everything under it
+    pub fn tree(&self, hive: Hive, path: &str) -> Result<RegTree, Box<dyn Error>> {
+        let values = match self.own_key(hive, path) {
+            Some((key_name, key)) => key
+                .values()
+                .iter()
+                .map(
+                    |(name, value)| match self.value_data(key_name, name, value) {
+                        Some(data) => Ok((value_name_str(name).to_string(), data)),
+                        None => Err(format!(
+                            "Key {}\\{} has an invalid value: {:?}",
+                            hive, path, name
+                        )
+                        .into()),
+                    },
+                )
+                .collect::<Result<Vec<(String, RegData)>, Box<dyn Error>>>()?,
+            None => Vec::new(),
+        };
+
+        let prefix = format!("{}\\", path);
+
+        let subkeys = self
+            .reg
+            .keys()
+            .keys()
+            .filter_map(split_key_name)
+            .filter_map(|(h, p)| {
+                let rest = p
+                    .get(prefix.len()..)
+                    .filter(|_| h == hive && p[..prefix.len()].eq_ignore_ascii_case(&prefix))?;
 
-                key.values()
+                // The child the key is under, it may not have a line of its own
+                rest.split('\\').next().map(str::to_string)
+            })
+            .fold(Vec::new(), |mut names: Vec<String>, name| {
+                if !names
                     .iter()
-                    .fold(Ok(()), |acc: NormalResult, (vname, value)| {
-                        if let Some(regvalue) = regasii_to_winreg(value.clone()) {
-                            regkey.set_raw_value::<&str>(vname.raw(), &regvalue)?;
-                            acc
-                        } else {
-                            Err(format!("Key has invalid value:{:?}, {:?}", name, vname).into())
-                        }
-                    })?;
-
-                acc
-            })?;
-
-        Ok(())
+                    .any(|listed| listed.eq_ignore_ascii_case(&name))
+                {
+                    names.push(name);
+                }
+                names
+            })
+            .into_iter()
+            .map(|name| {
+                let tree = self.tree(hive, &format!("{}\\{}", path, name))?;
+                Ok((name, tree))
+            })
+            .collect::<Result<Vec<(String, RegTree)>, Box<dyn Error>>>()?;
+
+        Ok(RegTree { values, subkeys })
     }
 }
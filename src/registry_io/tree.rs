@@ -0,0 +1,58 @@
+use std::error::Error;
+
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegData, RegistryBackend};
+
+/// A key with every one of its values and subkeys, read out of a backend so it can be
+/// compared or written back somewhere else untouched
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegTree {
+    pub values: Vec<(String, RegData)>,
+    pub subkeys: Vec<(String, RegTree)>,
+}
+
+impl RegTree {
+    pub fn read(reg: &dyn RegistryBackend, hive: Hive, path: &str) -> Result<Self, Box<dyn Error>> {
+        let subkeys = reg
+            .enum_keys(hive, path)?
+            .into_iter()
+            .map(|name| {
+                let tree = Self::read(reg, hive, &format!("{}\\{}", path, name))?;
+                Ok((name, tree))
+            })
+            .collect::<Result<Vec<(String, RegTree)>, Box<dyn Error>>>()?;
+
+        Ok(Self {
+            values: reg.enum_values(hive, path)?,
+            subkeys,
+        })
+    }
+
+    /// Creates the key if needed and writes every value and subkey into it,
+    /// anything already there that the tree doesn't mention is left alone
+    pub fn write(&self, reg: &dyn RegistryBackend, hive: Hive, path: &str) -> NormalResult {
+        reg.create_key(hive, path)?;
+
+        self.values
+            .iter()
+            .try_for_each(|(name, data)| reg.set_value(hive, path, name, data))?;
+
+        self.subkeys
+            .iter()
+            .try_for_each(|(name, tree)| tree.write(reg, hive, &format!("{}\\{}", path, name)))
+    }
+
+    pub fn value(&self, name: &str) -> Option<&RegData> {
+        self.values
+            .iter()
+            .find(|(value_name, _)| value_name.eq_ignore_ascii_case(name))
+            .map(|(_, data)| data)
+    }
+
+    pub fn subkey(&self, name: &str) -> Option<&RegTree> {
+        self.subkeys
+            .iter()
+            .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+            .map(|(_, tree)| tree)
+    }
+}
@@ -0,0 +1,254 @@
+use std::{cell::RefCell, error::Error, io};
+
+use windows::{
+    Win32::{
+        Foundation::{ERROR_SUCCESS, WIN32_ERROR},
+        System::Registry::{HKEY, REG_VALUE_TYPE, RegEnumValueW, RegQueryValueExW, RegSetValueExW},
+    },
+    core::{PCWSTR, PWSTR},
+};
+use winreg::{
+    RegKey,
+    enums::{HKEY_CLASSES_ROOT, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_ALL_ACCESS, KEY_READ},
+    transaction::Transaction,
+};
+
+use crate::edit_context_lib::types::NormalResult;
+use crate::registry_io::backend::{Hive, RegData, RegDataType, RegistryBackend};
+
+fn predef(hive: Hive) -> RegKey {
+    match hive {
+        Hive::ClassesRoot => RegKey::predef(HKEY_CLASSES_ROOT),
+        Hive::CurrentUser => RegKey::predef(HKEY_CURRENT_USER),
+        Hive::LocalMachine => RegKey::predef(HKEY_LOCAL_MACHINE),
+    }
+}
+
+fn check(err: WIN32_ERROR) -> io::Result<()> {
+    if err == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(err.0 as i32))
+    }
+}
+
+fn wide(str: &str) -> Vec<u16> {
+    str.encode_utf16().chain([0_u16]).collect()
+}
+
+fn handle(key: &RegKey) -> HKEY {
+    HKEY(key.raw_handle())
+}
+
+// winreg refuses types past REG_QWORD, so values go through the raw API to keep every
+// type and byte as it is
+fn enum_raw_values(key: &RegKey) -> io::Result<Vec<(String, RegData)>> {
+    let info = key.query_info()?;
+
+    (0..info.values)
+        .map(|index| {
+            // Room for the terminating null
+            let mut name = vec![0_u16; info.max_value_name_len as usize + 1];
+            let mut name_len = name.len() as u32;
+            let mut vtype = 0_u32;
+            let mut bytes = vec![0_u8; info.max_value_len as usize];
+            let mut len = bytes.len() as u32;
+
+            check(unsafe {
+                RegEnumValueW(
+                    handle(key),
+                    index,
+                    Some(PWSTR(name.as_mut_ptr())),
+                    &mut name_len,
+                    None,
+                    Some(&mut vtype),
+                    Some(bytes.as_mut_ptr()),
+                    Some(&mut len),
+                )
+            })?;
+
+            bytes.truncate(len as usize);
+
+            Ok((
+                String::from_utf16_lossy(&name[..name_len as usize]),
+                RegData {
+                    bytes,
+                    vtype: RegDataType::from_raw(vtype),
+                },
+            ))
+        })
+        .collect()
+}
+
+fn query_raw_value(key: &RegKey, name: &str) -> io::Result<RegData> {
+    let name = wide(name);
+    let mut vtype = REG_VALUE_TYPE(0);
+    let mut len = 0_u32;
+
+    // The first call only asks for the size
+    check(unsafe {
+        RegQueryValueExW(
+            handle(key),
+            PCWSTR(name.as_ptr()),
+            None,
+            Some(&mut vtype),
+            None,
+            Some(&mut len),
+        )
+    })?;
+
+    let mut bytes = vec![0_u8; len as usize];
+
+    check(unsafe {
+        RegQueryValueExW(
+            handle(key),
+            PCWSTR(name.as_ptr()),
+            None,
+            Some(&mut vtype),
+            Some(bytes.as_mut_ptr()),
+            Some(&mut len),
+        )
+    })?;
+
+    bytes.truncate(len as usize);
+
+    Ok(RegData {
+        bytes,
+        vtype: RegDataType::from_raw(vtype.0),
+    })
+}
+
+fn set_raw_value(key: &RegKey, name: &str, data: &RegData) -> io::Result<()> {
+    let name = wide(name);
+
+    check(unsafe {
+        RegSetValueExW(
+            handle(key),
+            PCWSTR(name.as_ptr()),
+            None,
+            REG_VALUE_TYPE(data.vtype.raw()),
+            Some(&data.bytes),
+        )
+    })
+}
+
+// RegDeleteTree has no transacted form, so subtrees are removed one key at a time
+fn delete_tree_transacted(parent: &RegKey, name: &str, tr: &Transaction) -> io::Result<()> {
+    let key = parent.open_subkey_transacted_with_flags(name, tr, KEY_ALL_ACCESS)?;
+
+    key.enum_keys()
+        .collect::<io::Result<Vec<String>>>()?
+        .iter()
+        .try_for_each(|sub| delete_tree_transacted(&key, sub, tr))?;
+
+    parent.delete_subkey_transacted(name, tr)
+}
+
+/// The live Windows registry, while a transaction is open every key is opened through it
+#[derive(Default)]
+pub struct WinRegistry {
+    transaction: RefCell<Option<Transaction>>,
+}
+
+impl WinRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn open_with(&self, hive: Hive, path: &str, perms: u32) -> io::Result<RegKey> {
+        match self.transaction.borrow().as_ref() {
+            Some(tr) => predef(hive).open_subkey_transacted_with_flags(path, tr, perms),
+            None => predef(hive).open_subkey_with_flags(path, perms),
+        }
+    }
+
+    fn open_read(&self, hive: Hive, path: &str) -> io::Result<RegKey> {
+        self.open_with(hive, path, KEY_READ)
+    }
+
+    fn open_write(&self, hive: Hive, path: &str) -> io::Result<RegKey> {
+        self.open_with(hive, path, KEY_ALL_ACCESS)
+    }
+}
+
+impl RegistryBackend for WinRegistry {
+    fn key_exists(&self, hive: Hive, path: &str) -> bool {
+        self.open_read(hive, path).is_ok()
+    }
+
+    fn create_key(&self, hive: Hive, path: &str) -> NormalResult {
+        match self.transaction.borrow().as_ref() {
+            Some(tr) => predef(hive).create_subkey_transacted(path, tr)?,
+            None => predef(hive).create_subkey(path)?,
+        };
+        Ok(())
+    }
+
+    fn delete_key_all(&self, hive: Hive, path: &str) -> NormalResult {
+        // delete_subkey_all with an empty path would clear the parent instead of removing it
+        let (parent, name) = match path.trim_end_matches('\\').rsplit_once('\\') {
+            Some((parent, name)) => (parent, name),
+            None => ("", path),
+        };
+
+        let parent_key = self.open_write(hive, parent)?;
+
+        match self.transaction.borrow().as_ref() {
+            Some(tr) => delete_tree_transacted(&parent_key, name, tr)?,
+            None => parent_key.delete_subkey_all(name)?,
+        };
+        Ok(())
+    }
+
+    fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(self
+            .open_read(hive, path)?
+            .enum_keys()
+            .collect::<Result<Vec<String>, io::Error>>()?)
+    }
+
+    fn enum_values(
+        &self,
+        hive: Hive,
+        path: &str,
+    ) -> Result<Vec<(String, RegData)>, Box<dyn Error>> {
+        Ok(enum_raw_values(&self.open_read(hive, path)?)?)
+    }
+
+    fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegData, Box<dyn Error>> {
+        Ok(query_raw_value(&self.open_read(hive, path)?, name)?)
+    }
+
+    fn set_value(&self, hive: Hive, path: &str, name: &str, value: &RegData) -> NormalResult {
+        Ok(set_raw_value(&self.open_write(hive, path)?, name, value)?)
+    }
+
+    fn delete_value(&self, hive: Hive, path: &str, name: &str) -> NormalResult {
+        Ok(self.open_write(hive, path)?.delete_value(name)?)
+    }
+
+    fn begin_transaction(&self) -> NormalResult {
+        let mut transaction = self.transaction.borrow_mut();
+
+        if transaction.is_some() {
+            return Err("A transaction is already in progress".into());
+        }
+
+        *transaction = Some(Transaction::new()?);
+        Ok(())
+    }
+
+    fn commit_transaction(&self) -> NormalResult {
+        match self.transaction.borrow_mut().take() {
+            Some(tr) => Ok(tr.commit()?),
+            None => Err("No transaction to commit".into()),
+        }
+    }
+
+    fn rollback_transaction(&self) -> NormalResult {
+        match self.transaction.borrow_mut().take() {
+            Some(tr) => Ok(tr.rollback()?),
+            None => Err("No transaction to roll back".into()),
+        }
+    }
+}
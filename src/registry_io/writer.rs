@@ -1,45 +1,30 @@
-use std::{cmp::min, error::Error, path::Path};
+use std::{error::Error, fs, path::Path};
 
-use regashii::{Key, KeyName, Registry, Value, ValueName};
-use winreg::{RegKey, RegValue};
+use regashii::{Key, KeyName, Kind, Registry, Value, ValueName};
 
-fn u8s_to_u32(vec: Vec<u8>) -> u32 {
-    let mut output: u32 = 0_u32;
+use crate::registry_io::backend::{Hive, RegData, RegDataType, RegistryBackend};
 
-    for i in 0..(min(vec.len(), 4)) {
-        output += (vec[0] as u32) << (8 * (3 - i));
-    }
-
-    output
-}
+// Anything regashii would write back differently, a string without its terminating null
+// for example, is written as hex so every value comes back byte for byte.
+// None for types above 255, which a .reg file has no way to write.
+fn regdata_to_regashii(value: RegData) -> Option<Value> {
+    use regashii::Value::*;
 
-fn u8s_to_u64(vec: Vec<u8>) -> u64 {
-    let mut output: u64 = 0_64;
+    let plain_sz = value
+        .as_string()
+        .filter(|str| value.vtype == RegDataType::Sz && RegData::sz(str).bytes == value.bytes);
 
-    for i in 0..(min(vec.len(), 8)) {
-        output += (vec[0] as u64) << (8 * (7 - i));
+    if let Some(str) = plain_sz {
+        return Some(Sz(str));
     }
 
-    output
-}
-
-fn winreg_to_regashii(value: RegValue) -> Option<Value> {
-    use regashii::Value::*;
-    use winreg::enums::RegType::*;
-
-    match value.vtype {
-        REG_SZ => Some(Sz(value.to_string())),
-        REG_EXPAND_SZ => Some(ExpandSz(value.to_string())),
-        REG_NONE => None,
-        REG_BINARY => Some(Binary(value.bytes)),
-        REG_DWORD => Some(Dword(u8s_to_u32(value.bytes))),
-        REG_DWORD_BIG_ENDIAN => Some(DwordBigEndian(u8s_to_u32(value.bytes))),
-        REG_LINK => panic!("Link values are not supported!"),
-        REG_MULTI_SZ => panic!("Multi sz are not supported!"),
-        REG_RESOURCE_LIST => panic!("Resource lists are not supported!"),
-        REG_FULL_RESOURCE_DESCRIPTOR => panic!("Full resource descriptors are not supported!"),
-        REG_RESOURCE_REQUIREMENTS_LIST => panic!("Resource requirements lists are not supported!"),
-        REG_QWORD => Some(Qword(u8s_to_u64(value.bytes))),
+    match (value.vtype, value.bytes.as_slice()) {
+        (RegDataType::Dword, [a, b, c, d]) => Some(Dword(u32::from_le_bytes([*a, *b, *c, *d]))),
+        (RegDataType::Binary, _) => Some(Binary(value.bytes)),
+        (vtype, _) => u8::try_from(vtype.raw()).ok().map(|kind| Hex {
+            kind: Kind::from(kind),
+            bytes: value.bytes,
+        }),
     }
 }
 
@@ -60,47 +45,195 @@ impl RegWriter {
     }
 
     pub fn with_all_subkeys(
+        self,
+        reg: &dyn RegistryBackend,
+        hive: Hive,
+        path: String,
+    ) -> Result<Self, Box<dyn Error>> {
+        let name = format!("{}\\{}", hive, path);
+        self.with_subtree_as(reg, hive, path, name)
+    }
+
+    /// Copies the key and everything under it, but writes it out under `name`
+    pub fn with_subtree_as(
         mut self,
-        regkey: RegKey,
+        reg: &dyn RegistryBackend,
+        hive: Hive,
+        path: String,
         name: String,
     ) -> Result<Self, Box<dyn Error>> {
-        let curkey: Key = regkey
-            .enum_values()
-            .filter_map(|x| if let Ok(item) = x { Some(item) } else { None })
-            .fold(Key::new(), |acc, (key, value)| {
-                if let Some(regash_val) = winreg_to_regashii(value) {
-                    if key.is_empty() {
-                        acc.with(ValueName::Default, regash_val)
-                    } else {
-                        acc.with(ValueName::Named(key), regash_val)
+        // One value that can't be written shouldn't stop the rest from being exported
+        let curkey: Key = reg.enum_values(hive, &path)?.into_iter().fold(
+            Key::new(),
+            |acc, (key, value)| {
+                let vtype = value.vtype.raw();
+
+                match regdata_to_regashii(value) {
+                    Some(regash_val) if key.is_empty() => acc.with(ValueName::Default, regash_val),
+                    Some(regash_val) => acc.with(ValueName::Named(key), regash_val),
+                    None => {
+                        log::warn!(
+                            "Skipped {}\\{} \"{}\", value type {} can't be written to a .reg file",
+                            hive,
+                            path,
+                            key,
+                            vtype
+                        );
+                        acc
                     }
-                } else {
-                    acc
                 }
-            });
+            },
+        );
 
         self = self.with_key(name.clone(), curkey);
 
-        Ok(regkey
-            .enum_keys()
-            .filter_map(|x: Result<String, std::io::Error>| {
-                if let Ok(key) = x {
-                    if key.is_empty() { None } else { Some(key) }
-                } else {
-                    None
-                }
+        reg.enum_keys(hive, &path)?
+            .into_iter()
+            .filter(|key| !key.is_empty())
+            .try_fold(self, |acc: RegWriter, val: String| {
+                let subpath = path.clone() + "\\" + &val;
+                let subname = name.clone() + "\\" + &val;
+                acc.with_subtree_as(reg, hive, subpath, subname)
             })
-            .fold(
-                Ok(self),
-                |acc: Result<RegWriter, Box<dyn Error>>, val: String| {
-                    let subkey = regkey.open_subkey(&val)?;
-                    let subname = name.clone() + "\\" + &val;
-                    Ok(acc?.with_all_subkeys(subkey, subname)?)
-                },
-            )?)
+    }
+
+    // What serialize_file writes for the Regedit5 format, UTF-16LE with a byte order mark
+    fn file_bytes(&self) -> Vec<u8> {
+        format!("\u{feff}{}", self.reg.serialize())
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    /// Whether the file holds exactly what this writer would write
+    pub fn same_as_file<T: AsRef<Path>>(&self, path: T) -> bool {
+        fs::read(path).is_ok_and(|bytes| bytes == self.file_bytes())
+    }
+
+    pub fn into_registry(self) -> Registry {
+        self.reg
     }
 
     pub fn write_to<T: AsRef<Path>>(&self, path: T) -> Result<(), Box<dyn Error>> {
         Ok(self.reg.serialize_file(path)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry_io::memory::MemoryRegistry;
+    use crate::registry_io::reader::RegReader;
+    use crate::registry_io::tree::RegTree;
+
+    const KEY: &str = "Directory\\shell\\Values";
+
+    fn data(vtype: RegDataType, bytes: &[u8]) -> RegData {
+        RegData {
+            bytes: bytes.to_vec(),
+            vtype,
+        }
+    }
+
+    fn round_trip(values: &[(&str, RegData)]) -> (RegTree, RegTree) {
+        let reg = MemoryRegistry::new();
+        reg.create_key(Hive::ClassesRoot, KEY).unwrap();
+        values
+            .iter()
+            .for_each(|(name, value)| reg.set_value(Hive::ClassesRoot, KEY, name, value).unwrap());
+
+        let file =
+            std::env::temp_dir().join(format!("context-editor-{}.reg", uuid::Uuid::new_v4()));
+        RegWriter::new()
+            .with_all_subkeys(&reg, Hive::ClassesRoot, KEY.to_string())
+            .unwrap()
+            .write_to(&file)
+            .unwrap();
+
+        let read = RegReader::try_read_file(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let mut expected = RegTree::read(&reg, Hive::ClassesRoot, KEY).unwrap();
+        let mut actual = read.tree(Hive::ClassesRoot, KEY).unwrap();
+        expected.values.sort_by(|a, b| a.0.cmp(&b.0));
+        actual.values.sort_by(|a, b| a.0.cmp(&b.0));
+
+        (expected, actual)
+    }
+
+    #[test]
+    fn every_value_type_round_trips() {
+        let long_binary = (0..=255).collect::<Vec<u8>>();
+
+        let (expected, actual) = round_trip(&[
+            ("", RegData::sz("Open \"here\" \\ now")),
+            ("ExpandSz", RegData::expand_sz("%SystemRoot%\\system32")),
+            ("Binary", data(RegDataType::Binary, &long_binary)),
+            (
+                "Dword",
+                data(RegDataType::Dword, &0xdeadbeef_u32.to_le_bytes()),
+            ),
+            (
+                "DwordBigEndian",
+                data(RegDataType::DwordBigEndian, &0xdeadbeef_u32.to_be_bytes()),
+            ),
+            ("Qword", data(RegDataType::Qword, &u64::MAX.to_le_bytes())),
+            ("Link", data(RegDataType::Link, &[0x5c, 0, 0x3f, 0])),
+            (
+                "MultiSz",
+                data(RegDataType::MultiSz, &[0x61, 0, 0, 0, 0x62, 0, 0, 0, 0, 0]),
+            ),
+            ("ResourceList", data(RegDataType::ResourceList, &[1, 2, 3])),
+            (
+                "FullResourceDescriptor",
+                data(RegDataType::FullResourceDescriptor, &[4, 5]),
+            ),
+            (
+                "ResourceRequirementsList",
+                data(RegDataType::ResourceRequirementsList, &[6]),
+            ),
+            ("None", data(RegDataType::None, &[])),
+            ("NoneWithData", data(RegDataType::None, &[7, 8])),
+            ("Unknown", data(RegDataType::Unknown(0x20), &[9, 10])),
+        ]);
+
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn values_a_reg_file_cant_hold_are_skipped() {
+        let (expected, actual) = round_trip(&[
+            ("", RegData::sz("Tool")),
+            ("Huge", data(RegDataType::Unknown(0x1000), &[1, 2])),
+        ]);
+
+        assert_eq!(actual.values, [("".to_string(), RegData::sz("Tool"))]);
+        assert_eq!(expected.values.len(), 2);
+    }
+
+    #[test]
+    fn malformed_values_round_trip() {
+        let (expected, actual) = round_trip(&[
+            // No terminating null, then one too many
+            ("SzUnterminated", data(RegDataType::Sz, &[0x61, 0, 0x62, 0])),
+            (
+                "SzDoubleNull",
+                data(RegDataType::Sz, &[0x61, 0, 0, 0, 0, 0]),
+            ),
+            ("SzOddLength", data(RegDataType::Sz, &[0x61, 0, 0])),
+            ("SzNewline", RegData::sz("line\r\nbreak")),
+            ("DwordShort", data(RegDataType::Dword, &[1, 2])),
+            (
+                "DwordBigEndianShort",
+                data(RegDataType::DwordBigEndian, &[1]),
+            ),
+            ("QwordShort", data(RegDataType::Qword, &[1, 2, 3])),
+            (
+                "MultiSzEmptyEntry",
+                data(RegDataType::MultiSz, &[0, 0, 0, 0]),
+            ),
+        ]);
+
+        assert_eq!(expected, actual);
+    }
+}
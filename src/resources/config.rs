@@ -1,24 +1,68 @@
 use std::str::FromStr;
 
+use crate::edit_context_lib::backup::BackupOptions;
+use crate::edit_context_lib::catalogue::{DEFAULT_PINNED, KnownLocation, find_location};
+use crate::edit_context_lib::scope::Scope;
 use crate::edit_context_lib::types::{Key, KeyProps, Keys};
 use ini::{Ini, Properties};
 
 pub type ConfKey<T> = (&'static str, T);
 
-const APP_TITLE: &'static str = "Edit Context Window";
+const APP_TITLE: &str = "Edit Context Window";
 
 const WIDTH_KEY: ConfKey<f32> = ("APP_WIDTH", 400_f32);
 const HEIGHT_KEY: ConfKey<f32> = ("APP_HEIGHT", 600_f32);
 const BACKUP_KEY: ConfKey<bool> = ("AUTO_BACKUP", true);
 const ICON_KEY: ConfKey<f32> = ("ICON_SIZE", 25_f32);
+const BACKUP_DIR_KEY: ConfKey<&str> = ("BACKUP_DIR", "");
+const MAX_BACKUPS_KEY: ConfKey<usize> = ("MAX_BACKUPS", 50);
+const MAX_BACKUP_AGE_KEY: ConfKey<u64> = ("MAX_BACKUP_AGE_DAYS", 90);
+const SCOPE_KEY: ConfKey<Scope> = ("SCOPE", Scope::PerUser);
+/// Names of the known locations shown as tabs, separated by commas
+pub const PINNED_KEY: &str = "PINNED";
+
+// Used when the config has no [ShellExtensions] section
+const SHELL_EXTENSIONS: [ConfKey<&str>; 4] = [
+    ("Files", "*/shellex/ContextMenuHandlers"),
+    ("Directories", "Directory/shellex/ContextMenuHandlers"),
+    (
+        "Background",
+        "Directory/Background/shellex/ContextMenuHandlers",
+    ),
+    ("Drives", "Drive/shellex/ContextMenuHandlers"),
+];
 
 pub struct AppConfig {
     pub title: &'static str,
     pub width: f32,
     pub height: f32,
     pub auto_backup: bool,
+    pub backup: BackupOptions,
     pub icon_size: f32,
+    pub scope: Scope,
+    // As written in the config, see `scoped_keys` for where they are in the registry.
+    // The pinned known locations come first.
     pub keys: Keys,
+    // Names of the known locations among `keys`
+    pub pinned: Vec<String>,
+    // ContextMenuHandlers keys, as written in the config
+    pub extension_keys: Keys,
+}
+
+impl AppConfig {
+    pub fn scoped_keys(&self) -> Keys {
+        self.keys
+            .iter()
+            .map(|key| key.in_scope(self.scope))
+            .collect()
+    }
+
+    pub fn scoped_extension_keys(&self) -> Keys {
+        self.extension_keys
+            .iter()
+            .map(|key| key.in_scope(self.scope))
+            .collect()
+    }
 }
 
 fn get_parse_or<T: FromStr + Copy>(props: &Properties, key: ConfKey<T>) -> T {
@@ -48,6 +92,21 @@ impl From<Ini> for AppConfig {
             None => (WIDTH_KEY.1, HEIGHT_KEY.1, BACKUP_KEY.1, ICON_KEY.1),
         };
 
+        let backup = BackupOptions {
+            dir: options
+                .and_then(|props| props.get(BACKUP_DIR_KEY.0))
+                .unwrap_or(BACKUP_DIR_KEY.1)
+                .to_string(),
+            max_count: options.map_or(MAX_BACKUPS_KEY.1, |props| {
+                get_parse_or(props, MAX_BACKUPS_KEY)
+            }),
+            max_age_days: options.map_or(MAX_BACKUP_AGE_KEY.1, |props| {
+                get_parse_or(props, MAX_BACKUP_AGE_KEY)
+            }),
+        };
+
+        let scope = options.map_or(SCOPE_KEY.1, |props| get_parse_or(props, SCOPE_KEY));
+
         let keys_sec = conf.section(Some("RegKeys"));
 
         let keys: Keys = match keys_sec {
@@ -83,13 +142,46 @@ impl From<Ini> for AppConfig {
             None => keys,
         };
 
+        let pinned = match options.and_then(|props| props.get(PINNED_KEY)) {
+            Some(names) => names
+                .split(',')
+                .map(str::trim)
+                .filter_map(find_location)
+                .map(|location| location.name.to_string())
+                .collect::<Vec<String>>(),
+            None => DEFAULT_PINNED.iter().map(ToString::to_string).collect(),
+        };
+
+        // A location in [RegKeys] with the name of a pinned one is left out
+        let keys: Keys = pinned
+            .iter()
+            .filter_map(|name| find_location(name))
+            .map(KnownLocation::key)
+            .chain(keys.into_iter().filter(|key| !pinned.contains(&key.name)))
+            .collect();
+
+        let extension_keys: Keys = match conf.section(Some("ShellExtensions")) {
+            Some(props) => props
+                .iter()
+                .map(|(name, path)| Key::new(name).with_path(path))
+                .collect(),
+            None => SHELL_EXTENSIONS
+                .iter()
+                .map(|(name, path)| Key::new(*name).with_path(*path))
+                .collect(),
+        };
+
         AppConfig {
             title: APP_TITLE,
             width,
             height,
             auto_backup,
+            backup,
             icon_size,
+            scope,
             keys,
+            pinned,
+            extension_keys,
         }
     }
 }
@@ -1,2 +1,3 @@
 pub mod config;
+#[allow(clippy::module_inception)]
 pub mod resources;
@@ -1,4 +1,4 @@
-use std::{error::Error, path::PathBuf};
+use std::{error::Error, fs, path::PathBuf};
 
 use ini::Ini;
 
@@ -22,3 +22,76 @@ pub fn load_config_ini() -> Result<Ini, Box<dyn Error>> {
 
     Ok(config)
 }
+
+// `text` with `key` set to `value` in its [Options] section, everything else is kept as it is
+fn with_option(text: &str, key: &str, value: &str) -> String {
+    let line = format!("{}={}", key, value);
+    let mut section = "";
+    let mut replaced = false;
+
+    let mut lines = text
+        .lines()
+        .map(|current| {
+            let trimmed = current.trim();
+
+            if trimmed.starts_with('[') {
+                section = trimmed;
+            } else if section.eq_ignore_ascii_case("[Options]")
+                && trimmed
+                    .split_once('=')
+                    .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case(key))
+            {
+                replaced = true;
+                return line.clone();
+            }
+
+            current.to_string()
+        })
+        .collect::<Vec<String>>();
+
+    if !replaced {
+        match lines
+            .iter()
+            .position(|current| current.trim().eq_ignore_ascii_case("[Options]"))
+        {
+            Some(index) => lines.insert(index + 1, line),
+            None => {
+                lines = ["[Options]".to_string(), line, String::new()]
+                    .into_iter()
+                    .chain(lines)
+                    .collect()
+            }
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Sets an option in config.ini, keeping the comments and the rest of the file
+pub fn save_config_option(key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let path = get_resource_path()?.join("config.ini");
+    let text = fs::read_to_string(&path)?;
+
+    fs::write(path, with_option(&text, key, value))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_set_without_touching_the_rest_of_the_file() {
+        let text = "; comment\n[Options]\nSCOPE=PerUser\n\n[RegKeys]\nPINNED=kept\n";
+
+        assert_eq!(
+            with_option(text, "PINNED", "File"),
+            "; comment\n[Options]\nPINNED=File\nSCOPE=PerUser\n\n[RegKeys]\nPINNED=kept\n"
+        );
+        assert_eq!(
+            with_option(&with_option(text, "PINNED", "File"), "PINNED", "Drive"),
+            "; comment\n[Options]\nPINNED=Drive\nSCOPE=PerUser\n\n[RegKeys]\nPINNED=kept\n"
+        );
+    }
+}
@@ -1,63 +1,321 @@
-use crate::parsing::folderize_title;
+use std::{path::PathBuf, rc::Rc};
+
+use crate::edit_context_lib::attributes::{
+    APPLIES_TO, COMMAND_FLAGS, HIDE_BASED_ON_VELOCITY_ID, MUI_VERB, MULTI_SELECT_MODEL,
+    MultiSelectModel, POSITION, Position, VerbAttributes,
+};
+use crate::edit_context_lib::catalogue::{KnownLocation, LOCATIONS};
+use crate::edit_context_lib::diff::{DiffKind, VerbDiff, diff_to_json, diff_to_text};
+use crate::edit_context_lib::export::ExportTarget;
+use crate::edit_context_lib::import::{ImportChoice, ImportPlan, ImportVerb};
+use crate::edit_context_lib::placeholder::{
+    check_placeholders, default_placeholder, replace_placeholder,
+};
+use crate::edit_context_lib::simulate::{EffectiveVerb, Simulation, TargetKind};
+use crate::edit_context_lib::staging::{ChangePreview, PreviewAction};
+use crate::edit_context_lib::submenu::with_child;
+use crate::parsing::{check_key_name, folderize_title, unique_key_name};
+use crate::registry_io::reader::{RegReader, RegVerb};
 use crate::ui::components::{
-    add_box_with_label, add_dialog_button, button_secondary, button_standard,
+    add_box_with_label, add_dialog_button, button_action, button_secondary, button_standard,
 };
 use crate::{edit_context_lib::types::StdCommand, ui::message::Message};
-use egui::Ui;
+use egui::{Color32, DragValue, RichText, ScrollArea, Ui};
 
 #[derive(Default, Clone, Debug)]
 pub enum AppModal {
     Create(StdCommand),
     Edit(StdCommand),
     Delete(StdCommand),
+    // Rc so the preview isn't copied every frame
+    Review(Rc<[ChangePreview]>),
+    Backups(BackupBrowser),
+    Diff(Rc<[VerbDiff]>),
+    Export(ExportTarget),
+    Import(ImportDialog),
+    // The submenu and the entry being added to it
+    CreateSub(Box<StdCommand>, StdCommand),
+    // Title of the submenu the selected entries are grouped into
+    Group(String),
+    // The catalogue of known locations
+    Locations,
+    Simulate(Simulator),
     #[default]
     None,
 }
 
+/// A backup file that has been parsed, along with which of its verbs are ticked for restoring
+#[derive(Clone, Debug)]
+pub struct OpenBackup {
+    pub file: PathBuf,
+    pub reader: Rc<RegReader>,
+    pub verbs: Rc<[RegVerb]>,
+    pub checked: Vec<bool>,
+}
+
+impl OpenBackup {
+    pub fn new(file: PathBuf, reader: RegReader) -> Self {
+        let verbs: Rc<[RegVerb]> = reader.verbs().into();
+
+        Self {
+            file,
+            checked: vec![false; verbs.len()],
+            reader: Rc::new(reader),
+            verbs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BackupBrowser {
+    pub files: Rc<[PathBuf]>,
+    pub open: Option<OpenBackup>,
+}
+
+/// A .reg file picked for importing, with a choice made for each of its verbs
+#[derive(Clone, Debug)]
+pub struct ImportDialog {
+    pub file: PathBuf,
+    pub reader: Rc<RegReader>,
+    pub plan: ImportPlan,
+    // Offered when a conflicting verb is switched to renaming, one per verb
+    pub suggested: Vec<String>,
+}
+
+/// An item to build the effective menu for, and the menu once it has been built
+#[derive(Clone, Debug, Default)]
+pub struct Simulator {
+    pub path: String,
+    pub kind: TargetKind,
+    // As if Shift is held
+    pub extended: bool,
+    pub result: Option<Rc<Simulation>>,
+}
+
 impl AppModal {
     pub fn default_create() -> Self {
         AppModal::Create(StdCommand::default())
     }
 }
 
-fn command_input_menu(ui: &mut Ui, cmd: &mut StdCommand) {
+// Placeholders the location leaves empty, each with a button to use the right one
+fn placeholder_warnings(ui: &mut Ui, cmd: &mut StdCommand, location: Option<&KnownLocation>) {
+    check_placeholders(&cmd.command, location)
+        .into_iter()
+        .for_each(|warning| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(format!("⚠ {}", warning)).color(Color32::YELLOW));
+
+                if let Some(suggestion) = warning.suggestion
+                    && ui
+                        .add(button_secondary(format!("Use {}", suggestion)))
+                        .clicked()
+                {
+                    cmd.command =
+                        replace_placeholder(&cmd.command, &warning.placeholder, suggestion);
+                }
+            });
+        });
+}
+
+fn command_input_menu(ui: &mut Ui, cmd: &mut StdCommand, location: Option<&KnownLocation>) {
     add_box_with_label(ui, "Title: ", &mut cmd.title);
 
     add_box_with_label(ui, "Command: ", &mut cmd.command);
 
+    placeholder_warnings(ui, cmd, location);
+
     if let Some(path) = add_dialog_button(
         ui,
         "Run File",
         &[("Executable", &["exe"]), ("All files", &["*"])],
-    ) {
-        if let Some(str) = path.to_str() {
-            cmd.command = format!("\"{}\" %V", str);
-        }
+    ) && let Some(str) = path.to_str()
+    {
+        cmd.command = format!("\"{}\" \"{}\"", str, default_placeholder(location));
     }
 
     add_box_with_label(ui, "Icon: ", &mut cmd.icon);
 
     if let Some(path) =
         add_dialog_button(ui, "Choose Icon", &[("Iconable", &["exe", "dll", "ico"])])
+        && let Some(str) = path.to_str()
     {
-        if let Some(str) = path.to_str() {
-            cmd.icon = format!("{}", str);
-        }
+        cmd.icon = str.to_string();
     }
+
+    ui.collapsing("Attributes", |ui: &mut Ui| {
+        attributes_menu(ui, &mut cmd.attributes)
+    });
+}
+
+// A text value that's removed from the verb when left empty
+fn optional_text(ui: &mut Ui, label: &str, value: &mut Option<String>) {
+    let mut text = value.clone().unwrap_or_default();
+    add_box_with_label(ui, label, &mut text);
+    *value = (!text.is_empty()).then_some(text);
+}
+
+// A DWORD value that's removed from the verb when unticked
+fn optional_dword(ui: &mut Ui, label: &str, value: &mut Option<u32>) {
+    ui.horizontal(|ui: &mut Ui| {
+        let mut set = value.is_some();
+        ui.checkbox(&mut set, label);
+
+        *value = match (set, *value) {
+            (true, Some(mut number)) => {
+                ui.add(DragValue::new(&mut number).hexadecimal(8, false, true));
+                Some(number)
+            }
+            (true, None) => Some(0),
+            (false, _) => None,
+        };
+    });
+}
+
+fn optional_choice<T: Copy + PartialEq + ToString>(
+    ui: &mut Ui,
+    label: &str,
+    value: &mut Option<T>,
+    options: &[T],
+) {
+    ui.horizontal(|ui: &mut Ui| {
+        ui.label(label);
+        egui::ComboBox::from_id_salt(label)
+            .selected_text(value.map_or("Not set".to_string(), |value| value.to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(value, None, "Not set");
+                options.iter().for_each(|option| {
+                    ui.selectable_value(value, Some(*option), option.to_string());
+                });
+            });
+    });
 }
 
-pub fn show_create_modal(ui: &mut Ui, current_command: StdCommand) -> Message {
+// A value that was kept because it couldn't be read, until it's removed or the field is set
+fn unparsed_note(ui: &mut Ui, attributes: &mut VerbAttributes, name: &str) {
+    let Some(raw) = attributes.unparsed(name) else {
+        return;
+    };
+    let note = format!("⚠ {} is kept as it is: {}", name, raw);
+
+    ui.horizontal(|ui: &mut Ui| {
+        ui.label(RichText::new(note).color(Color32::YELLOW));
+
+        if ui.add(button_secondary("Remove")).clicked() {
+            attributes.clear_unparsed(name);
+        }
+    });
+}
+
+fn attributes_menu(ui: &mut Ui, attributes: &mut VerbAttributes) {
+    let before = attributes.clone();
+
+    ui.checkbox(&mut attributes.disabled, "Disabled")
+        .on_hover_text("Hidden from the menu with LegacyDisable, nothing is deleted");
+    ui.checkbox(&mut attributes.extended, "Extended")
+        .on_hover_text("Only shown while Shift is held");
+    optional_choice(ui, "Position: ", &mut attributes.position, &Position::ALL);
+    unparsed_note(ui, attributes, POSITION);
+    ui.checkbox(&mut attributes.separator_before, "Separator before");
+    ui.checkbox(&mut attributes.separator_after, "Separator after");
+    optional_text(ui, "MUIVerb: ", &mut attributes.mui_verb);
+    unparsed_note(ui, attributes, MUI_VERB);
+    ui.checkbox(&mut attributes.never_default, "Never default")
+        .on_hover_text("Never run on double click");
+    ui.checkbox(&mut attributes.has_lua_shield, "UAC shield icon");
+    ui.checkbox(
+        &mut attributes.programmatic_access_only,
+        "Programmatic access only",
+    )
+    .on_hover_text("Hidden from the menu, only programs can invoke it");
+    optional_choice(
+        ui,
+        "Multi-select: ",
+        &mut attributes.multi_select_model,
+        &MultiSelectModel::ALL,
+    );
+    unparsed_note(ui, attributes, MULTI_SELECT_MODEL);
+    optional_text(ui, "AppliesTo: ", &mut attributes.applies_to);
+    unparsed_note(ui, attributes, APPLIES_TO);
+    optional_dword(ui, "CommandFlags", &mut attributes.command_flags);
+    unparsed_note(ui, attributes, COMMAND_FLAGS);
+    optional_dword(
+        ui,
+        "HideBasedOnVelocityId",
+        &mut attributes.hide_based_on_velocity_id,
+    );
+    unparsed_note(ui, attributes, HIDE_BASED_ON_VELOCITY_ID);
+
+    // A field that's been changed no longer keeps the value it couldn't read
+    [
+        (POSITION, before.position != attributes.position),
+        (MUI_VERB, before.mui_verb != attributes.mui_verb),
+        (
+            MULTI_SELECT_MODEL,
+            before.multi_select_model != attributes.multi_select_model,
+        ),
+        (APPLIES_TO, before.applies_to != attributes.applies_to),
+        (
+            COMMAND_FLAGS,
+            before.command_flags != attributes.command_flags,
+        ),
+        (
+            HIDE_BASED_ON_VELOCITY_ID,
+            before.hide_based_on_velocity_id != attributes.hide_based_on_velocity_id,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .for_each(|(name, _)| attributes.clear_unparsed(name));
+}
+
+pub fn show_create_modal(
+    ui: &mut Ui,
+    current_command: StdCommand,
+    taken: &dyn Fn(&str) -> bool,
+    location: Option<&KnownLocation>,
+) -> Message {
     let mut new_command = current_command;
 
-    command_input_menu(ui, &mut new_command);
+    // The key name follows the title until it's changed by hand
+    let derived =
+        new_command.folder.is_empty() || new_command.folder == folderize_title(&new_command.title);
+
+    command_input_menu(ui, &mut new_command, location);
+
+    if derived {
+        new_command.folder = folderize_title(&new_command.title);
+    }
+
+    add_box_with_label(ui, "Key name: ", &mut new_command.folder);
+
+    let problem = check_key_name(&new_command.folder)
+        .err()
+        .map(|err| err.to_string())
+        .or_else(|| {
+            taken(&new_command.folder)
+                .then(|| format!("\"{}\" already exists here", new_command.folder))
+        });
+
+    let rename_clicked = problem.as_ref().is_some_and(|problem| {
+        ui.label(RichText::new(format!("⚠ {}", problem)).color(Color32::YELLOW));
+
+        let unique = unique_key_name(&folderize_title(&new_command.folder), taken);
+        ui.add(button_secondary(format!("Use \"{}\"", unique)))
+            .clicked()
+            .then(|| new_command.folder = unique)
+            .is_some()
+    });
 
     ui.add_space(10f32);
 
     ui.horizontal(|ui: &mut Ui| {
-        if ui.add(button_standard("Confirm")).clicked() {
-            let mut command = new_command.clone(); // TODO, Get rid of this clone
-            command.folder = folderize_title(&command.title);
-            Message::AddCommand(new_command.clone()) // TODO: Get rid of this clone
+        if !rename_clicked
+            && ui
+                .add_enabled(problem.is_none(), button_standard("Confirm"))
+                .clicked()
+        {
+            Message::AddCommand(new_command)
         } else if ui.add(button_secondary("Cancel")).clicked() {
             Message::UpdateModal(AppModal::None)
         } else {
@@ -67,10 +325,76 @@ pub fn show_create_modal(ui: &mut Ui, current_command: StdCommand) -> Message {
     .inner
 }
 
-pub fn show_edit_modal(ui: &mut Ui, current_command: StdCommand) -> Message {
+pub fn show_sub_command_modal(
+    ui: &mut Ui,
+    parent: Box<StdCommand>,
+    child: StdCommand,
+    location: Option<&KnownLocation>,
+) -> Message {
+    let mut child = child;
+
+    ui.heading(format!("Add to \"{}\"", parent.title));
+    ui.add_space(10f32);
+
+    command_input_menu(ui, &mut child, location);
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui: &mut Ui| {
+        if ui.add(button_standard("Confirm")).clicked() {
+            // Entries of a submenu only have to differ from each other
+            let siblings = parent.submenu.as_ref().map_or(&[][..], |sub| &sub.children);
+            child.folder = unique_key_name(&folderize_title(&child.title), |name| {
+                siblings
+                    .iter()
+                    .any(|sibling| sibling.folder.eq_ignore_ascii_case(name))
+            });
+            Message::UpdateCommand(with_child(&parent, child))
+        } else if ui.add(button_secondary("Cancel")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::CreateSub(parent, child))
+        }
+    })
+    .inner
+}
+
+pub fn show_group_modal(ui: &mut Ui, title: String, count: usize) -> Message {
+    let mut title = title;
+
+    ui.heading(format!("Group {} entries into a submenu", count));
+    ui.add_space(10f32);
+
+    add_box_with_label(ui, "Title: ", &mut title);
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui: &mut Ui| {
+        if ui
+            .add_enabled(!title.trim().is_empty(), button_action("Group"))
+            .clicked()
+        {
+            Message::GroupSelected(title.trim().to_string())
+        } else if ui.add(button_secondary("Cancel")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::Group(title))
+        }
+    })
+    .inner
+}
+
+pub fn show_edit_modal(
+    ui: &mut Ui,
+    current_command: StdCommand,
+    location: Option<&KnownLocation>,
+) -> Message {
     let mut new_command = current_command;
 
-    command_input_menu(ui, &mut new_command);
+    // Changing the key name renames the verb's key when the change is applied
+    add_box_with_label(ui, "Key name: ", &mut new_command.folder);
+
+    command_input_menu(ui, &mut new_command, location);
 
     ui.add_space(10f32);
 
@@ -101,3 +425,506 @@ pub fn show_delete_modal(ui: &mut Ui, current_command: StdCommand) -> Message {
     })
     .inner
 }
+
+fn preview_color(action: PreviewAction) -> Color32 {
+    match action {
+        PreviewAction::Create => Color32::LIGHT_GREEN,
+        PreviewAction::Overwrite => Color32::YELLOW,
+        PreviewAction::Delete => Color32::LIGHT_RED,
+    }
+}
+
+pub fn show_review_modal(ui: &mut Ui, preview: &[ChangePreview]) -> Message {
+    ui.heading("Review changes");
+    ui.add_space(10f32);
+
+    ScrollArea::vertical().max_height(300f32).show(ui, |ui| {
+        if preview.is_empty() {
+            ui.label("The pending changes leave the registry as it is.");
+        }
+
+        preview.iter().for_each(|line| {
+            ui.label(RichText::new(line.to_string()).color(preview_color(line.action)));
+        });
+    });
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui| {
+        if ui.add(button_action("Apply")).clicked() {
+            Message::ApplyChanges
+        } else if ui.add(button_standard("Discard")).clicked() {
+            Message::DiscardChanges
+        } else if ui.add(button_secondary("Close")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::None
+        }
+    })
+    .inner
+}
+
+fn file_label(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+fn backup_file_list(ui: &mut Ui, browser: &BackupBrowser) -> Message {
+    ScrollArea::vertical()
+        .id_salt("Backup-Files")
+        .max_height(300f32)
+        .show(ui, |ui| {
+            if browser.files.is_empty() {
+                ui.label("No backups have been taken yet.");
+            }
+
+            browser.files.iter().fold(Message::None, |msg, file| {
+                let selected = browser.open.as_ref().is_some_and(|open| &open.file == file);
+
+                if ui.selectable_label(selected, file_label(file)).clicked() {
+                    Message::OpenBackup(file.clone())
+                } else {
+                    msg
+                }
+            })
+        })
+        .inner
+}
+
+fn backup_verb_list(ui: &mut Ui, open: &mut OpenBackup) {
+    ScrollArea::vertical()
+        .id_salt("Backup-Verbs")
+        .max_height(300f32)
+        .show(ui, |ui| {
+            if open.verbs.is_empty() {
+                ui.label("This backup has no context menu entries.");
+            }
+
+            // Verbs come out of the file sorted by key, so each location is one run
+            open.verbs.iter().zip(open.checked.iter_mut()).fold(
+                None,
+                |location: Option<String>, (verb, checked)| {
+                    let verb_location = format!("{}\\{}", verb.hive, verb.location);
+
+                    if location.as_ref() != Some(&verb_location) {
+                        ui.add_space(5f32);
+                        ui.strong(&verb_location);
+                    }
+
+                    let label = match &verb.title {
+                        Some(title) if !title.is_empty() => format!("{} ({})", title, verb.name),
+                        _ => verb.name.clone(),
+                    };
+
+                    ui.checkbox(checked, label);
+
+                    Some(verb_location)
+                },
+            );
+        });
+}
+
+pub fn show_backup_modal(ui: &mut Ui, browser: BackupBrowser) -> Message {
+    let mut browser = browser;
+
+    ui.heading("Backups");
+    ui.add_space(10f32);
+
+    let open_msg = ui
+        .horizontal_top(|ui| {
+            let msg = ui.vertical(|ui| backup_file_list(ui, &browser)).inner;
+
+            if let Some(open) = &mut browser.open {
+                ui.separator();
+                ui.vertical(|ui| backup_verb_list(ui, open));
+            }
+
+            msg
+        })
+        .inner;
+
+    if let Message::OpenBackup(_) = open_msg {
+        return open_msg;
+    }
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui| {
+        if let Some(path) = add_dialog_button(ui, "Open File", &[("Registry Files", &["reg"])]) {
+            return Message::OpenBackup(path);
+        }
+
+        if let Some(open) = &browser.open {
+            if ui.add(button_standard("Compare with live")).clicked() {
+                return Message::CompareWithLive(Rc::clone(&open.reader));
+            }
+
+            if let Some(path) =
+                add_dialog_button(ui, "Compare with file", &[("Registry Files", &["reg"])])
+            {
+                return Message::CompareWithFile(Rc::clone(&open.reader), path);
+            }
+        }
+
+        let Some(open) = &browser.open else {
+            return if ui.add(button_secondary("Close")).clicked() {
+                Message::UpdateModal(AppModal::None)
+            } else {
+                Message::UpdateModal(AppModal::Backups(browser.clone()))
+            };
+        };
+
+        let selected = open
+            .verbs
+            .iter()
+            .zip(open.checked.iter())
+            .filter(|(_, checked)| **checked)
+            .map(|(verb, _)| verb.clone())
+            .collect::<Vec<RegVerb>>();
+
+        if ui
+            .add_enabled(
+                !selected.is_empty(),
+                button_action(format!("Restore selected ({})", selected.len())),
+            )
+            .clicked()
+        {
+            Message::RestoreVerbs(Rc::clone(&open.reader), selected)
+        } else if ui.add(button_standard("Restore all")).clicked() {
+            Message::RestoreAll(Rc::clone(&open.reader))
+        } else if ui.add(button_secondary("Close")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::Backups(browser.clone()))
+        }
+    })
+    .inner
+}
+
+fn diff_color(kind: DiffKind) -> Color32 {
+    match kind {
+        DiffKind::Added => preview_color(PreviewAction::Create),
+        DiffKind::Modified => preview_color(PreviewAction::Overwrite),
+        DiffKind::Removed => preview_color(PreviewAction::Delete),
+    }
+}
+
+pub fn show_diff_modal(ui: &mut Ui, diffs: &[VerbDiff]) -> Message {
+    ui.heading("Differences");
+    ui.add_space(10f32);
+
+    ScrollArea::vertical().max_height(300f32).show(ui, |ui| {
+        if diffs.is_empty() {
+            ui.label("Both sides have the same context menu entries.");
+        }
+
+        diffs.iter().for_each(|diff| {
+            let heading = match &diff.title {
+                Some(title) => format!(
+                    "{:?} {}\\{} \"{}\"",
+                    diff.kind, diff.location, diff.verb, title
+                ),
+                None => format!("{:?} {}\\{}", diff.kind, diff.location, diff.verb),
+            };
+
+            ui.label(RichText::new(heading).strong().color(diff_color(diff.kind)));
+
+            diff.changes.iter().for_each(|change| {
+                ui.horizontal(|ui| {
+                    ui.add_space(20f32);
+                    ui.label(RichText::new(change.to_string()).color(diff_color(change.kind)));
+                });
+            });
+        });
+    });
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui| {
+        if ui.add(button_standard("Copy as text")).clicked() {
+            ui.ctx().copy_text(diff_to_text(diffs));
+            Message::None
+        } else if ui.add(button_standard("Copy as JSON")).clicked() {
+            match diff_to_json(diffs) {
+                Ok(json) => ui.ctx().copy_text(json),
+                Err(err) => log::error!("Unable to serialize diff: {}", err),
+            }
+            Message::None
+        } else if ui.add(button_secondary("Close")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::None
+        }
+    })
+    .inner
+}
+
+pub fn show_export_modal(ui: &mut Ui, target: ExportTarget, count: usize) -> Message {
+    let mut target = target;
+
+    ui.heading(format!("Export {} entries", count));
+    ui.add_space(10f32);
+
+    ui.label("Import into:");
+    [ExportTarget::ClassesRoot, ExportTarget::CurrentUserClasses]
+        .into_iter()
+        .for_each(|option| {
+            ui.radio_value(&mut target, option, option.to_string());
+        });
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui| {
+        if ui.add(button_action("Export")).clicked() {
+            match rfd::FileDialog::new()
+                .add_filter("Registry Files", &["reg"])
+                .set_file_name("context-menu.reg")
+                .save_file()
+            {
+                Some(path) => Message::ExportSelected(target, path),
+                None => Message::UpdateModal(AppModal::Export(target)),
+            }
+        } else if ui.add(button_secondary("Cancel")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::Export(target))
+        }
+    })
+    .inner
+}
+
+/// Every known location, with a box to pin it as a tab
+pub fn show_locations_modal(ui: &mut Ui, pinned: &[String]) -> Message {
+    ui.heading("Locations");
+    ui.add_space(10f32);
+
+    let pin_msg = ScrollArea::vertical()
+        .max_height(400f32)
+        .show(ui, |ui| {
+            LOCATIONS.iter().fold(Message::None, |acc, location| {
+                let mut is_pinned = pinned.iter().any(|name| name == location.name);
+
+                let toggled = ui
+                    .horizontal(|ui| {
+                        let toggled = ui.checkbox(&mut is_pinned, location.name).changed();
+                        ui.label(RichText::new(location.path.replace('/', "\\")).weak());
+                        toggled
+                    })
+                    .inner;
+
+                ui.indent(location.name, |ui| {
+                    ui.label(location.shown);
+                    ui.label(
+                        RichText::new(format!("Placeholders: {}", location.placeholders.join(" ")))
+                            .weak(),
+                    );
+                });
+
+                if toggled {
+                    Message::PinLocation(location.name.to_string(), is_pinned)
+                } else {
+                    acc
+                }
+            })
+        })
+        .inner;
+
+    ui.add_space(10f32);
+
+    if ui.add(button_secondary("Close")).clicked() {
+        Message::UpdateModal(AppModal::None)
+    } else {
+        pin_msg
+    }
+}
+
+fn effective_verb_row(ui: &mut Ui, effective: &EffectiveVerb, reason: Option<&str>) {
+    let verb = &effective.verb;
+    let label = format!("{} ({}) from {}", verb.title, verb.folder, effective.source);
+
+    match (reason, &effective.note) {
+        (Some(reason), _) => {
+            ui.label(RichText::new(label).weak()).on_hover_text(reason);
+        }
+        (None, Some(note)) => {
+            ui.label(RichText::new(format!("{} ⚠", label)).color(Color32::YELLOW))
+                .on_hover_text(note);
+        }
+        (None, None) => {
+            ui.label(label);
+        }
+    }
+}
+
+/// Builds the menu Explorer would show for a path from every key that adds to it
+pub fn show_simulate_modal(ui: &mut Ui, simulator: Simulator) -> Message {
+    let mut simulator = simulator;
+
+    ui.heading("Effective menu");
+    ui.add_space(10f32);
+
+    add_box_with_label(ui, "Path: ", &mut simulator.path);
+
+    ui.horizontal(|ui| {
+        TargetKind::ALL.into_iter().for_each(|kind| {
+            ui.radio_value(&mut simulator.kind, kind, kind.to_string());
+        });
+    });
+    ui.checkbox(&mut simulator.extended, "Shift held");
+
+    if let Some(simulation) = &simulator.result {
+        ui.add_space(10f32);
+
+        ScrollArea::vertical().max_height(300f32).show(ui, |ui| {
+            if simulation.shown.is_empty() {
+                ui.label("Nothing is shown for this item.");
+            }
+
+            simulation
+                .shown
+                .iter()
+                .for_each(|shown| effective_verb_row(ui, shown, None));
+
+            if !simulation.hidden.is_empty() {
+                ui.collapsing(format!("Left out ({})", simulation.hidden.len()), |ui| {
+                    simulation
+                        .hidden
+                        .iter()
+                        .for_each(|(hidden, reason)| effective_verb_row(ui, hidden, Some(reason)));
+                });
+            }
+        });
+    }
+
+    ui.add_space(10f32);
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(!simulator.path.trim().is_empty(), button_action("Simulate"))
+            .clicked()
+        {
+            Message::Simulate(simulator)
+        } else if ui.add(button_secondary("Close")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::Simulate(simulator))
+        }
+    })
+    .inner
+}
+
+fn import_verb_row(ui: &mut Ui, import: &mut ImportVerb, suggested: &str) {
+    let verb = &import.verb;
+    let label = match &verb.title {
+        Some(title) if !title.is_empty() => format!("{} ({})", title, verb.name),
+        _ => verb.name.clone(),
+    };
+
+    if !import.conflict {
+        let mut checked = import.choice == ImportChoice::Import;
+
+        if ui.checkbox(&mut checked, label).changed() {
+            import.choice = if checked {
+                ImportChoice::Import
+            } else {
+                ImportChoice::Skip
+            };
+        }
+
+        return;
+    }
+
+    ui.label(RichText::new(format!("{} already exists", label)).color(Color32::ORANGE));
+
+    ui.horizontal(|ui| {
+        let renaming = matches!(import.choice, ImportChoice::Rename(_));
+
+        if ui
+            .radio(import.choice == ImportChoice::Import, "Overwrite")
+            .clicked()
+        {
+            import.choice = ImportChoice::Import;
+        }
+
+        if ui
+            .radio(import.choice == ImportChoice::Skip, "Skip")
+            .clicked()
+        {
+            import.choice = ImportChoice::Skip;
+        }
+
+        if ui.radio(renaming, "Rename").clicked() && !renaming {
+            import.choice = ImportChoice::Rename(suggested.to_string());
+        }
+
+        if let ImportChoice::Rename(name) = &mut import.choice {
+            ui.text_edit_singleline(name);
+        }
+    });
+}
+
+pub fn show_import_modal(ui: &mut Ui, dialog: ImportDialog) -> Message {
+    let mut dialog = dialog;
+
+    ui.heading(format!("Import {}", file_label(&dialog.file)));
+    ui.add_space(10f32);
+
+    if dialog.plan.verbs.is_empty() {
+        ui.label("This file has no entries for any of the configured locations.");
+    }
+
+    ScrollArea::vertical()
+        .id_salt("Import-Verbs")
+        .max_height(300f32)
+        .show(ui, |ui| {
+            dialog
+                .plan
+                .verbs
+                .iter_mut()
+                .zip(dialog.suggested.iter())
+                .fold(None, |location: Option<String>, (import, suggested)| {
+                    let verb_location = format!("{}\\{}", import.verb.hive, import.verb.location);
+
+                    if location.as_ref() != Some(&verb_location) {
+                        ui.add_space(5f32);
+                        ui.strong(&verb_location);
+                    }
+
+                    import_verb_row(ui, import, suggested);
+
+                    Some(verb_location)
+                });
+        });
+
+    if !dialog.plan.ignored.is_empty() {
+        ui.add_space(5f32);
+        ui.label(format!(
+            "{} entries are outside of the configured locations and won't be imported.",
+            dialog.plan.ignored.len()
+        ));
+    }
+
+    ui.add_space(10f32);
+
+    let count = dialog
+        .plan
+        .verbs
+        .iter()
+        .filter(|import| import.choice != ImportChoice::Skip)
+        .count();
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(count > 0, button_action(format!("Import ({})", count)))
+            .clicked()
+        {
+            Message::ApplyImport(Rc::clone(&dialog.reader), dialog.plan.verbs.clone())
+        } else if ui.add(button_secondary("Cancel")).clicked() {
+            Message::UpdateModal(AppModal::None)
+        } else {
+            Message::UpdateModal(AppModal::Import(dialog.clone()))
+        }
+    })
+    .inner
+}
@@ -43,9 +43,9 @@ pub fn button_heading<'a, T: Into<String>>(label: T, selected: bool) -> Button<'
 pub fn add_dialog_button(ui: &mut Ui, label: &str, filters: &[(&str, &[&str])]) -> Option<PathBuf> {
     if ui.add(button_standard(label)).clicked() {
         filters
-            .into_iter()
+            .iter()
             .fold(rfd::FileDialog::new(), |fd, (name, extensions)| {
-                fd.add_filter(*name, *extensions)
+                fd.add_filter(*name, extensions)
             })
             .pick_file()
     } else {
@@ -1,53 +1,180 @@
-use egui::{Context, TextureOptions, Ui};
+use std::rc::Rc;
+
+use egui::{Color32, ColorImage, Context, RichText, TextureOptions, Ui};
 
 use crate::{
-    edit_context_lib::types::{Key, KeyProps, Keys, StdCommand},
+    edit_context_lib::{
+        association::Association,
+        catalogue::location_of,
+        export::ExportTarget,
+        history::History,
+        order::plan_order,
+        origin::Origin,
+        perceived::PerceivedType,
+        scope::Scope,
+        shellex::ShellExtension,
+        staging::PendingChange,
+        submenu::{SubMenu, without_child},
+        types::{Key, KeyProps, Keys, StdCommand, key_path_string},
+    },
+    registry_io::backend::Hive,
     ui::{
         appmodal::AppModal,
         appstyle::AppStyle,
         components::{
-            add_box_with_label, button_action, button_dropdown, button_heading, button_standard,
+            add_box_with_label, button_action, button_dropdown, button_heading, button_secondary,
+            button_standard,
         },
         message::Message,
         state::AppState,
     },
 };
 
-fn item_dropdown(ui: &mut Ui, item: &StdCommand) -> Option<AppModal> {
-    if item.command.is_empty() {
-        if ui.add(button_standard("Add Sub-command")).clicked() {
-            println!("Add Sub-command clicked!");
-        }
+// What is carried while a list item is dragged, its index in the list
+struct DraggedItem(usize);
+
+fn item_dropdown(ui: &mut Ui, item: &StdCommand) -> Option<Message> {
+    // Entries of shared submenus belong to other menus as well
+    let can_nest = item.handler.is_none()
+        && item
+            .submenu
+            .as_ref()
+            .map_or(item.command.is_empty(), SubMenu::is_editable);
+
+    if can_nest && ui.add(button_dropdown("Add Sub-command")).clicked() {
+        return Some(Message::UpdateModal(AppModal::CreateSub(
+            Box::new(item.clone()),
+            StdCommand::default(),
+        )));
     }
 
-    if ui.add(button_dropdown("Edit")).clicked() {
-        Some(AppModal::Edit(item.clone()))
+    let move_to = match item.origin.map(Origin::hive) {
+        Some(Hive::CurrentUser) => Some(("Move to machine", Scope::Machine)),
+        Some(Hive::LocalMachine) => Some(("Move to per-user", Scope::PerUser)),
+        _ => None,
+    };
+
+    let toggle = if item.attributes.disabled {
+        "Enable"
+    } else {
+        "Disable"
+    };
+
+    // COM verbs keep values this app doesn't write, editing would drop them
+    if item.handler.is_none() && ui.add(button_dropdown("Edit")).clicked() {
+        Some(Message::UpdateModal(AppModal::Edit(item.clone())))
+    } else if ui.add(button_dropdown(toggle)).clicked() {
+        Some(Message::ToggleDisabled(item.clone()))
     } else if ui.add(button_dropdown("Remove")).clicked() {
-        Some(AppModal::Delete(item.clone()))
+        Some(Message::UpdateModal(AppModal::Delete(item.clone())))
+    } else if let Some((label, scope)) = move_to
+        && ui.add(button_dropdown(label)).clicked()
+    {
+        Some(Message::MoveVerb(item.clone(), scope))
     } else {
         None
     }
 }
 
+// Shows the entries of a submenu as a tree, `at` is where it is under `root`
+fn render_submenu(
+    ui: &mut Ui,
+    root: &StdCommand,
+    submenu: &SubMenu,
+    at: &[usize],
+    editable: bool,
+) -> Option<Message> {
+    let editable = editable && submenu.is_editable();
+
+    ui.label(RichText::new(submenu.source.to_string()).weak());
+
+    submenu
+        .children
+        .iter()
+        .enumerate()
+        .fold(None, |acc, (index, child)| {
+            let at = [at, &[index]].concat();
+
+            let removed = ui
+                .horizontal(|ui| {
+                    ui.label(item_label(child));
+                    match &child.handler {
+                        Some(handler) => ui.label(RichText::new(handler.to_string()).weak()),
+                        None => ui.label(RichText::new(&child.command).weak()),
+                    };
+                    render_problems(ui, child);
+
+                    editable && ui.small_button("Remove").clicked()
+                })
+                .inner;
+
+            let nested = child.submenu.as_ref().and_then(|nested| {
+                ui.indent((&root.folder, &at), |ui| {
+                    render_submenu(ui, root, nested, &at, editable)
+                })
+                .inner
+            });
+
+            acc.or(removed.then(|| Message::UpdateCommand(without_child(root, &at))))
+                .or(nested)
+        })
+}
+
+// A warning sign listing what couldn't be read, nothing for a complete verb
+fn render_problems(ui: &mut Ui, cmd: &StdCommand) {
+    if cmd.problems.is_empty() {
+        return;
+    }
+
+    ui.label(RichText::new("⚠").color(Color32::YELLOW))
+        .on_hover_text(
+            cmd.problems
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<String>>()
+                .join("\n"),
+        );
+}
+
+// Disabled verbs are greyed out
+fn item_label(item: &StdCommand) -> RichText {
+    let label = match item.origin {
+        Some(origin) => format!("{} [{}]", item.title, origin),
+        None => item.title.clone(),
+    };
+
+    if item.attributes.disabled {
+        RichText::new(format!("{} (disabled)", label)).weak()
+    } else {
+        RichText::new(label)
+    }
+}
+
 pub fn render_context_menu(
     state: &AppState,
     style: &AppStyle,
     ctx: &Context,
     ui: &mut Ui,
 ) -> Message {
-    let message = match &state.path {
+    match &state.path {
         Some((key, index)) => {
-            let extension_input_msg = if key.properties == KeyProps::HasExt {
-                let mut new_extension: String = key.path.borrow()[0].to_string();
+            // An association of a file type is shown in place of the extension key
+            let base = state
+                .associations
+                .first()
+                .map_or(key, |association| &association.key);
+
+            let extension_input_msg = if base.properties == KeyProps::HasExt {
+                let mut new_extension: String = base.extension().to_string();
 
                 add_box_with_label(ui, "Extension: ", &mut new_extension);
 
                 let reload_button = ui.add(button_standard("Reload"));
 
-                if *new_extension.as_str() != *key.path.borrow()[0].clone() {
-                    Message::SetPath(Some((key.clone().with_extension(new_extension), *index)))
+                if *new_extension.as_str() != *base.extension() {
+                    Message::SetPath(Some((base.clone().with_extension(new_extension), *index)))
                 } else if reload_button.clicked() {
-                    Message::ReloadKey((key.clone(), *index))
+                    Message::ReloadKey((base.clone(), *index))
                 } else {
                     Message::None
                 }
@@ -55,36 +182,254 @@ pub fn render_context_menu(
                 Message::None
             };
 
+            let perceived_msg = if key.properties == KeyProps::PerceivedType {
+                render_perceived_types(&state.perceived_types, key, *index, ui)
+            } else {
+                Message::None
+            };
+
+            let association_msg = if state.associations.len() > 1 {
+                ui.horizontal_wrapped(|ui| render_associations(&state.associations, key, ui))
+                    .inner
+            } else {
+                Message::None
+            };
+
             ui.add_space(10_f32);
 
             let blank_items = Vec::new();
             let items = state.items.get(*index).unwrap_or(&blank_items);
 
-            let next_modal_opt = items.iter().fold(None, |acc, (item, img)| {
-                let handle = ctx.load_texture("", img.clone(), TextureOptions::default());
-                let sized_image = egui::load::SizedTexture::new(
-                    handle.id(),
-                    egui::vec2(style.icon_size, style.icon_size),
-                );
+            let item_msg =
+                items
+                    .iter()
+                    .enumerate()
+                    .fold(Message::None, |acc, (row_index, (item, img))| {
+                        let handle = ctx.load_texture("", img.clone(), TextureOptions::default());
+                        let sized_image = egui::load::SizedTexture::new(
+                            handle.id(),
+                            egui::vec2(style.icon_size, style.icon_size),
+                        );
 
-                let response = ui
-                    .menu_image_text_button(sized_image, &item.title, |ui| item_dropdown(ui, item));
+                        let row = ui.horizontal(|ui| {
+                            ui.add(egui::Label::new("☰").sense(egui::Sense::drag()))
+                                .on_hover_text("Drag to reorder")
+                                .dnd_set_drag_payload(DraggedItem(row_index));
 
-                response.inner.unwrap_or(acc)
-            });
+                            let mut selected = state.selected.contains(item);
+
+                            let toggled = ui.checkbox(&mut selected, "").changed();
+
+                            let response =
+                                ui.menu_image_text_button(sized_image, item_label(item), |ui| {
+                                    item_dropdown(ui, item)
+                                });
+                            render_problems(ui, item);
+
+                            match response.inner.flatten() {
+                                Some(msg) => Some(msg),
+                                None if toggled => Some(Message::ToggleSelected(item.clone())),
+                                None => None,
+                            }
+                        });
+
+                        let dropped = row
+                            .response
+                            .dnd_release_payload::<DraggedItem>()
+                            .filter(|dragged| dragged.0 != row_index)
+                            .map(|dragged| Message::MoveItem(dragged.0, row_index));
+                        let row_msg = row.inner.or(dropped);
+
+                        if let Some(handler) = &item.handler {
+                            ui.indent(&item.folder, |ui| {
+                                ui.label(RichText::new(handler.to_string()).weak())
+                                    .on_hover_text(&handler.clsid);
+                            });
+                        }
+
+                        let submenu_msg = item.submenu.as_ref().and_then(|submenu| {
+                            ui.indent(&item.folder, |ui| {
+                                render_submenu(ui, item, submenu, &[], true)
+                            })
+                            .inner
+                        });
+
+                        row_msg.or(submenu_msg).unwrap_or(acc)
+                    });
+
+            let order_msg = if state.reordered {
+                render_order(items, state.prefix_order, &state.default_verbs, ui)
+            } else {
+                Message::None
+            };
+
+            let add_clicked = ui.add(button_action("+ Add New")).clicked();
+            let selection_msg = render_selection(&state.selected, ui);
 
-            if ui.add(button_action("+ Add New")).clicked() {
+            if add_clicked {
                 Message::UpdateModal(AppModal::default_create())
-            } else if let Some(next_modal) = next_modal_opt {
-                Message::UpdateModal(next_modal)
+            } else if !matches!(item_msg, Message::None) {
+                item_msg
+            } else if !matches!(selection_msg, Message::None) {
+                selection_msg
+            } else if !matches!(order_msg, Message::None) {
+                order_msg
+            } else if !matches!(association_msg, Message::None) {
+                association_msg
+            } else if !matches!(perceived_msg, Message::None) {
+                perceived_msg
             } else {
                 extension_input_msg
             }
         }
         _ => Message::None,
-    };
+    }
+}
 
-    message
+// Picks the perceived type whose verbs are shown, with the extensions that have it
+fn render_perceived_types(
+    types: &[PerceivedType],
+    key: &Key,
+    index: usize,
+    ui: &mut Ui,
+) -> Message {
+    let current = key.perceived_type();
+
+    let picked = egui::ComboBox::from_label("Perceived type")
+        .selected_text(current.as_ref())
+        .show_ui(ui, |ui| {
+            types.iter().fold(None, |acc, perceived| {
+                let label = format!("{} ({})", perceived.name, perceived.extensions.len());
+
+                if ui
+                    .selectable_label(perceived.name.eq_ignore_ascii_case(&current), label)
+                    .clicked()
+                {
+                    Some(perceived.name.clone())
+                } else {
+                    acc
+                }
+            })
+        })
+        .inner
+        .flatten();
+
+    let extensions = types
+        .iter()
+        .find(|perceived| perceived.name.eq_ignore_ascii_case(&current))
+        .map_or(Vec::new(), |perceived| perceived.extensions.clone());
+
+    if extensions.is_empty() {
+        ui.label(RichText::new("No extension has this perceived type").weak());
+    } else {
+        ui.label(RichText::new(format!("Shown for {}", extensions.join(", "))).weak());
+    }
+
+    match picked {
+        Some(name) if !name.eq_ignore_ascii_case(&current) => {
+            Message::ReloadKey((key.clone().with_perceived_type(name), index))
+        }
+        _ => Message::None,
+    }
+}
+
+// One tab for each key the file type takes verbs from
+fn render_associations(associations: &[Association], open: &Key, ui: &mut Ui) -> Message {
+    associations
+        .iter()
+        .enumerate()
+        .fold(Message::None, |acc, (i, association)| {
+            let is_open = Rc::ptr_eq(&association.key.path, &open.path);
+            let clicked = ui
+                .add(button_heading(association.source.to_string(), is_open))
+                .on_hover_text(key_path_string(&association.key.path))
+                .clicked();
+
+            if clicked && !is_open {
+                Message::OpenAssociation(i)
+            } else {
+                acc
+            }
+        })
+}
+
+// The order the list was dragged into, with what Explorer will make of it
+fn render_order(
+    items: &[(StdCommand, ColorImage)],
+    prefixes: bool,
+    default_verbs: &[String],
+    ui: &mut Ui,
+) -> Message {
+    let order = items
+        .iter()
+        .map(|(item, _)| item.clone())
+        .collect::<Vec<StdCommand>>();
+    let plan = plan_order(&order, prefixes, default_verbs);
+
+    ui.add_space(10f32);
+
+    plan.warnings.iter().for_each(|warning| {
+        ui.label(RichText::new(format!("⚠ {}", warning)).color(Color32::YELLOW));
+    });
+
+    ui.horizontal(|ui| {
+        let mut prefixes = prefixes;
+        let prefixes_changed = ui
+            .checkbox(&mut prefixes, "Number key names when needed")
+            .changed();
+
+        if prefixes_changed {
+            Message::SetPrefixOrder(prefixes)
+        } else if ui
+            .add(button_action(format!(
+                "Stage order ({} changes)",
+                plan.changes.len()
+            )))
+            .clicked()
+        {
+            Message::StageOrder
+        } else if ui.add(button_secondary("Reset order")).clicked() {
+            Message::ResetOrder
+        } else {
+            Message::None
+        }
+    })
+    .inner
+}
+
+fn render_selection(selected: &[StdCommand], ui: &mut Ui) -> Message {
+    if selected.is_empty() {
+        return Message::None;
+    }
+
+    ui.horizontal(|ui| {
+        if ui
+            .add(button_standard(format!(
+                "Export selected ({})",
+                selected.len()
+            )))
+            .clicked()
+        {
+            Message::UpdateModal(AppModal::Export(ExportTarget::default()))
+        } else if ui.add(button_standard("Group into submenu")).clicked() {
+            Message::UpdateModal(AppModal::Group(String::new()))
+        } else if ui.add(button_standard("Clear selection")).clicked() {
+            Message::ClearSelection
+        } else {
+            Message::None
+        }
+    })
+    .inner
+}
+
+// Known locations say when Explorer shows them
+fn add_location_tab(ui: &mut Ui, key: &Key, selected: bool) -> egui::Response {
+    let tab = ui.add(button_heading(&key.name, selected));
+
+    match location_of(key.hive, &key_path_string(&key.path)) {
+        Some(location) => tab.on_hover_text(location.shown),
+        None => tab,
+    }
 }
 
 pub fn render_heading(keys: &Keys, path: &Option<(Key, usize)>, ui: &mut Ui) -> Message {
@@ -92,11 +437,11 @@ pub fn render_heading(keys: &Keys, path: &Option<(Key, usize)>, ui: &mut Ui) ->
         .enumerate()
         .fold(Message::None, |acc, (i, key)| match path {
             Some((_, ind)) if *ind == i => {
-                ui.add(button_heading(&key.name, true));
+                add_location_tab(ui, key, true);
                 acc
             }
             _ => {
-                if ui.add(button_heading(&key.name, false)).clicked() {
+                if add_location_tab(ui, key, false).clicked() {
                     Message::SetPath(Some((key.deep_clone(), i)))
                 } else {
                     acc
@@ -104,3 +449,131 @@ pub fn render_heading(keys: &Keys, path: &Option<(Key, usize)>, ui: &mut Ui) ->
             }
         })
 }
+
+pub fn render_extension_tabs(keys: &Keys, open: Option<usize>, ui: &mut Ui) -> Message {
+    keys.iter()
+        .enumerate()
+        .fold(Message::None, |acc, (i, key)| {
+            let clicked = ui
+                .add(button_heading(&key.name, open == Some(i)))
+                .on_hover_text("Shell extensions")
+                .clicked();
+
+            if clicked && open != Some(i) {
+                Message::OpenExtensions(i)
+            } else {
+                acc
+            }
+        })
+}
+
+/// Lists the handlers of the open shell extension tab, each with a toggle
+pub fn render_extensions(extensions: &[ShellExtension], ui: &mut Ui) -> Message {
+    if extensions.is_empty() {
+        ui.label("No shell extensions are registered here.");
+        return Message::None;
+    }
+
+    extensions.iter().fold(Message::None, |acc, extension| {
+        ui.horizontal(|ui| {
+            let mut enabled = extension.is_enabled();
+
+            let toggled = ui
+                .add_enabled(
+                    extension.handler.is_some(),
+                    egui::Checkbox::new(&mut enabled, &extension.name),
+                )
+                .on_hover_text("Disabling blocks the handler's CLSID for every location")
+                .changed();
+
+            match &extension.handler {
+                Some(handler) => {
+                    ui.label(RichText::new(handler.to_string()).weak())
+                        .on_hover_text(&handler.clsid);
+
+                    if !handler.is_registered() {
+                        ui.label(RichText::new("⚠").color(Color32::YELLOW))
+                            .on_hover_text("The CLSID is not registered");
+                    }
+                }
+                None => {
+                    ui.label(RichText::new("⚠").color(Color32::YELLOW))
+                        .on_hover_text("No CLSID, the handler can't be disabled");
+                }
+            }
+
+            if toggled {
+                Message::ToggleExtension(extension.clone())
+            } else {
+                acc
+            }
+        })
+        .inner
+    })
+}
+
+pub fn render_pending(pending: &[PendingChange], ui: &mut Ui) -> Message {
+    if pending.is_empty() {
+        return Message::None;
+    }
+
+    let response = ui
+        .add(button_action(format!("Review changes ({})", pending.len())))
+        .on_hover_text(
+            pending
+                .iter()
+                .map(PendingChange::to_string)
+                .collect::<Vec<String>>()
+                .join("\n"),
+        );
+
+    if response.clicked() {
+        Message::ReviewChanges
+    } else {
+        Message::None
+    }
+}
+
+/// Picks which classes are shown and edited, None until the choice changes
+pub fn render_scope(current: Scope, ui: &mut Ui) -> Option<Scope> {
+    let mut selected = current;
+
+    egui::ComboBox::from_id_salt("Scope")
+        .selected_text(current.to_string())
+        .show_ui(ui, |ui| {
+            Scope::ALL.iter().for_each(|scope| {
+                ui.selectable_value(&mut selected, *scope, scope.to_string())
+                    .on_hover_text(format!("{}", scope.hive()));
+            });
+        });
+
+    (selected != current).then_some(selected)
+}
+
+pub fn render_history(history: &History, state: &AppState, ui: &mut Ui) -> Message {
+    let next_undo = state
+        .pending
+        .last()
+        .map(PendingChange::to_string)
+        .or_else(|| history.next_undo().map(str::to_string));
+    let next_redo = state
+        .unstaged
+        .last()
+        .map(PendingChange::to_string)
+        .or_else(|| history.next_redo().map(str::to_string));
+
+    let undo = ui
+        .add_enabled(next_undo.is_some(), button_standard("Undo"))
+        .on_hover_text(format!("Undo {} (Ctrl+Z)", next_undo.unwrap_or_default()));
+    let redo = ui
+        .add_enabled(next_redo.is_some(), button_standard("Redo"))
+        .on_hover_text(format!("Redo {} (Ctrl+Y)", next_redo.unwrap_or_default()));
+
+    if undo.clicked() {
+        Message::Undo
+    } else if redo.clicked() {
+        Message::Redo
+    } else {
+        Message::None
+    }
+}
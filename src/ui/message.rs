@@ -1,8 +1,15 @@
-//use std::path::Path;
+use std::{path::PathBuf, rc::Rc};
 
 use crate::{
-    edit_context_lib::types::{Key, StdCommand},
-    ui::appmodal::AppModal,
+    edit_context_lib::{
+        export::ExportTarget,
+        import::ImportVerb,
+        scope::Scope,
+        shellex::ShellExtension,
+        types::{Key, StdCommand},
+    },
+    registry_io::reader::{RegReader, RegVerb},
+    ui::appmodal::{AppModal, Simulator},
 };
 
 #[derive(Debug)]
@@ -10,9 +17,37 @@ pub enum Message {
     UpdateModal(AppModal),
     SetPath(Option<(Key, usize)>),
     ReloadKey((Key, usize)),
+    OpenAssociation(usize),
+    PinLocation(String, bool),
+    Simulate(Simulator),
     AddCommand(StdCommand),
     UpdateCommand(StdCommand),
     RemoveCommand(StdCommand),
-    //LoadBackup(Box<Path>),
+    ToggleDisabled(StdCommand),
+    MoveItem(usize, usize),
+    SetPrefixOrder(bool),
+    StageOrder,
+    ResetOrder,
+    ReviewChanges,
+    ApplyChanges,
+    DiscardChanges,
+    ShowBackups,
+    OpenBackup(PathBuf),
+    RestoreAll(Rc<RegReader>),
+    RestoreVerbs(Rc<RegReader>, Vec<RegVerb>),
+    CompareWithLive(Rc<RegReader>),
+    CompareWithFile(Rc<RegReader>, PathBuf),
+    ToggleSelected(StdCommand),
+    ClearSelection,
+    ExportSelected(ExportTarget, PathBuf),
+    OpenImport(PathBuf),
+    ApplyImport(Rc<RegReader>, Vec<ImportVerb>),
+    SetScope(Scope),
+    MoveVerb(StdCommand, Scope),
+    GroupSelected(String),
+    OpenExtensions(usize),
+    ToggleExtension(ShellExtension),
+    Undo,
+    Redo,
     None,
 }
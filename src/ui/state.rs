@@ -1,7 +1,13 @@
 use egui::ColorImage;
 
 use crate::{
-    edit_context_lib::types::{Key, StdCommand},
+    edit_context_lib::{
+        association::Association,
+        perceived::PerceivedType,
+        shellex::ShellExtension,
+        staging::PendingChange,
+        types::{Key, StdCommand},
+    },
     ui::appmodal::AppModal,
 };
 
@@ -9,8 +15,25 @@ use crate::{
 pub struct AppState {
     pub modal: AppModal,
     pub path: Option<(Key, usize)>,
+    // The keys the open file type takes its verbs from, the first is the extension itself
+    pub associations: Vec<Association>,
+    // Read the first time a perceived type location is opened
+    pub perceived_types: Vec<PerceivedType>,
+    // The open shell extension tab, shown instead of a path
+    pub extensions: Option<(usize, Vec<ShellExtension>)>,
     pub alerts: Vec<Box<str>>,
     pub items: Box<[Vec<(StdCommand, ColorImage)>]>,
+    pub pending: Vec<PendingChange>,
+    // Pending changes taken back with undo, in the order redo puts them back
+    pub unstaged: Vec<PendingChange>,
+    // Set once the open list has been dragged into an order that isn't staged yet
+    pub reordered: bool,
+    // Numbers key names when Position can't give the order
+    pub prefix_order: bool,
+    // The verbs the open location's shell key names as its default, read when reordering starts
+    pub default_verbs: Vec<String>,
+    // Ticked in the menu view for exporting
+    pub selected: Vec<StdCommand>,
     // TODO: Implement LRU Cache for dynamically changing paths (extension searches)
     //pub cache: HashMap<KeyPath, Vec<(StdCommand, ColorImage)>>,
 }